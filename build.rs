@@ -0,0 +1,50 @@
+//! Bakes the git commit and the locked versions of the dependencies whose IO
+//! gets profiled into the binary, see `src/metadata.rs`.
+
+use std::fs;
+use std::process::Command;
+
+fn main() {
+    let sha = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    // results from a binary built with uncommitted changes can't be traced
+    // back to the commit alone
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .is_some_and(|output| !output.stdout.is_empty());
+    let sha = if dirty { format!("{sha}-dirty") } else { sha };
+    println!("cargo:rustc-env=PPROF_DEV_GIT_SHA={sha}");
+
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    for (name, var) in [
+        ("lance", "PPROF_DEV_LANCE_VERSION"),
+        ("lance-index", "PPROF_DEV_LANCE_INDEX_VERSION"),
+        ("object_store", "PPROF_DEV_OBJECT_STORE_VERSION"),
+    ] {
+        let version = locked_version(&lock, name).unwrap_or("unknown");
+        println!("cargo:rustc-env={var}={version}");
+    }
+
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+}
+
+/// the version of the first `name` package in the lock file
+fn locked_version<'a>(lock: &'a str, name: &str) -> Option<&'a str> {
+    let name_line = format!("name = \"{name}\"");
+    let mut lines = lock.lines();
+    lines.find(|line| *line == name_line)?;
+    lines
+        .next()?
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+}
@@ -0,0 +1,189 @@
+//! `--admin-addr`: a `/stats` endpoint with the run's counters so far, for
+//! checking on a long soak test with curl while it runs:
+//!
+//! ```sh
+//! curl -s localhost:9100/stats | jq .ops.get
+//! ```
+//!
+//! The counters are kept for the whole run rather than per phase, and count
+//! requests as they complete, except for `in_flight`. `/profile/{name}/{op}`
+//! has the profile of `op` the wrapper registered as `name` recorded so far
+//! in the current phase.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use object_store::path::Path;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::http;
+use crate::op::ObjectStoreOp;
+use crate::registry::registry;
+use crate::run::Phase;
+
+#[derive(Default)]
+struct OpCounters {
+    count: AtomicU64,
+    bytes: AtomicU64,
+    in_flight: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// Hooks counting every request of the run, for `/stats`.
+pub struct LiveCounters {
+    run_id: String,
+    started: Instant,
+    phase: Mutex<Option<Phase>>,
+    ops: [OpCounters; ObjectStoreOp::ALL.len()],
+}
+
+#[derive(Serialize)]
+struct LiveStats<'a> {
+    run_id: &'a str,
+    /// the phase running right now, if any
+    phase: Option<Phase>,
+    uptime_secs: f64,
+    ops: BTreeMap<ObjectStoreOp, LiveOp>,
+    /// whether each op is profiled right now, see `--toggle-ops`
+    profiling: BTreeMap<ObjectStoreOp, bool>,
+}
+
+#[derive(Serialize)]
+struct LiveOp {
+    count: u64,
+    bytes: u64,
+    in_flight: u64,
+    errors: u64,
+}
+
+impl LiveCounters {
+    pub fn new(run_id: &str) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            started: Instant::now(),
+            phase: Mutex::new(None),
+            ops: Default::default(),
+        }
+    }
+
+    pub fn set_phase(&self, phase: Option<Phase>) {
+        *self.phase.lock() = phase;
+    }
+
+    fn render(&self) -> Vec<u8> {
+        let ops = ObjectStoreOp::ALL
+            .into_iter()
+            .map(|op| {
+                let counters = &self.ops[op as usize];
+                let live = LiveOp {
+                    count: counters.count.load(Ordering::Relaxed),
+                    bytes: counters.bytes.load(Ordering::Relaxed),
+                    in_flight: counters.in_flight.load(Ordering::Relaxed),
+                    errors: counters.errors.load(Ordering::Relaxed),
+                };
+                (op, live)
+            })
+            .collect();
+        let stats = LiveStats {
+            run_id: &self.run_id,
+            phase: *self.phase.lock(),
+            uptime_secs: self.started.elapsed().as_secs_f64(),
+            ops,
+            profiling: ObjectStoreOp::PROFILED
+                .into_iter()
+                .map(|op| (op, registry().toggles().is_enabled(op)))
+                .collect(),
+        };
+        serde_json::to_vec_pretty(&stats).unwrap()
+    }
+}
+
+impl StoreHooks for LiveCounters {
+    fn on_request(&self, op: ObjectStoreOp, _path: &Path, _range: Option<Range<usize>>) {
+        self.ops[op as usize]
+            .in_flight
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        _path: &Path,
+        _range: Option<Range<usize>>,
+        bytes: usize,
+        _duration: Duration,
+        outcome: Outcome<'_>,
+        _labels: &Labels,
+    ) {
+        let counters = &self.ops[op as usize];
+        counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+        counters.count.fetch_add(1, Ordering::Relaxed);
+        counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Outcome::Error(_) = outcome {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// serves `/stats` at `addr` in the background for as long as the process runs
+pub async fn spawn(addr: &str, counters: Arc<LiveCounters>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    log::info!("serving stats at http://{}/stats", listener.local_addr()?);
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::warn!("admin endpoint stopped accepting connections: {e}");
+                    return;
+                }
+            };
+            let counters = counters.clone();
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &counters).await {
+                    log::warn!("couldn't answer an admin request: {e}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn respond(mut stream: TcpStream, counters: &LiveCounters) -> io::Result<()> {
+    let path = http::request_path(&mut stream).await?;
+    if path == "/stats" {
+        http::respond(stream, "200 OK", "application/json", &counters.render()).await
+    } else if let Some(profile) = path.strip_prefix("/profile/").and_then(live_profile) {
+        http::respond(stream, "200 OK", "application/octet-stream", &profile).await
+    } else {
+        http::respond(
+            stream,
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            b"not found",
+        )
+        .await
+    }
+}
+
+/// the encoded profile `name/op` asks for, if that wrapper profiles that op
+#[cfg(feature = "profiling")]
+fn live_profile(name_op: &str) -> Option<Vec<u8>> {
+    use pprof::protos::Message;
+
+    let (name, op) = name_op.split_once('/')?;
+    let profile = registry().report(name, op.parse().ok()?)?;
+    profile.write_to_bytes().ok()
+}
+
+#[cfg(not(feature = "profiling"))]
+fn live_profile(_name_op: &str) -> Option<Vec<u8>> {
+    None
+}
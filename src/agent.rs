@@ -0,0 +1,246 @@
+//! `--agent-addr` and `pprof-dev collect`: profiling a job spread over several
+//! processes or machines, e.g. the workers of one ingestion job, end to end.
+//! Every worker run with `--agent-addr` sends the profiles it wrote to the
+//! collector after each phase. The collector keeps the latest of each
+//! worker's profiles and merges them into one profile of the whole job per
+//! file:
+//!
+//! ```sh
+//! cargo run -- collect --addr 0.0.0.0:9200
+//! cargo run -- --agent-addr collector:9200 --uri s3://bucket/worker-1.lance
+//! ```
+//!
+//! Each profile is sent over a plain tcp connection as a line of json, its
+//! [`Snapshot`], followed by the profile's bytes.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::cli::{Args, CollectArgs};
+use crate::merge;
+use crate::report;
+use crate::run::RunDir;
+
+/// the largest profile the collector accepts, far more than any phase's
+/// profile of one op needs
+const MAX_PROFILE_BYTES: u64 = 64 << 20;
+
+/// What comes before each profile sent to the collector.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    /// who sent it, see `--agent-worker`
+    worker: String,
+    /// the profile's path relative to the worker's run directory, e.g.
+    /// `query_dataset_get.pb`
+    profile: String,
+    /// the length of the profile following the line
+    bytes: u64,
+}
+
+/// A worker's end, sending the profiles of its run written since the last
+/// phase.
+pub struct Agent {
+    addr: String,
+    worker: String,
+    run_dir: PathBuf,
+    /// profiles written before this have been sent already
+    sent_until: SystemTime,
+    stream: Option<TcpStream>,
+}
+
+impl Agent {
+    pub fn new(addr: &str, args: &Args, run_dir: &RunDir) -> Self {
+        Self {
+            addr: addr.to_string(),
+            worker: args.agent_worker.clone().unwrap_or_else(default_worker),
+            run_dir: run_dir.path().to_path_buf(),
+            sent_until: SystemTime::now(),
+            stream: None,
+        }
+    }
+
+    /// sends every profile of the run written since the last call, returns
+    /// how many. on failure they're sent again with the next call's
+    pub async fn send_profiles(&mut self) -> io::Result<usize> {
+        let until = SystemTime::now();
+        let mut profiles = vec![];
+        for path in report::find_profiles(&self.run_dir)? {
+            if fs::metadata(&path)?.modified()? >= self.sent_until {
+                profiles.push(path);
+            }
+        }
+        match self.send(&profiles).await {
+            Ok(()) => {
+                self.sent_until = until;
+                Ok(profiles.len())
+            }
+            Err(e) => {
+                // reconnect next time
+                self.stream = None;
+                Err(e)
+            }
+        }
+    }
+
+    async fn send(&mut self, profiles: &[PathBuf]) -> io::Result<()> {
+        if profiles.is_empty() {
+            return Ok(());
+        }
+        if self.stream.is_none() {
+            self.stream = Some(TcpStream::connect(&self.addr).await?);
+        }
+        let stream = self.stream.as_mut().unwrap();
+        for path in profiles {
+            let profile = fs::read(path)?;
+            let relative = path.strip_prefix(&self.run_dir).unwrap_or(path);
+            let snapshot = Snapshot {
+                worker: self.worker.clone(),
+                profile: relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                bytes: profile.len() as u64,
+            };
+            let mut header = serde_json::to_vec(&snapshot)?;
+            header.push(b'\n');
+            stream.write_all(&header).await?;
+            stream.write_all(&profile).await?;
+        }
+        stream.flush().await
+    }
+}
+
+/// the hostname and pid, unique to each worker of a job
+fn default_worker() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string());
+    format!("{host}-{}", std::process::id())
+}
+
+/// The collector's end, keeping each worker's profiles under
+/// `workers/{worker}/` and their merge at the top of the output directory.
+struct Collector {
+    output_dir: PathBuf,
+    /// held while a profile is stored and merged, so a merge doesn't read
+    /// another worker's profile halfway through being written
+    lock: Mutex<()>,
+}
+
+impl Collector {
+    fn store(&self, snapshot: &Snapshot, profile: &[u8]) -> io::Result<()> {
+        let (Some(worker), Some(name)) = (relative(&snapshot.worker), relative(&snapshot.profile))
+        else {
+            return Err(io::Error::other(format!(
+                "not writing {:?} of {:?}, it's not a relative path",
+                snapshot.profile, snapshot.worker
+            )));
+        };
+        let _guard = self.lock.lock();
+        let workers = self.output_dir.join("workers");
+        let path = workers.join(worker).join(&name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, profile)?;
+
+        let mut paths = vec![];
+        for entry in fs::read_dir(&workers)? {
+            let path = entry?.path().join(&name);
+            if path.exists() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        let merged = self.output_dir.join(&name);
+        fs::create_dir_all(merged.parent().unwrap())?;
+        // the workers' own profiles are kept either way
+        match merge::merge_files(&paths, &merged) {
+            Ok(()) => log::info!(
+                "merged {} of {} workers, with {}'s latest",
+                name.display(),
+                paths.len(),
+                snapshot.worker
+            ),
+            Err(e) => log::warn!("not merging {}: {e}", name.display()),
+        }
+        Ok(())
+    }
+}
+
+/// `path` if it's relative and stays inside the directory it's joined to
+fn relative(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    let inside = path.components().all(|c| matches!(c, Component::Normal(_)));
+    (inside && !path.as_os_str().is_empty()).then(|| path.to_path_buf())
+}
+
+/// receives and merges the profiles of every worker that connects, until
+/// it's stopped
+pub async fn collect(args: &CollectArgs) -> io::Result<()> {
+    fs::create_dir_all(&args.output_dir)?;
+    let listener = TcpListener::bind(&args.addr).await?;
+    log::info!(
+        "collecting profiles at {} into {}",
+        listener.local_addr()?,
+        args.output_dir.display()
+    );
+    let collector = Arc::new(Collector {
+        output_dir: args.output_dir.clone(),
+        lock: Mutex::new(()),
+    });
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let collector = collector.clone();
+        tokio::spawn(async move {
+            if let Err(e) = receive(stream, collector).await {
+                log::warn!("stopped receiving profiles from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn receive(stream: TcpStream, collector: Arc<Collector>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let snapshot: Snapshot = serde_json::from_str(&line)?;
+        if snapshot.bytes > MAX_PROFILE_BYTES {
+            return Err(io::Error::other(format!(
+                "{} of {} is {} bytes, more than the {MAX_PROFILE_BYTES} accepted",
+                snapshot.profile, snapshot.worker, snapshot.bytes
+            )));
+        }
+        let mut profile = vec![];
+        (&mut reader)
+            .take(snapshot.bytes)
+            .read_to_end(&mut profile)
+            .await?;
+        if profile.len() as u64 != snapshot.bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{} of {} ended after {} of its {} bytes",
+                    snapshot.profile,
+                    snapshot.worker,
+                    profile.len(),
+                    snapshot.bytes
+                ),
+            ));
+        }
+        // writing and merging is blocking file IO
+        let collector = collector.clone();
+        tokio::task::spawn_blocking(move || collector.store(&snapshot, &profile))
+            .await
+            .map_err(io::Error::other)??;
+    }
+}
@@ -0,0 +1,49 @@
+//! Where the dataset lives, for backends that need more than a uri.
+
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// credentials a fresh minio server starts with
+const MINIO_USER: &str = "minioadmin";
+const MINIO_PASSWORD: &str = "minioadmin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// whatever store `--uri` names, configured by `--storage-option`s
+    Uri,
+    /// an s3 `--uri` served by the minio server at `--minio-endpoint`
+    Minio,
+}
+
+// for the PPROF_DEV_BACKEND override
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+impl Backend {
+    /// storage options the backend needs, which `--storage-option`s override
+    pub fn storage_options(&self, minio_endpoint: &str) -> Vec<(String, String)> {
+        match self {
+            Backend::Uri => vec![],
+            Backend::Minio => [
+                ("aws_endpoint", minio_endpoint),
+                ("aws_access_key_id", MINIO_USER),
+                ("aws_secret_access_key", MINIO_PASSWORD),
+                ("aws_region", "us-east-1"),
+                ("allow_http", "true"),
+                // minio buckets aren't virtual hosts
+                ("aws_virtual_hosted_style_request", "false"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        }
+    }
+}
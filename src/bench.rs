@@ -0,0 +1,92 @@
+//! `pprof-dev bench`: the write, index and query phases repeated
+//! `--repetitions` times, each in a run directory of its own with its
+//! profiles, and the rows ingested, vectors indexed and queries answered per
+//! second across them.
+
+use crate::cli::BenchArgs;
+use crate::run::Phase;
+use crate::summary::{per_sec, PhaseTiming};
+use crate::sweep::{Runner, Step};
+use crate::table::{Cell, Table};
+
+const METRICS: [&str; 3] = ["rows_per_sec", "vectors_per_sec", "queries_per_sec"];
+
+pub async fn run_bench(bench: BenchArgs) {
+    assert!(bench.repetitions > 0, "--repetitions must be at least 1");
+    let mut runner = Runner::new("bench", &bench.run);
+
+    let mut repetitions = Vec::new();
+    for repetition in 0..bench.repetitions {
+        log::info!(
+            "bench: repetition {} of {}",
+            repetition + 1,
+            bench.repetitions
+        );
+        let steps = [Step::Write, Step::Index, Step::Warmup, Step::Query];
+        let m = runner
+            .run(&repetition.to_string(), |_| {}, &[], &steps)
+            .await;
+
+        let rates = [
+            m.timings
+                .get(&Phase::Write)
+                .map_or(0.0, PhaseTiming::rows_per_sec),
+            per_sec(m.args.rows as u64, m.wall_us(Phase::Index)),
+            per_sec(m.queries as u64, m.wall_us(Phase::Query)),
+        ];
+        log::info!(
+            "bench: {:.0} rows/s, {:.0} vectors/s, {:.1} queries/s",
+            rates[0],
+            rates[1],
+            rates[2]
+        );
+        repetitions.push(rates);
+    }
+    runner.write_table(&table(&repetitions));
+}
+
+/// the mean of `values`, their sample standard deviation, min and max
+fn spread(values: &[f64]) -> (f64, f64, f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let stddev = match values.len() {
+        0 | 1 => 0.0,
+        _ => (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt(),
+    };
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    (mean, stddev, min, max)
+}
+
+/// each metric's mean over the repetitions, how much it varied and its range
+fn table(repetitions: &[[f64; 3]]) -> Table {
+    let mut table = Table::new(&[
+        "metric",
+        "repetitions",
+        "mean",
+        "stddev",
+        "cv_pct",
+        "min",
+        "max",
+    ]);
+    for (i, metric) in METRICS.iter().enumerate() {
+        let values = repetitions.iter().map(|r| r[i]).collect::<Vec<_>>();
+        let (mean, stddev, min, max) = spread(&values);
+        // the coefficient of variation
+        let cv = if mean > 0.0 {
+            stddev / mean * 100.0
+        } else {
+            0.0
+        };
+        table.row(vec![
+            (*metric).into(),
+            values.len().into(),
+            Cell::Float(mean, 2),
+            Cell::Float(stddev, 2),
+            Cell::Float(cv, 2),
+            Cell::Float(min, 2),
+            Cell::Float(max, 2),
+        ]);
+    }
+    table
+}
@@ -0,0 +1,185 @@
+//! The gets of each phase that read an index split into those of the index's
+//! files (its partitions, pq codes and auxiliary files) and those of the data
+//! files (the takes and the refine of a search), in `{phase}_index_data.txt`,
+//! to tell whether a query phase was index or data bound.
+//!
+//! Time is the gets' latencies summed, so concurrent gets count once each
+//! rather than by how long the phase waited on them.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::time::Duration;
+
+use object_store::path::Path;
+use parking_lot::Mutex;
+
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+
+/// What part of the dataset a file is, going by its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FileKind {
+    /// an index's `index.idx`: ivf centroids and partitions, or a scalar
+    /// index's root
+    Index,
+    /// an index's `auxiliary.idx`, where the pq codes of each partition are
+    IndexAuxiliary,
+    /// any other file of an index, e.g. the pages of a btree
+    IndexOther,
+    /// the data files, read by takes and the refine
+    Data,
+    /// manifests, deletion files and transactions
+    Other,
+}
+
+impl FileKind {
+    fn of(path: &Path) -> Self {
+        let parts: Vec<_> = path.parts().collect();
+        let top = parts
+            .iter()
+            .rposition(|part| matches!(part.as_ref(), "_indices" | "data"));
+        match top.map(|i| parts[i].as_ref()) {
+            Some("_indices") => match parts.last().map(|part| part.as_ref()) {
+                Some("index.idx") => FileKind::Index,
+                Some("auxiliary.idx") => FileKind::IndexAuxiliary,
+                _ => FileKind::IndexOther,
+            },
+            Some("data") => FileKind::Data,
+            _ => FileKind::Other,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            FileKind::Index => "index.idx",
+            FileKind::IndexAuxiliary => "auxiliary.idx",
+            FileKind::IndexOther => "index_other",
+            FileKind::Data => "data",
+            FileKind::Other => "other",
+        }
+    }
+
+    fn is_index(&self) -> bool {
+        matches!(
+            self,
+            FileKind::Index | FileKind::IndexAuxiliary | FileKind::IndexOther
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct KindReads {
+    gets: u64,
+    bytes: u64,
+    time: Duration,
+}
+
+impl KindReads {
+    fn add(&mut self, other: &KindReads) {
+        self.gets += other.gets;
+        self.bytes += other.bytes;
+        self.time += other.time;
+    }
+}
+
+/// Hooks adding up the gets of each kind of file.
+#[derive(Default)]
+pub struct FileBreakdown {
+    kinds: Mutex<BTreeMap<FileKind, KindReads>>,
+}
+
+impl FileBreakdown {
+    /// the gets since the last report, `None` if none of them were of an
+    /// index
+    pub fn take_report(&self) -> Option<BreakdownReport> {
+        let kinds = std::mem::take(&mut *self.kinds.lock());
+        kinds
+            .keys()
+            .any(FileKind::is_index)
+            .then_some(BreakdownReport { kinds })
+    }
+}
+
+impl StoreHooks for FileBreakdown {
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        _range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        _outcome: Outcome<'_>,
+        _labels: &Labels,
+    ) {
+        if op != ObjectStoreOp::Get {
+            return;
+        }
+        let mut kinds = self.kinds.lock();
+        let reads = kinds.entry(FileKind::of(path)).or_default();
+        reads.gets += 1;
+        reads.bytes += bytes as u64;
+        reads.time += duration;
+    }
+}
+
+/// The gets of a phase by kind of file, then the index's and the data's.
+pub struct BreakdownReport {
+    kinds: BTreeMap<FileKind, KindReads>,
+}
+
+impl BreakdownReport {
+    fn total(&self, filter: impl Fn(&FileKind) -> bool) -> KindReads {
+        let mut total = KindReads::default();
+        for (kind, reads) in &self.kinds {
+            if filter(kind) {
+                total.add(reads);
+            }
+        }
+        total
+    }
+}
+
+impl Display for BreakdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = self.total(|_| true);
+        let time_share = |reads: &KindReads| {
+            100.0 * reads.time.as_secs_f64() / all.time.as_secs_f64().max(f64::EPSILON)
+        };
+        let line = |f: &mut fmt::Formatter<'_>, name: &str, reads: &KindReads| {
+            let bytes_share = 100.0 * reads.bytes as f64 / all.bytes.max(1) as f64;
+            let time_share = time_share(reads);
+            writeln!(
+                f,
+                "{name:<16} {:>8} {:>14} {:>10.1} {bytes_share:>6.1}% {time_share:>6.1}%",
+                reads.gets,
+                reads.bytes,
+                reads.time.as_secs_f64() * 1000.0
+            )
+        };
+        writeln!(
+            f,
+            "{:<16} {:>8} {:>14} {:>10} {:>7} {:>7}",
+            "file", "gets", "bytes", "time_ms", "bytes", "time"
+        )?;
+        for (kind, reads) in &self.kinds {
+            line(f, kind.as_str(), reads)?;
+        }
+        writeln!(f)?;
+        let index = self.total(FileKind::is_index);
+        let data = self.total(|kind| *kind == FileKind::Data);
+        line(f, "index", &index)?;
+        line(f, "data", &data)?;
+        let bound = if index.time >= data.time {
+            "index"
+        } else {
+            "data"
+        };
+        writeln!(
+            f,
+            "\n{bound} bound: {:.0}% of the time getting was the index's, {:.0}% the data's",
+            time_share(&index),
+            time_share(&data)
+        )
+    }
+}
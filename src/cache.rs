@@ -0,0 +1,242 @@
+//! An optional cache of `head` results in front of the store, enabled with
+//! `--head-cache-ttl-ms`, to see how many of lance's heads a metadata cache
+//! would absorb and which call stacks they come from. Cache hits never reach
+//! the store, so they don't show up as requests anywhere else.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOpts,
+    PutOptions, PutPayload, PutResult, Result,
+};
+use parking_lot::Mutex;
+
+use crate::stacks;
+
+#[derive(Default)]
+struct StackStats {
+    hits: u64,
+    misses: u64,
+    /// misses where the entry was there, but older than the ttl
+    expired: u64,
+}
+
+/// The cached metadata, and how lookups into it went per call stack.
+pub struct HeadCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<Path, (Instant, ObjectMeta)>>,
+    stacks: Mutex<HashMap<Vec<usize>, StackStats>>,
+}
+
+impl HeadCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            stacks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lookup(&self, path: &Path) -> Option<ObjectMeta> {
+        let (meta, expired) = match self.entries.lock().get(path) {
+            Some((cached, meta)) if cached.elapsed() < self.ttl => (Some(meta.clone()), false),
+            Some(_) => (None, true),
+            None => (None, false),
+        };
+
+        let stack = stacks::capture();
+        let mut stacks = self.stacks.lock();
+        let stats = stacks.entry(stack).or_default();
+        match meta {
+            Some(_) => stats.hits += 1,
+            None => stats.misses += 1,
+        }
+        if expired {
+            stats.expired += 1;
+        }
+        meta
+    }
+
+    fn insert(&self, path: &Path, meta: &ObjectMeta) {
+        self.entries
+            .lock()
+            .insert(path.clone(), (Instant::now(), meta.clone()));
+    }
+
+    fn invalidate(&self, path: &Path) {
+        self.entries.lock().remove(path);
+    }
+
+    /// the hit rate of each call stack since the last call, busiest first.
+    /// the cached entries themselves are kept
+    pub fn take_report(&self) -> HeadCacheReport {
+        let stacks = std::mem::take(&mut *self.stacks.lock());
+        let mut stacks = stacks
+            .into_iter()
+            .map(|(stack, stats)| (stacks::report(&stack, &["pprof_dev::cache::"]), stats))
+            .collect::<Vec<_>>();
+        stacks.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.hits + stats.misses));
+        HeadCacheReport { stacks }
+    }
+}
+
+impl fmt::Debug for HeadCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeadCache")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct HeadCacheReport {
+    stacks: Vec<(Vec<String>, StackStats)>,
+}
+
+impl Display for HeadCacheReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hits, lookups) = self.stacks.iter().fold((0, 0), |(hits, lookups), (_, s)| {
+            (hits + s.hits, lookups + s.hits + s.misses)
+        });
+        writeln!(
+            f,
+            "{hits} hits in {lookups} lookups ({})",
+            hit_rate(hits, lookups)
+        )?;
+        for (frames, s) in &self.stacks {
+            writeln!(
+                f,
+                "\n{} lookups, {} hits, {} misses ({} expired), {}",
+                s.hits + s.misses,
+                s.hits,
+                s.misses,
+                s.expired,
+                hit_rate(s.hits, s.hits + s.misses)
+            )?;
+            for frame in frames {
+                writeln!(f, "    {frame}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hit_rate(hits: u64, lookups: u64) -> String {
+    match lookups {
+        0 => "no lookups".to_string(),
+        _ => format!("{:.1}% hit rate", hits as f64 * 100.0 / lookups as f64),
+    }
+}
+
+/// Object store answering `head` from `cache` when it can. anything that
+/// writes to a path drops it from the cache
+#[derive(Debug)]
+pub struct CachedObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    cache: Arc<HeadCache>,
+}
+
+impl CachedObjectStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, cache: Arc<HeadCache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+impl Display for CachedObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CachedObjectStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for CachedObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.cache.invalidate(location);
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.cache.invalidate(location);
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.inner.get_range(location, range).await
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.inner.get_ranges(location, ranges).await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        if let Some(meta) = self.cache.lookup(location) {
+            return Ok(meta);
+        }
+        let meta = self.inner.head(location).await?;
+        self.cache.insert(location, &meta);
+        Ok(meta)
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.cache.invalidate(location);
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list_with_offset(prefix, offset)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.cache.invalidate(to);
+        self.inner.copy(from, to).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.cache.invalidate(from);
+        self.cache.invalidate(to);
+        self.inner.rename(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.cache.invalidate(to);
+        self.inner.copy_if_not_exists(from, to).await
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.cache.invalidate(from);
+        self.cache.invalidate(to);
+        self.inner.rename_if_not_exists(from, to).await
+    }
+}
@@ -0,0 +1,1240 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use serde::Serialize;
+
+use crate::backend::Backend;
+use crate::data::{ExtraVector, SizeDistribution, VectorType};
+use crate::distribution::AccessDistribution;
+use crate::index::{Metric, ScalarIndexType, VectorIndexType};
+use crate::metrics::MetricsFormat;
+use crate::op::ObjectStoreOp;
+use crate::profiling::FrameResolution;
+use crate::reconcile::LogFormat;
+use crate::run::{OnTimeout, Phase};
+use crate::steady::SteadyAfter;
+use crate::summary::SummaryFormat;
+use crate::sweep::RuntimeFlavor;
+
+pub const ENV_PREFIX: &str = "PPROF_DEV_";
+
+// `PPROF_DEV_STORAGE_AWS_ACCESS_KEY_ID=...` becomes the `aws_access_key_id`
+// storage option, and so on for any other credential or store setting
+const STORAGE_ENV_PREFIX: &str = "PPROF_DEV_STORAGE_";
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "pprof-dev",
+    about = "profile object store interactions of lance workloads",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// without a subcommand, runs the workload
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// build a self-contained html report from a run directory
+    Report(ReportArgs),
+    /// render a differential flamegraph of two profiles
+    Diff(DiffArgs),
+    /// combine profiles, e.g. of several workers, into one
+    Merge(MergeArgs),
+    /// merge the profiles of a shared run's `--process`es into its directory
+    Finalize(FinalizeArgs),
+    /// open profiles in pprof's web ui, or a minimal viewer without go
+    View(ViewArgs),
+    /// receive the profiles of workers run with `--agent-addr` and merge them
+    Collect(CollectArgs),
+    /// repeat the write, index and query phases and report their throughput
+    Bench(BenchArgs),
+    /// compare the requests s3 or minio logged for a run with those recorded
+    Reconcile(ReconcileArgs),
+    /// replay a run's traced gets at other block sizes and readaheads
+    Simulate(SimulateArgs),
+    /// rebuild the index and rerun the queries across ivf partition counts
+    Sweep(SweepArgs),
+    /// grow the dataset in steps, rebuilding the index and rerunning the
+    /// queries after each one
+    Scale(ScaleArgs),
+    /// run the same queries against different index types on the same data
+    Compare(CompareArgs),
+    /// run the same workload on a current-thread runtime and multi-thread
+    /// runtimes of different sizes
+    Runtimes(RuntimesArgs),
+    /// rerun the queries and scans across lance's io parallelism
+    Parallelism(ParallelismArgs),
+    /// rerun the scans projecting more and more of the dataset's columns
+    Projections(ProjectionsArgs),
+    /// rerun the scans across scanner batch sizes and fragment readaheads
+    ScanTuning(ScanTuningArgs),
+    /// rerun the scans with filters matching more and more of the rows
+    Selectivity(SelectivityArgs),
+    /// rerun the queries across result sizes and concurrent searches
+    Knn(KnnArgs),
+    /// rerun the queries and scans with more and more of the rows deleted
+    Deletes(DeletesArgs),
+    /// run the workload reading a local dataset through the filesystem store,
+    /// then through memory mapped files
+    Mmap(MmapArgs),
+    /// write, index and query several datasets at once
+    Multi(MultiArgs),
+    /// write the same rows to several stores and run the same phases against
+    /// each, in one run
+    Tiers(TiersArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReportArgs {
+    /// run directory to build the report from, e.g. `pprof-dev-runs/<run-id>`
+    pub run_dir: PathBuf,
+
+    /// defaults to `report.html` in the run directory
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MergeArgs {
+    /// the profiles to combine, e.g. every worker's `query_dataset_get.pb`
+    #[arg(required = true)]
+    pub profiles: Vec<PathBuf>,
+
+    /// where to write the combined profile
+    #[arg(long, short, default_value = "merged.pb")]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct FinalizeArgs {
+    /// the shared run directory, e.g. `pprof-dev-runs/<run-id>`
+    pub run_dir: PathBuf,
+
+    /// merge the profiles of processes that still hold their lock, because
+    /// they died, too
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    /// the profile to compare against, e.g. from the run before a change
+    pub before: PathBuf,
+
+    /// the profile drawn, colored by how each frame changed from `before`
+    pub after: PathBuf,
+
+    /// defaults to `after` with a `.diff.svg` extension
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ViewArgs {
+    /// profiles to view, or run directories to view all the profiles in
+    #[arg(required = true)]
+    pub profiles: Vec<PathBuf>,
+
+    /// where to serve the ui
+    #[arg(long, default_value = "localhost:8080")]
+    pub addr: String,
+
+    /// serve the built-in viewer even if go is installed
+    #[arg(long)]
+    pub embedded: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CollectArgs {
+    /// where to listen for workers
+    #[arg(long, default_value = "0.0.0.0:9200")]
+    pub addr: String,
+
+    /// where to keep each worker's profiles and write their merges
+    #[arg(long, default_value = "pprof-dev-collected")]
+    pub output_dir: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BenchArgs {
+    /// times to write, index and query the dataset, each in its own run
+    /// directory
+    #[arg(long, default_value_t = 3)]
+    pub repetitions: usize,
+
+    /// the workload each repetition runs
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReconcileArgs {
+    /// run directory whose requests to check, e.g. `pprof-dev-runs/<run-id>`
+    pub run_dir: PathBuf,
+
+    /// server log files, or directories of them
+    #[arg(required = true)]
+    pub logs: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = LogFormat::S3)]
+    pub log_format: LogFormat,
+
+    /// how far the server's clock (and a log's timestamp precision) may be
+    /// off, logged requests this close to a phase count towards it
+    #[arg(long, default_value_t = 1000)]
+    pub slack_ms: i64,
+
+    #[arg(long, value_enum, default_value_t = SummaryFormat::Text)]
+    pub format: SummaryFormat,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SimulateArgs {
+    /// run directory recorded with `--trace`, e.g. `pprof-dev-runs/<run-id>`
+    pub run_dir: PathBuf,
+
+    /// block sizes to read in, in bytes, above 0
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = [4096, 65536, 1 << 20, 8 << 20],
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub block_sizes: Vec<u64>,
+
+    /// bytes to read past the end of each get that misses, tried with each
+    /// block size
+    #[arg(long, value_delimiter = ',', default_values_t = [0])]
+    pub readahead: Vec<u64>,
+
+    #[arg(long, value_enum, default_value_t = SummaryFormat::Text)]
+    pub format: SummaryFormat,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SweepArgs {
+    /// partition counts to build the index with, e.g. `--partitions 4,16,64`
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub partitions: Vec<usize>,
+
+    /// the workload each configuration runs, `--num-partitions` is ignored
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ScaleArgs {
+    /// row counts to grow the dataset to, in increasing order, e.g.
+    /// `--steps 100000,1000000,10000000`
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub steps: Vec<i32>,
+
+    /// the workload each step runs, `--rows` is ignored
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CompareArgs {
+    /// index types to build and query, one after the other
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "ivf-pq,ivf-hnsw-pq"
+    )]
+    pub index_types: Vec<VectorIndexType>,
+
+    /// queries to measure recall over, against an exact search
+    #[arg(long, default_value_t = 20)]
+    pub recall_queries: usize,
+
+    /// the workload each index type runs, `--index-type` is ignored. all of
+    /// them search for the same vectors, from `--query-seed` if it's set
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RuntimesArgs {
+    /// tokio runtimes to run the workload on, one after the other: `current`
+    /// for a current-thread runtime, or a worker count for a multi-thread one
+    #[arg(long, value_delimiter = ',', default_value = "current,4")]
+    pub runtimes: Vec<RuntimeFlavor>,
+
+    /// the workload each runtime runs. all of them search for the same
+    /// vectors, from `--query-seed` if it's set
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ParallelismArgs {
+    /// requests lance's io scheduler may have in flight per store, e.g.
+    /// `--io-threads 1,8,64`
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub io_threads: Vec<usize>,
+
+    /// runs only the setting at this index of `--io-threads`, how the sweep
+    /// runs each of them in a process of its own
+    #[arg(long, hide = true)]
+    pub setting: Option<usize>,
+
+    /// the workload each setting runs. all of them search for the same
+    /// vectors, from `--query-seed` if it's set
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ProjectionsArgs {
+    /// how many of the dataset's columns each setting's scans project, the
+    /// first ones in schema order, e.g. `--widths 1,2,4`. every width from
+    /// one column to all of them by default
+    #[arg(long, value_delimiter = ',')]
+    pub widths: Vec<usize>,
+
+    /// the workload each width runs. `--scan-columns` is ignored
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ScanTuningArgs {
+    /// rows per batch to scan with, e.g. `--batch-sizes 1024,8192,65536`.
+    /// `--scan-batch-size` if not given
+    #[arg(long, value_delimiter = ',')]
+    pub batch_sizes: Vec<usize>,
+
+    /// fragments to read ahead, e.g. `--fragment-readaheads 1,4,16`, each
+    /// with every batch size. `--fragment-readahead` if not given
+    #[arg(long, value_delimiter = ',')]
+    pub fragment_readaheads: Vec<usize>,
+
+    /// the workload each setting runs
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SelectivityArgs {
+    /// the fractions of rows each setting's filter matches, spread evenly
+    /// over the dataset. rounded to one in every so many rows
+    #[arg(long, value_delimiter = ',', default_values_t = [0.0001, 0.001, 0.01, 0.1, 0.5])]
+    pub selectivities: Vec<f64>,
+
+    /// the workload each setting runs, after an unfiltered scan to compare
+    /// against. `--scan-filter` is ignored
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct KnnArgs {
+    /// results per search, e.g. `--ks 1,10,100,1000`
+    #[arg(long, value_delimiter = ',', default_values_t = [1, 10, 100, 1000])]
+    pub ks: Vec<usize>,
+
+    /// searches in flight at once, each with every k. `--concurrency` if not
+    /// given
+    #[arg(long, value_delimiter = ',')]
+    pub concurrencies: Vec<usize>,
+
+    /// the workload each setting runs. all of them search for the same
+    /// vectors, from `--query-seed` if it's set
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DeletesArgs {
+    /// the fractions of rows deleted before each setting's queries and
+    /// scans, spread evenly over the fragments. each setting deletes on top
+    /// of the one before
+    #[arg(long, value_delimiter = ',', default_values_t = [0.01, 0.1, 0.25, 0.5, 0.9])]
+    pub delete_ratios: Vec<f64>,
+
+    /// the workload each setting runs. all of them search for the same
+    /// vectors, from `--query-seed` if it's set
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MmapArgs {
+    /// the workload each reader runs, against a local `--uri`. `--mmap` is
+    /// ignored
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MultiArgs {
+    /// datasets to run the workload against concurrently, each written next
+    /// to `--uri` with its index as a suffix
+    #[arg(long, default_value_t = 2)]
+    pub datasets: usize,
+
+    /// the workload each dataset runs
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TiersArgs {
+    /// a store to run the workload against, as `NAME=URI`, e.g. `--tier
+    /// local=/tmp/tiers.lance --tier s3=s3://bucket/tiers.lance`. the first
+    /// is the one the others are compared with
+    #[arg(long = "tier", value_name = "NAME=URI", value_parser = parse_key_val, required = true)]
+    pub tiers: Vec<(String, String)>,
+
+    /// a storage option for one tier only, on top of the `--storage-option`s
+    /// every tier gets, e.g. `--tier-option s3:aws_region=us-east-1`
+    #[arg(long = "tier-option", value_name = "NAME:KEY=VALUE", value_parser = parse_tier_option)]
+    pub tier_options: Vec<(String, (String, String))>,
+
+    /// the workload each tier runs. all of them search for the same vectors,
+    /// from `--query-seed` if it's set. `--uri` is ignored
+    #[command(flatten)]
+    pub run: Args,
+}
+
+#[derive(Debug, Clone, clap::Args, Serialize)]
+pub struct Args {
+    /// uri of the dataset to write, index and query
+    #[arg(long, default_value = "~/Desktop/lance_datasets/test_pprof.lance")]
+    pub uri: String,
+
+    /// object store option passed through to lance (credentials, region,
+    /// endpoint, ...), e.g. `--storage-option aws_region=us-east-1`
+    #[arg(long = "storage-option", value_name = "KEY=VALUE", value_parser = parse_key_val)]
+    pub storage_options: Vec<(String, String)>,
+
+    /// the store behind `--uri`
+    #[arg(long, value_enum, default_value_t = Backend::Uri)]
+    pub backend: Backend,
+
+    /// the minio server `--backend minio` sends requests to
+    #[arg(long, default_value = "http://localhost:9000")]
+    pub minio_endpoint: String,
+
+    #[arg(long, default_value_t = 20_000)]
+    pub rows: i32,
+
+    #[arg(long, default_value_t = 1536)]
+    pub vector_dims: i32,
+
+    /// split the written rows into fragments of at most this many rows
+    #[arg(long)]
+    pub max_rows_per_file: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = VectorType::Float32)]
+    pub vector_type: VectorType,
+
+    /// generate a multivector column with this many vectors per row
+    #[arg(long)]
+    pub multivector: Option<usize>,
+
+    /// another vector column with dims of its own, indexed and searched
+    /// alongside `vector`, e.g. `--extra-vector image=512`. repeatable
+    #[arg(long, value_name = "NAME=DIMS", value_delimiter = ',')]
+    pub extra_vector: Vec<ExtraVector>,
+
+    /// add a `blob` large binary column with blobs of around this many bytes
+    #[arg(long)]
+    pub blob_size: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = SizeDistribution::Fixed)]
+    pub blob_size_distribution: SizeDistribution,
+
+    /// add a `label` utf8 column with this many distinct values
+    #[arg(long)]
+    pub string_cardinality: Option<usize>,
+
+    /// mean length of the `label` values
+    #[arg(long, default_value_t = 16)]
+    pub string_length: usize,
+
+    #[arg(long, value_enum, default_value_t = SizeDistribution::Fixed)]
+    pub string_length_distribution: SizeDistribution,
+
+    /// fraction of rows with a null vector, blob and label, 0 keeps the columns
+    /// non-nullable. from 0 to 1
+    #[arg(long, default_value_t = 0.0, value_parser = parse_fraction)]
+    pub null_fraction: f64,
+
+    /// distance to index and search by. defaults to hamming for binary
+    /// vectors, cosine for multivectors and l2 otherwise
+    #[arg(long, value_enum)]
+    pub metric: Option<Metric>,
+
+    #[arg(long, value_enum, default_value_t = VectorIndexType::IvfPq)]
+    pub index_type: VectorIndexType,
+
+    #[arg(long, default_value_t = 4)]
+    pub num_partitions: usize,
+
+    /// pq sub vectors, for the pq index types
+    #[arg(long, default_value_t = 2)]
+    pub num_sub_vectors: usize,
+
+    /// bits per pq code or per sq dimension
+    #[arg(long, default_value_t = 8)]
+    pub num_bits: u16,
+
+    /// kmeans iterations when training ivf partitions
+    #[arg(long, default_value_t = 1)]
+    pub max_iterations: usize,
+
+    /// columns to build a scalar index on in the index phase, e.g.
+    /// `--scalar-index id,label`
+    #[arg(long, value_delimiter = ',')]
+    pub scalar_index: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t = ScalarIndexType::Btree)]
+    pub scalar_index_type: ScalarIndexType,
+
+    /// sql filter to prefilter searches by in a prefilter phase run after the
+    /// query phase, e.g. `id < 1000`
+    #[arg(long)]
+    pub prefilter: Option<String>,
+
+    /// number of nearest neighbour searches to run in the query phase
+    #[arg(long, default_value_t = 10)]
+    pub queries: usize,
+
+    #[arg(long, default_value_t = 10)]
+    pub k: usize,
+
+    /// iterations of the query, scan and time_range workloads to run before
+    /// each of those phases starts recording
+    #[arg(long, default_value_t = 0)]
+    pub warmup: usize,
+
+    /// record the queries up to this point as the query_startup phase and
+    /// only the rest as the query phase: after a number of queries, or `auto`
+    /// once the requests per query level off
+    #[arg(long)]
+    pub steady_after: Option<SteadyAfter>,
+
+    /// queries per window compared to detect the steady state with
+    /// `--steady-after auto`
+    #[arg(long, default_value_t = 10)]
+    pub steady_window: usize,
+
+    /// after the query phase, rerun it against a freshly opened dataset
+    /// (query_cold) and again once that's warmed up (query_warm)
+    #[arg(long)]
+    pub cold_warm: bool,
+
+    /// then load a freshly opened dataset's indices and every partition of
+    /// them (index_prime) before rerunning the queries (query_primed), and
+    /// compare them with queries against an unprimed dataset (query_cold)
+    #[arg(long)]
+    pub prime_index: bool,
+
+    /// tasks issuing queries at once in the query phase
+    #[arg(long, default_value_t = 1)]
+    pub concurrency: usize,
+
+    /// target queries per second across all tasks, unpaced by default
+    #[arg(long, value_parser = parse_positive)]
+    pub qps: Option<f64>,
+
+    /// run the query phase for this long instead of `--queries` queries
+    #[arg(long)]
+    pub duration_secs: Option<u64>,
+
+    /// number of distinct query vectors the query phase picks from, at least 1
+    #[arg(long, default_value_t = 100, value_parser = parse_count)]
+    pub query_pool: usize,
+
+    /// draw the query vectors from a generator seeded with this, so separate
+    /// runs search for the same vectors
+    #[arg(long)]
+    pub query_seed: Option<u64>,
+
+    /// number of take calls to make in the take phase
+    #[arg(long, default_value_t = 10)]
+    pub takes: usize,
+
+    /// rows fetched by each take
+    #[arg(long, default_value_t = 10)]
+    pub take_size: usize,
+
+    /// number of scans to run in the scan phase
+    #[arg(long, default_value_t = 1)]
+    pub scans: usize,
+
+    /// comma separated columns the scan phase projects, defaults to all of them
+    #[arg(long)]
+    pub scan_columns: Option<String>,
+
+    /// sql filter applied in the scan phase, e.g. `id % 100 = 0`
+    #[arg(long)]
+    pub scan_filter: Option<String>,
+
+    /// rows per batch the scan phase's scanner reads, lance's default if
+    /// unset
+    #[arg(long)]
+    pub scan_batch_size: Option<usize>,
+
+    /// fragments the scan phase's scanner reads ahead, lance's default if
+    /// unset
+    #[arg(long)]
+    pub fragment_readahead: Option<usize>,
+
+    /// sql statement to run in a sql phase, against the dataset as table `t`,
+    /// e.g. `--sql "SELECT label, count(*) FROM t GROUP BY label"`. repeatable
+    #[arg(long)]
+    pub sql: Vec<String>,
+
+    /// times to run each `--sql` statement
+    #[arg(long, default_value_t = 1)]
+    pub sql_repeats: usize,
+
+    /// number of time-range filtered scans to run in the time_range phase
+    #[arg(long, default_value_t = 10)]
+    pub time_range_queries: usize,
+
+    /// fraction of the dataset's time span each time-range scan covers
+    #[arg(long, default_value_t = 0.01)]
+    pub time_range_fraction: f64,
+
+    /// rows appended to the indexed dataset before the optimize_index phase
+    #[arg(long, default_value_t = 1_000)]
+    pub append_rows: u64,
+
+    /// number of existing index deltas optimize_indices merges the new rows
+    /// into, 0 always writes a new delta
+    #[arg(long, default_value_t = 1)]
+    pub merge_indices: usize,
+
+    /// sql expression the evolve phase backfills its new column from
+    #[arg(long, default_value = "id * 2")]
+    pub add_column_expr: String,
+
+    /// versions to create (by deleting a row each) before the cleanup phase
+    /// removes all but the latest
+    #[arg(long, default_value_t = 10)]
+    pub cleanup_versions: usize,
+
+    /// compaction tasks (groups of adjacent small fragments) the rewrite phase
+    /// runs, the rest of the plan is left alone
+    #[arg(long, default_value_t = 1)]
+    pub rewrite_tasks: usize,
+
+    /// how query vectors and taken rows are picked
+    #[arg(long, value_enum, default_value_t = AccessDistribution::Uniform)]
+    pub distribution: AccessDistribution,
+
+    /// the `s` of `--distribution zipfian`, above 0. the higher, the more
+    /// picks land on the hottest keys
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    pub zipf_exponent: f64,
+
+    /// the share of keys that are hot with `--distribution hotspot`, from 0
+    /// to 1
+    #[arg(long, default_value_t = 0.1, value_parser = parse_fraction)]
+    pub hotspot_fraction: f64,
+
+    /// the share of picks that land on the hot keys with `--distribution
+    /// hotspot`, from 0 to 1
+    #[arg(long, default_value_t = 0.9, value_parser = parse_fraction)]
+    pub hotspot_probability: f64,
+
+    /// directory under which each run gets its own run directory
+    #[arg(long, default_value = "pprof-dev-runs")]
+    pub output_dir: PathBuf,
+
+    /// defaults to the time the run started and its pid, e.g.
+    /// `20250327-101500-4242`
+    #[arg(long)]
+    pub run_id: Option<String>,
+
+    /// write into `processes/<NAME>/` of the run directory, for several
+    /// processes sharing one `--run-id`. `{run_id}` in the output template
+    /// stands for that directory then
+    #[arg(long, value_name = "NAME")]
+    pub process: Option<String>,
+
+    /// where each profile is written, relative to `--output-dir`. supports the
+    /// `{run_id}`, `{timestamp}`, `{phase}`, `{op}` and `{ext}` placeholders
+    #[arg(long, default_value = "{run_id}/{phase}_{op}.{ext}")]
+    pub output_template: String,
+
+    /// don't capture stacks at all, only count requests, bytes and latency
+    /// per op into flat profiles, for when stack capture skews the workload
+    #[arg(long)]
+    pub count_only: bool,
+
+    /// keep only this many frames of each profiled stack, counted from the
+    /// object store call outwards
+    #[arg(long)]
+    pub max_stack_depth: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = FrameResolution::Full)]
+    pub frame_resolution: FrameResolution,
+
+    /// also write `{op}_bytes` profiles of the count, total, smallest and
+    /// largest bytes of the gets and puts made from each stack
+    #[arg(long)]
+    pub request_sizes: bool,
+
+    /// also write `{op}_latency` profiles with a sample per power of two
+    /// microseconds of latency, next to the stack or count-only profiles
+    #[arg(long)]
+    pub latency_profiles: bool,
+
+    /// ops not to profile until they're toggled on, e.g. `--disable-ops put`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub disable_ops: Vec<ObjectStoreOp>,
+
+    /// ops whose profiling SIGUSR1 turns on or off again, e.g. to profile
+    /// puts only while a long run commits
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub toggle_ops: Vec<ObjectStoreOp>,
+
+    /// answer heads from a cache of their results for this long, writing how
+    /// often each call stack hit it to `{phase}_head_cache.txt`
+    #[arg(long)]
+    pub head_cache_ttl_ms: Option<u64>,
+
+    /// prefetch this many bytes past every ranged get, answering later gets
+    /// from them, and write how much of each call stack's prefetches went
+    /// unused to `{phase}_prefetch.txt`
+    #[arg(long)]
+    pub prefetch_bytes: Option<usize>,
+
+    /// let at most this many requests at a time through to the store, like a
+    /// smaller connection pool would. requests waiting for one aren't timed
+    #[arg(long)]
+    pub store_permits: Option<usize>,
+
+    /// serve gets from memory mapped files instead of reading them, for a
+    /// local dataset opened as `file-object-store://<path>`
+    #[arg(long)]
+    pub mmap: bool,
+
+    /// a toml file stacking the layers between lance and the store in any
+    /// order, in place of the flags for each, see `src/layers.rs`
+    #[arg(
+        long,
+        conflicts_with_all = ["head_cache_ttl_ms", "prefetch_bytes", "store_permits", "mmap"]
+    )]
+    pub store_layers: Option<PathBuf>,
+
+    /// write the ranged gets that could have been coalesced with another, by
+    /// the stack that issued them, to `{phase}_coalescing.txt`
+    #[arg(long)]
+    pub coalescing: bool,
+
+    /// how far apart two ranges can be and still count as coalescable
+    #[arg(long, default_value_t = 0)]
+    pub coalesce_gap_bytes: usize,
+
+    /// how soon after a get another has to be issued to be coalescable with it
+    #[arg(long, default_value_t = 10)]
+    pub coalesce_window_ms: u64,
+
+    /// label the gets of data files with the column (and page) of the
+    /// dataset they read, from the files' layout, and write the bytes read
+    /// of each column to `{phase}_columns.txt`
+    #[arg(long)]
+    pub column_attribution: bool,
+
+    /// label every request with the tenant its key belongs to, by default the
+    /// dataset directory it's in, and summarize each tenant's requests in
+    /// `tenants.{txt,csv}`
+    #[arg(long)]
+    pub tenant_labels: bool,
+
+    /// take the tenant from this segment of the keys instead, counting from 0
+    #[arg(long)]
+    pub tenant_segment: Option<usize>,
+
+    /// otlp grpc endpoint (e.g. jaeger's, `http://localhost:4317`) to export
+    /// tracing spans to. needs the `otel` feature
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// serve the run's request counters as json at `/stats` on this address,
+    /// e.g. `localhost:9100`
+    #[arg(long)]
+    pub admin_addr: Option<String>,
+
+    /// log every object store request as it's made and when it completes, at
+    /// info level (e.g. `RUST_LOG=pprof_dev=info`)
+    #[arg(long)]
+    pub log_requests: bool,
+
+    /// label every request with the id of the tokio task that made it, and
+    /// where the task was spawned when tokio traces its tasks
+    #[arg(long)]
+    pub task_labels: bool,
+
+    /// record a chrome trace of every object store request to `trace.json` in
+    /// the run directory, viewable in Perfetto or chrome://tracing
+    #[arg(long)]
+    pub trace: bool,
+
+    /// draw the stack each request was made from under it in the trace, as
+    /// a flame chart over time
+    #[arg(long, requires = "trace")]
+    pub trace_stacks: bool,
+
+    /// chart the requests in flight over the run in `timeline.svg`
+    #[arg(long)]
+    pub queue_depth: bool,
+
+    /// write every request that takes longer than this to
+    /// `slow_requests.jsonl` in the run directory, with its stack, path, range
+    /// and latency
+    #[arg(long)]
+    pub slow_request_ms: Option<u64>,
+
+    /// write every request that moves at least this many bytes to
+    /// `huge_requests.jsonl` the same way, to catch whole file reads
+    #[arg(long)]
+    pub huge_request_bytes: Option<usize>,
+
+    /// format of the request summary written to the run directory when the
+    /// run finishes
+    #[arg(long, value_enum, default_value_t = SummaryFormat::Text)]
+    pub format: SummaryFormat,
+
+    /// push each phase's aggregate metrics here when it finishes, e.g.
+    /// `udp://localhost:8089` or `http://localhost:8086/api/v2/write?bucket=bench`
+    #[arg(long, value_name = "URL")]
+    pub metrics_sink: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = MetricsFormat::Influx)]
+    pub metrics_format: MetricsFormat,
+
+    /// dollars per get or head, for the cost estimate in `cost.txt`
+    #[arg(long, default_value_t = 0.0000004)]
+    pub price_per_get: f64,
+
+    /// dollars per put, copy, rename or listing
+    #[arg(long, default_value_t = 0.000005)]
+    pub price_per_put: f64,
+
+    /// dollars per GB read out of the store
+    #[arg(long, default_value_t = 0.09)]
+    pub price_per_gb_egress: f64,
+
+    /// copy the run directory to this url (e.g. `s3://bucket/prefix`) once the
+    /// run finishes, under a directory named after the run id. uses the same
+    /// storage options as the dataset
+    #[arg(long, value_name = "URL")]
+    pub upload_to: Option<String>,
+
+    /// send the profiles to a `pprof-dev collect` at this address after each
+    /// phase, e.g. `collector:9200`
+    #[arg(long)]
+    pub agent_addr: Option<String>,
+
+    /// the name the collector keeps this process's profiles under, defaults
+    /// to the hostname and pid
+    #[arg(long)]
+    pub agent_worker: Option<String>,
+
+    /// cut every phase short after this long, writing what it recorded so far
+    #[arg(long)]
+    pub phase_timeout_secs: Option<u64>,
+
+    /// a timeout for one phase in seconds, overriding `--phase-timeout-secs`,
+    /// e.g. `--phase-timeout index=600,query=60`
+    #[arg(
+        long = "phase-timeout",
+        value_name = "PHASE=SECS",
+        value_delimiter = ',',
+        value_parser = parse_phase_timeout
+    )]
+    pub phase_timeouts: Vec<(Phase, u64)>,
+
+    /// what to do once a phase times out
+    #[arg(long, value_enum, default_value_t = OnTimeout::Continue)]
+    pub on_timeout: OnTimeout,
+
+    /// continue the run identified by `--run-id`, skipping phases it already completed
+    #[arg(long, requires = "run_id")]
+    pub resume: bool,
+}
+
+impl Cli {
+    /// parses the command line, then applies any `PPROF_DEV_*` environment
+    /// variables on top. the environment wins over flags so CI can tweak a
+    /// containerized run without touching the command it was built with
+    pub fn load() -> Result<Self, clap::Error> {
+        let mut cli = Self::parse();
+        cli.run.apply_env_overrides()?;
+        if let Some(run) = cli.command.as_mut().and_then(Command::run_args_mut) {
+            run.apply_env_overrides()?;
+        }
+        Ok(cli)
+    }
+}
+
+impl Command {
+    /// the workload of the subcommands that run one
+    pub fn run_args_mut(&mut self) -> Option<&mut Args> {
+        match self {
+            Command::Report(_)
+            | Command::Diff(_)
+            | Command::Merge(_)
+            | Command::Finalize(_)
+            | Command::View(_)
+            | Command::Collect(_)
+            | Command::Reconcile(_)
+            | Command::Simulate(_) => None,
+            Command::Bench(args) => Some(&mut args.run),
+            Command::Sweep(args) => Some(&mut args.run),
+            Command::Scale(args) => Some(&mut args.run),
+            Command::Compare(args) => Some(&mut args.run),
+            Command::Runtimes(args) => Some(&mut args.run),
+            Command::Parallelism(args) => Some(&mut args.run),
+            Command::Projections(args) => Some(&mut args.run),
+            Command::ScanTuning(args) => Some(&mut args.run),
+            Command::Selectivity(args) => Some(&mut args.run),
+            Command::Knn(args) => Some(&mut args.run),
+            Command::Deletes(args) => Some(&mut args.run),
+            Command::Mmap(args) => Some(&mut args.run),
+            Command::Multi(args) => Some(&mut args.run),
+            Command::Tiers(args) => Some(&mut args.run),
+        }
+    }
+}
+
+impl Args {
+    /// the config as saved in the run directory, without storage option
+    /// values since those are usually credentials
+    pub fn redacted(&self) -> Self {
+        let mut args = self.clone();
+        for (_, value) in &mut args.storage_options {
+            *value = "<redacted>".to_string();
+        }
+        args
+    }
+
+    /// how long `phase` gets before it's cut short, if at all
+    pub fn phase_timeout(&self, phase: Phase) -> Option<Duration> {
+        self.phase_timeouts
+            .iter()
+            .rev()
+            .find(|(p, _)| *p == phase)
+            .map(|(_, secs)| *secs)
+            .or(self.phase_timeout_secs)
+            .map(Duration::from_secs)
+    }
+
+    pub fn storage_options(&self) -> Option<HashMap<String, String>> {
+        let mut options = self.backend.storage_options(&self.minio_endpoint);
+        options.extend(self.storage_options.iter().cloned());
+        if options.is_empty() {
+            return None;
+        }
+        Some(options.into_iter().collect())
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<(), clap::Error> {
+        override_from_env("URI", &mut self.uri)?;
+        override_from_env("BACKEND", &mut self.backend)?;
+        override_from_env("MINIO_ENDPOINT", &mut self.minio_endpoint)?;
+        override_from_env("ROWS", &mut self.rows)?;
+        override_from_env("VECTOR_DIMS", &mut self.vector_dims)?;
+        override_opt_from_env("MAX_ROWS_PER_FILE", &mut self.max_rows_per_file)?;
+        override_from_env("VECTOR_TYPE", &mut self.vector_type)?;
+        override_opt_from_env("MULTIVECTOR", &mut self.multivector)?;
+        override_list_from_env("EXTRA_VECTOR", &mut self.extra_vector)?;
+        override_opt_from_env("BLOB_SIZE", &mut self.blob_size)?;
+        override_from_env("BLOB_SIZE_DISTRIBUTION", &mut self.blob_size_distribution)?;
+        override_opt_from_env("STRING_CARDINALITY", &mut self.string_cardinality)?;
+        override_from_env("STRING_LENGTH", &mut self.string_length)?;
+        override_from_env(
+            "STRING_LENGTH_DISTRIBUTION",
+            &mut self.string_length_distribution,
+        )?;
+        override_parsed_from_env("NULL_FRACTION", &mut self.null_fraction, parse_fraction)?;
+        override_opt_from_env("METRIC", &mut self.metric)?;
+        override_from_env("INDEX_TYPE", &mut self.index_type)?;
+        override_from_env("NUM_PARTITIONS", &mut self.num_partitions)?;
+        override_from_env("NUM_SUB_VECTORS", &mut self.num_sub_vectors)?;
+        override_from_env("NUM_BITS", &mut self.num_bits)?;
+        override_from_env("MAX_ITERATIONS", &mut self.max_iterations)?;
+        override_list_from_env("SCALAR_INDEX", &mut self.scalar_index)?;
+        override_from_env("SCALAR_INDEX_TYPE", &mut self.scalar_index_type)?;
+        override_opt_from_env("PREFILTER", &mut self.prefilter)?;
+        override_from_env("QUERIES", &mut self.queries)?;
+        override_from_env("K", &mut self.k)?;
+        override_from_env("WARMUP", &mut self.warmup)?;
+        override_opt_from_env("STEADY_AFTER", &mut self.steady_after)?;
+        override_from_env("STEADY_WINDOW", &mut self.steady_window)?;
+        override_from_env("COLD_WARM", &mut self.cold_warm)?;
+        override_from_env("PRIME_INDEX", &mut self.prime_index)?;
+        override_from_env("CONCURRENCY", &mut self.concurrency)?;
+        override_opt_parsed_from_env("QPS", &mut self.qps, parse_positive)?;
+        override_opt_from_env("DURATION_SECS", &mut self.duration_secs)?;
+        override_parsed_from_env("QUERY_POOL", &mut self.query_pool, parse_count)?;
+        override_opt_from_env("QUERY_SEED", &mut self.query_seed)?;
+        override_from_env("TAKES", &mut self.takes)?;
+        override_from_env("TAKE_SIZE", &mut self.take_size)?;
+        override_from_env("SCANS", &mut self.scans)?;
+        override_opt_from_env("SCAN_COLUMNS", &mut self.scan_columns)?;
+        override_opt_from_env("SCAN_FILTER", &mut self.scan_filter)?;
+        override_opt_from_env("SCAN_BATCH_SIZE", &mut self.scan_batch_size)?;
+        override_opt_from_env("FRAGMENT_READAHEAD", &mut self.fragment_readahead)?;
+        // statements have commas of their own
+        if let Ok(raw) = env::var(format!("{ENV_PREFIX}SQL")) {
+            self.sql = raw
+                .split(';')
+                .map(str::trim)
+                .filter(|sql| !sql.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        override_from_env("SQL_REPEATS", &mut self.sql_repeats)?;
+        override_from_env("TIME_RANGE_QUERIES", &mut self.time_range_queries)?;
+        override_from_env("TIME_RANGE_FRACTION", &mut self.time_range_fraction)?;
+        override_from_env("APPEND_ROWS", &mut self.append_rows)?;
+        override_from_env("MERGE_INDICES", &mut self.merge_indices)?;
+        override_from_env("ADD_COLUMN_EXPR", &mut self.add_column_expr)?;
+        override_from_env("CLEANUP_VERSIONS", &mut self.cleanup_versions)?;
+        override_from_env("REWRITE_TASKS", &mut self.rewrite_tasks)?;
+        override_from_env("DISTRIBUTION", &mut self.distribution)?;
+        override_parsed_from_env("ZIPF_EXPONENT", &mut self.zipf_exponent, parse_positive)?;
+        override_parsed_from_env(
+            "HOTSPOT_FRACTION",
+            &mut self.hotspot_fraction,
+            parse_fraction,
+        )?;
+        override_parsed_from_env(
+            "HOTSPOT_PROBABILITY",
+            &mut self.hotspot_probability,
+            parse_fraction,
+        )?;
+        override_from_env("OUTPUT_DIR", &mut self.output_dir)?;
+        override_opt_from_env("RUN_ID", &mut self.run_id)?;
+        override_opt_from_env("PROCESS", &mut self.process)?;
+        override_from_env("OUTPUT_TEMPLATE", &mut self.output_template)?;
+        override_from_env("LOG_REQUESTS", &mut self.log_requests)?;
+        override_from_env("TASK_LABELS", &mut self.task_labels)?;
+        override_from_env("TRACE", &mut self.trace)?;
+        override_from_env("TRACE_STACKS", &mut self.trace_stacks)?;
+        override_from_env("QUEUE_DEPTH", &mut self.queue_depth)?;
+        override_opt_from_env("SLOW_REQUEST_MS", &mut self.slow_request_ms)?;
+        override_opt_from_env("HUGE_REQUEST_BYTES", &mut self.huge_request_bytes)?;
+        override_from_env("FORMAT", &mut self.format)?;
+        override_from_env("COUNT_ONLY", &mut self.count_only)?;
+        override_opt_from_env("MAX_STACK_DEPTH", &mut self.max_stack_depth)?;
+        override_from_env("FRAME_RESOLUTION", &mut self.frame_resolution)?;
+        override_from_env("REQUEST_SIZES", &mut self.request_sizes)?;
+        override_from_env("LATENCY_PROFILES", &mut self.latency_profiles)?;
+        override_list_from_env("DISABLE_OPS", &mut self.disable_ops)?;
+        override_list_from_env("TOGGLE_OPS", &mut self.toggle_ops)?;
+        override_opt_from_env("HEAD_CACHE_TTL_MS", &mut self.head_cache_ttl_ms)?;
+        override_opt_from_env("PREFETCH_BYTES", &mut self.prefetch_bytes)?;
+        override_opt_from_env("STORE_PERMITS", &mut self.store_permits)?;
+        override_from_env("MMAP", &mut self.mmap)?;
+        override_opt_from_env("STORE_LAYERS", &mut self.store_layers)?;
+        override_from_env("COALESCING", &mut self.coalescing)?;
+        override_from_env("COALESCE_GAP_BYTES", &mut self.coalesce_gap_bytes)?;
+        override_from_env("COALESCE_WINDOW_MS", &mut self.coalesce_window_ms)?;
+        override_from_env("COLUMN_ATTRIBUTION", &mut self.column_attribution)?;
+        override_from_env("TENANT_LABELS", &mut self.tenant_labels)?;
+        override_opt_from_env("TENANT_SEGMENT", &mut self.tenant_segment)?;
+        override_opt_from_env("OTLP_ENDPOINT", &mut self.otlp_endpoint)?;
+        override_opt_from_env("ADMIN_ADDR", &mut self.admin_addr)?;
+        override_opt_from_env("METRICS_SINK", &mut self.metrics_sink)?;
+        override_from_env("METRICS_FORMAT", &mut self.metrics_format)?;
+        override_from_env("PRICE_PER_GET", &mut self.price_per_get)?;
+        override_from_env("PRICE_PER_PUT", &mut self.price_per_put)?;
+        override_from_env("PRICE_PER_GB_EGRESS", &mut self.price_per_gb_egress)?;
+        override_opt_from_env("UPLOAD_TO", &mut self.upload_to)?;
+        override_opt_from_env("AGENT_ADDR", &mut self.agent_addr)?;
+        override_opt_from_env("AGENT_WORKER", &mut self.agent_worker)?;
+        override_from_env("RESUME", &mut self.resume)?;
+        override_opt_from_env("PHASE_TIMEOUT_SECS", &mut self.phase_timeout_secs)?;
+        override_from_env("ON_TIMEOUT", &mut self.on_timeout)?;
+        if let Ok(raw) = env::var(format!("{ENV_PREFIX}PHASE_TIMEOUT")) {
+            self.phase_timeouts = raw
+                .split(',')
+                .map(|s| {
+                    parse_phase_timeout(s)
+                        .map_err(|e| invalid_env(&format!("{ENV_PREFIX}PHASE_TIMEOUT"), s, e))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        for (key, value) in env::vars() {
+            if let Some(option) = key.strip_prefix(STORAGE_ENV_PREFIX) {
+                let option = option.to_lowercase();
+                self.storage_options.retain(|(k, _)| *k != option);
+                self.storage_options.push((option, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn override_from_env<T>(name: &str, value: &mut T) -> Result<(), clap::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(parsed) = parse_env(name)? {
+        *value = parsed;
+    }
+    Ok(())
+}
+
+fn override_opt_from_env<T>(name: &str, value: &mut Option<T>) -> Result<(), clap::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(parsed) = parse_env(name)? {
+        *value = Some(parsed);
+    }
+    Ok(())
+}
+
+/// an override checked with the flag's `value_parser`
+fn override_parsed_from_env<T>(
+    name: &str,
+    value: &mut T,
+    parse: fn(&str) -> Result<T, String>,
+) -> Result<(), clap::Error> {
+    if let Some(parsed) = parse_env_with(name, parse)? {
+        *value = parsed;
+    }
+    Ok(())
+}
+
+fn override_opt_parsed_from_env<T>(
+    name: &str,
+    value: &mut Option<T>,
+    parse: fn(&str) -> Result<T, String>,
+) -> Result<(), clap::Error> {
+    if let Some(parsed) = parse_env_with(name, parse)? {
+        *value = Some(parsed);
+    }
+    Ok(())
+}
+
+fn parse_env_with<T>(
+    name: &str,
+    parse: fn(&str) -> Result<T, String>,
+) -> Result<Option<T>, clap::Error> {
+    let var = format!("{ENV_PREFIX}{name}");
+    let Ok(raw) = env::var(&var) else {
+        return Ok(None);
+    };
+    let parsed = parse(&raw).map_err(|e| invalid_env(&var, &raw, e))?;
+    log::debug!("{var} overrides configured value");
+    Ok(Some(parsed))
+}
+
+/// a comma separated override
+fn override_list_from_env<T>(name: &str, values: &mut Vec<T>) -> Result<(), clap::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(parsed) = parse_env::<String>(name)? {
+        let var = format!("{ENV_PREFIX}{name}");
+        *values = parsed
+            .split(',')
+            .map(|s| s.parse::<T>().map_err(|e| invalid_env(&var, s, e)))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(())
+}
+
+fn parse_env<T>(name: &str) -> Result<Option<T>, clap::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let var = format!("{ENV_PREFIX}{name}");
+    let Ok(raw) = env::var(&var) else {
+        return Ok(None);
+    };
+    let parsed = raw.parse().map_err(|e| invalid_env(&var, &raw, e))?;
+    log::debug!("{var} overrides configured value");
+    Ok(Some(parsed))
+}
+
+/// a bad override, reported like a bad flag
+fn invalid_env(var: &str, raw: &str, e: impl Display) -> clap::Error {
+    Cli::command().error(
+        ErrorKind::InvalidValue,
+        format!("invalid value {raw:?} for {var}: {e}"),
+    )
+}
+
+fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {s:?}"))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn parse_tier_option(s: &str) -> Result<(String, (String, String)), String> {
+    let (tier, option) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected NAME:KEY=VALUE, got {s:?}"))?;
+    Ok((tier.to_string(), parse_key_val(option)?))
+}
+
+/// a finite number above 0
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let value: f64 = s
+        .parse()
+        .map_err(|e| format!("invalid number {s:?}: {e}"))?;
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("{s} isn't a finite number above 0"))
+    }
+}
+
+/// a whole number above 0
+fn parse_count(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("has to be at least 1".to_string()),
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("invalid count {s:?}: {e}")),
+    }
+}
+
+/// a number from 0 to 1
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let value: f64 = s
+        .parse()
+        .map_err(|e| format!("invalid number {s:?}: {e}"))?;
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{s} isn't from 0 to 1"))
+    }
+}
+
+fn parse_phase_timeout(s: &str) -> Result<(Phase, u64), String> {
+    let (phase, secs) = parse_key_val(s)?;
+    let secs = secs
+        .parse()
+        .map_err(|e| format!("invalid timeout {secs:?}: {e}"))?;
+    Ok((phase.parse()?, secs))
+}
@@ -0,0 +1,68 @@
+//! Where the hooked store reads the time from to time its requests, so tests
+//! can decide how long each request took instead of sleeping.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub trait Clock: Send + Sync + 'static {
+    /// the time since some fixed point of the clock's own, only ever
+    /// compared with its other readings
+    fn now(&self) -> Duration;
+}
+
+/// The real, monotonic time.
+pub struct MonotonicClock(Instant);
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// A clock that only moves when it's told to.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanos: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn advance(&self, by: Duration) {
+        self.nanos
+            .fetch_add(by.as_nanos() as u64, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+/// When something started, by its clock.
+#[derive(Clone)]
+pub struct Started {
+    clock: Arc<dyn Clock>,
+    at: Duration,
+}
+
+impl Started {
+    pub fn now(clock: &Arc<dyn Clock>) -> Self {
+        Self {
+            clock: clock.clone(),
+            at: clock.now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_sub(self.at)
+    }
+}
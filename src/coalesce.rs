@@ -0,0 +1,163 @@
+//! Ranged gets that could have been coalesced with another, enabled with
+//! `--coalescing`: those to the same object, issued close together, whose
+//! ranges overlap or are at most `--coalesce-gap-bytes` apart. Each is a round
+//! trip lance's scheduler could have saved, and they're broken down by the
+//! call stack that issued them to show where to tune it.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use object_store::path::Path;
+use parking_lot::Mutex;
+
+use crate::hooks::StoreHooks;
+use crate::op::ObjectStoreOp;
+use crate::stacks;
+
+/// the modules issuing a get to the analyzer, whose frames are dropped from
+/// its stack in the report
+const OWN_MODULES: &[&str] = &[
+    "pprof_dev::coalesce::",
+    "pprof_dev::hooks::",
+    "pprof_dev::scope::",
+];
+
+/// stacks shown in the report, most wasted round trips first
+const REPORT_STACKS: usize = 20;
+
+struct Issued {
+    path: Path,
+    range: Range<usize>,
+    at: Duration,
+    stack: Vec<usize>,
+}
+
+#[derive(Default)]
+struct StackStats {
+    gets: u64,
+    /// gets that could have been part of an earlier one
+    coalescable: u64,
+    /// bytes of those gets
+    coalescable_bytes: u64,
+    /// bytes of those gets an earlier one had already fetched
+    overlapping_bytes: u64,
+}
+
+pub struct CoalescingAnalyzer {
+    gap: usize,
+    window: Duration,
+    started: Instant,
+    issued: Mutex<Vec<Issued>>,
+}
+
+impl CoalescingAnalyzer {
+    /// gets issued within `window` of each other whose ranges are at most
+    /// `gap` bytes apart could have been one
+    pub fn new(gap: usize, window: Duration) -> Self {
+        Self {
+            gap,
+            window,
+            started: Instant::now(),
+            issued: Mutex::new(vec![]),
+        }
+    }
+
+    /// what could have been coalesced of the gets issued since the last call
+    pub fn take_report(&self) -> CoalescingReport {
+        let mut issued = std::mem::take(&mut *self.issued.lock());
+        issued.sort_by(|a, b| a.path.cmp(&b.path).then(a.at.cmp(&b.at)));
+
+        let mut stacks = HashMap::<&[usize], StackStats>::new();
+        for (i, get) in issued.iter().enumerate() {
+            let stats = stacks.entry(get.stack.as_slice()).or_default();
+            stats.gets += 1;
+            // the earlier gets to the same object still within the window
+            let earlier = issued[..i]
+                .iter()
+                .rev()
+                .take_while(|e| e.path == get.path && get.at - e.at <= self.window);
+            let mut coalescable = false;
+            let mut overlap = 0;
+            for e in earlier {
+                if get.range.start <= e.range.end + self.gap
+                    && e.range.start <= get.range.end + self.gap
+                {
+                    coalescable = true;
+                    overlap = overlap.max(
+                        get.range
+                            .end
+                            .min(e.range.end)
+                            .saturating_sub(get.range.start.max(e.range.start)),
+                    );
+                }
+            }
+            if coalescable {
+                stats.coalescable += 1;
+                stats.coalescable_bytes += get.range.len() as u64;
+                stats.overlapping_bytes += overlap as u64;
+            }
+        }
+
+        let mut stacks = stacks
+            .into_iter()
+            .map(|(stack, stats)| (stacks::report(&stack, OWN_MODULES), stats))
+            .collect::<Vec<_>>();
+        stacks.sort_by_key(|(_, stats)| std::cmp::Reverse((stats.coalescable, stats.gets)));
+        CoalescingReport { stacks }
+    }
+}
+
+impl StoreHooks for CoalescingAnalyzer {
+    fn on_request(&self, op: ObjectStoreOp, path: &Path, range: Option<Range<usize>>) {
+        let (ObjectStoreOp::Get, Some(range)) = (op, range) else {
+            return;
+        };
+        let stack = stacks::capture();
+        self.issued.lock().push(Issued {
+            path: path.clone(),
+            range,
+            at: self.started.elapsed(),
+            stack,
+        });
+    }
+}
+
+pub struct CoalescingReport {
+    stacks: Vec<(Vec<String>, StackStats)>,
+}
+
+impl Display for CoalescingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self
+            .stacks
+            .iter()
+            .fold(StackStats::default(), |mut total, (_, s)| {
+                total.gets += s.gets;
+                total.coalescable += s.coalescable;
+                total.coalescable_bytes += s.coalescable_bytes;
+                total.overlapping_bytes += s.overlapping_bytes;
+                total
+            });
+        writeln!(f, "{}", summary(&total))?;
+        for (frames, s) in self.stacks.iter().take(REPORT_STACKS) {
+            if s.coalescable == 0 {
+                break;
+            }
+            writeln!(f, "\n{}", summary(s))?;
+            for frame in frames {
+                writeln!(f, "    {frame}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn summary(s: &StackStats) -> String {
+    format!(
+        "{} of {} ranged gets could have been coalesced, {} bytes in them, {} of those \
+         already fetched",
+        s.coalescable, s.gets, s.coalescable_bytes, s.overlapping_bytes
+    )
+}
@@ -0,0 +1,259 @@
+//! `--column-attribution`: which column of the dataset each get read, worked
+//! out from where lance laid out the columns' pages in its data files, so the
+//! columns driving the read volume of a wide schema stand out. Gets of the
+//! data files are labelled with the column they read the most bytes of and,
+//! when they start in one of its pages, the page, and the bytes of every
+//! phase are broken down by column in `{phase}_columns.txt`.
+//!
+//! Only files written in the v2 format have a layout to go by, gets of other
+//! objects (manifests, indices, v1 files) aren't attributed. Bytes of a data
+//! file outside any column's buffers, its footer and metadata, are counted as
+//! `metadata`.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use lance::datatypes::Schema;
+use lance::Dataset;
+use lance_file::v2::reader::FileReader;
+use lance_io::scheduler::{ScanScheduler, SchedulerConfig};
+use object_store::path::Path;
+use parking_lot::{Mutex, RwLock};
+
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+
+/// what the bytes of a data file outside all of its columns' buffers are
+const METADATA: &str = "metadata";
+
+/// A buffer of one column in a data file.
+struct Extent {
+    range: Range<u64>,
+    column: Arc<str>,
+    /// the page of the column it's part of, `None` for the column's own
+    /// buffers
+    page: Option<usize>,
+}
+
+struct FileLayout {
+    size: u64,
+    /// sorted by start, they don't overlap
+    extents: Vec<Extent>,
+}
+
+/// The bytes of a get read of one column.
+struct ColumnBytes {
+    column: Arc<str>,
+    /// the page the get started in
+    page: Option<usize>,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct ColumnReads {
+    /// gets that read this column the most
+    gets: u64,
+    bytes: u64,
+}
+
+/// Where the columns are in each data file of the dataset, and how many
+/// bytes of each were read this phase.
+#[derive(Default)]
+pub struct ColumnLayout {
+    files: RwLock<HashMap<Path, FileLayout>>,
+    reads: Mutex<HashMap<Arc<str>, ColumnReads>>,
+}
+
+impl ColumnLayout {
+    /// (re)reads the layout of every data file of `ds`, for after it's been
+    /// written to. the reads go through `ds`'s store, so this is done between
+    /// phases
+    pub async fn load(&self, ds: &Dataset) -> lance::Result<()> {
+        let scheduler = ScanScheduler::new(
+            Arc::new(ds.object_store().clone()),
+            SchedulerConfig::max_bandwidth(ds.object_store()),
+        );
+        let mut files = HashMap::new();
+        for fragment in ds.get_fragments() {
+            for file in &fragment.metadata().files {
+                let path = ds.data_dir().child(file.path.as_str());
+                if file.file_major_version < 2 {
+                    log::debug!("{path} has no column layout, it's a v1 file");
+                    continue;
+                }
+                let file_scheduler = scheduler.open_file(&path).await?;
+                let metadata = FileReader::read_all_metadata(&file_scheduler).await?;
+                let mut extents = vec![];
+                for (&field, &column) in file.fields.iter().zip(&file.column_indices) {
+                    let Some(column) = usize::try_from(column)
+                        .ok()
+                        .and_then(|column| metadata.column_metadatas.get(column))
+                    else {
+                        continue;
+                    };
+                    let name: Arc<str> = column_name(ds.schema(), field).into();
+                    let buffers = column.buffer_offsets.iter().zip(&column.buffer_sizes);
+                    extents.extend(buffers.map(|(&offset, &size)| Extent {
+                        range: offset..offset + size,
+                        column: name.clone(),
+                        page: None,
+                    }));
+                    for (i, page) in column.pages.iter().enumerate() {
+                        let buffers = page.buffer_offsets.iter().zip(&page.buffer_sizes);
+                        extents.extend(buffers.map(|(&offset, &size)| Extent {
+                            range: offset..offset + size,
+                            column: name.clone(),
+                            page: Some(i),
+                        }));
+                    }
+                }
+                extents.retain(|extent| !extent.range.is_empty());
+                extents.sort_by_key(|extent| extent.range.start);
+                let size = file_scheduler.reader().size().await? as u64;
+                files.insert(path, FileLayout { size, extents });
+            }
+        }
+        log::info!("read the column layout of {} data files", files.len());
+        *self.files.write() = files;
+        Ok(())
+    }
+
+    /// the columns a get of `range` of `path` read, most bytes first, `None`
+    /// unless it's a data file with a layout. gets without a range are taken
+    /// to be of the object's last `bytes`, which they are for both whole
+    /// objects and suffixes
+    fn attribute(
+        &self,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+    ) -> Option<Vec<ColumnBytes>> {
+        let files = self.files.read();
+        let file = files.get(path)?;
+        let range = match range {
+            Some(range) => range.start as u64..range.end as u64,
+            None => file.size.saturating_sub(bytes as u64)..file.size,
+        };
+        let first = file
+            .extents
+            .partition_point(|extent| extent.range.end <= range.start);
+        let last = file
+            .extents
+            .partition_point(|extent| extent.range.start < range.end);
+        let mut columns: Vec<ColumnBytes> = vec![];
+        let mut attributed = 0;
+        for extent in &file.extents[first..last.max(first)] {
+            let overlap = extent.range.end.min(range.end) - extent.range.start.max(range.start);
+            attributed += overlap;
+            match columns.iter_mut().find(|c| c.column == extent.column) {
+                Some(column) => column.bytes += overlap,
+                None => columns.push(ColumnBytes {
+                    column: extent.column.clone(),
+                    page: extent.page.filter(|_| extent.range.contains(&range.start)),
+                    bytes: overlap,
+                }),
+            }
+        }
+        let rest = (range.end - range.start).saturating_sub(attributed);
+        if rest > 0 {
+            columns.push(ColumnBytes {
+                column: METADATA.into(),
+                page: None,
+                bytes: rest,
+            });
+        }
+        columns.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+        Some(columns)
+    }
+
+    /// the bytes read of each column since the last report
+    pub fn take_report(&self) -> ColumnReport {
+        let mut columns: Vec<_> = std::mem::take(&mut *self.reads.lock())
+            .into_iter()
+            .map(|(column, reads)| (column, reads.gets, reads.bytes))
+            .collect();
+        columns.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+        ColumnReport { columns }
+    }
+}
+
+/// the top level column field `id` is part of
+fn column_name(schema: &Schema, id: i32) -> String {
+    schema
+        .field_ancestry_by_id(id)
+        .and_then(|ancestry| ancestry.first().map(|field| field.name.clone()))
+        .unwrap_or_else(|| format!("field_{id}"))
+}
+
+/// Hooks labelling the gets of data files with the column they read before
+/// firing `inner`. Time to first byte is fired without, there's no range to
+/// attribute it by yet.
+pub struct ColumnLabels(pub Arc<dyn StoreHooks>, pub Arc<ColumnLayout>);
+
+impl StoreHooks for ColumnLabels {
+    fn on_request(&self, op: ObjectStoreOp, path: &Path, range: Option<Range<usize>>) {
+        self.0.on_request(op, path, range);
+    }
+
+    fn on_first_byte(&self, op: ObjectStoreOp, path: &Path, ttfb: Duration, labels: &Labels) {
+        self.0.on_first_byte(op, path, ttfb, labels);
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        let columns = match (op, &outcome) {
+            (ObjectStoreOp::Get, Outcome::Success) => self.1.attribute(path, range.clone(), bytes),
+            _ => None,
+        };
+        let Some(top) = columns.as_ref().and_then(|columns| columns.first()) else {
+            self.0
+                .on_complete(op, path, range, bytes, duration, outcome, labels);
+            return;
+        };
+        {
+            let mut reads = self.1.reads.lock();
+            for column in columns.iter().flatten() {
+                reads.entry(column.column.clone()).or_default().bytes += column.bytes;
+            }
+            reads.entry(top.column.clone()).or_default().gets += 1;
+        }
+        let mut labels = labels.clone().str("column", top.column.as_ref());
+        if let Some(page) = top.page {
+            labels = labels.num("page", page as i64);
+        }
+        self.0
+            .on_complete(op, path, range, bytes, duration, outcome, &labels);
+    }
+}
+
+/// The bytes read of each column, most first.
+pub struct ColumnReport {
+    columns: Vec<(Arc<str>, u64, u64)>,
+}
+
+impl Display for ColumnReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: u64 = self.columns.iter().map(|(_, _, bytes)| bytes).sum();
+        writeln!(
+            f,
+            "{:<24} {:>8} {:>14} {:>7}",
+            "column", "gets", "bytes", "share"
+        )?;
+        for (column, gets, bytes) in &self.columns {
+            let share = 100.0 * *bytes as f64 / total.max(1) as f64;
+            writeln!(f, "{column:<24} {gets:>8} {bytes:>14} {share:>6.1}%")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! Lance's commit path, profiled as ops of its own: each attempt to commit a
+//! manifest, those that lost to a concurrent commit (and get retried), and
+//! finding the manifest of the latest or a given version. Writing and reading
+//! the manifests are requests like any other, this is the time around them.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use lance::io::ObjectStoreParams;
+use lance_io::object_store::ObjectStore;
+use lance_table::format::{Index, Manifest};
+use lance_table::io::commit::{
+    commit_handler_from_url, CommitError, CommitHandler, ManifestLocation, ManifestNamingScheme,
+    ManifestWriter,
+};
+use object_store::path::Path;
+
+use crate::cli::Args;
+use crate::profiling::ProfiledOp;
+use crate::registry::registry;
+
+struct CommitOp(&'static str);
+
+impl ProfiledOp for CommitOp {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn value_type(&self) -> (&'static str, &'static str) {
+        ("latency", "nanoseconds")
+    }
+}
+
+const COMMIT: CommitOp = CommitOp("commit");
+/// commits that found another one had taken the version first
+const COMMIT_CONFLICT: CommitOp = CommitOp("commit_conflict");
+const RESOLVE_MANIFEST: CommitOp = CommitOp("resolve_manifest");
+
+/// lance's commit handler for `uri`, recording how long each call takes
+pub async fn commit_handler(args: &Args, uri: &str) -> Arc<dyn CommitHandler> {
+    let params = ObjectStoreParams {
+        storage_options: args.storage_options(),
+        ..Default::default()
+    };
+    let inner = commit_handler_from_url(uri, &Some(params)).await.unwrap();
+    Arc::new(ProfiledCommitHandler { inner })
+}
+
+#[derive(Debug)]
+struct ProfiledCommitHandler {
+    inner: Arc<dyn CommitHandler>,
+}
+
+fn record(op: &CommitOp, started: Instant) {
+    registry().record(op, started.elapsed().as_nanos() as i64);
+}
+
+#[async_trait]
+impl CommitHandler for ProfiledCommitHandler {
+    async fn resolve_latest_location(
+        &self,
+        base_path: &Path,
+        object_store: &ObjectStore,
+    ) -> lance::Result<ManifestLocation> {
+        let started = Instant::now();
+        let location = self
+            .inner
+            .resolve_latest_location(base_path, object_store)
+            .await;
+        record(&RESOLVE_MANIFEST, started);
+        location
+    }
+
+    async fn resolve_version_location(
+        &self,
+        base_path: &Path,
+        version: u64,
+        object_store: &dyn object_store::ObjectStore,
+    ) -> lance::Result<ManifestLocation> {
+        let started = Instant::now();
+        let location = self
+            .inner
+            .resolve_version_location(base_path, version, object_store)
+            .await;
+        record(&RESOLVE_MANIFEST, started);
+        location
+    }
+
+    async fn commit(
+        &self,
+        manifest: &mut Manifest,
+        indices: Option<Vec<Index>>,
+        base_path: &Path,
+        object_store: &ObjectStore,
+        manifest_writer: ManifestWriter,
+        naming_scheme: ManifestNamingScheme,
+    ) -> Result<ManifestLocation, CommitError> {
+        let started = Instant::now();
+        let result = self
+            .inner
+            .commit(
+                manifest,
+                indices,
+                base_path,
+                object_store,
+                manifest_writer,
+                naming_scheme,
+            )
+            .await;
+        match &result {
+            Err(CommitError::CommitConflict) => record(&COMMIT_CONFLICT, started),
+            _ => record(&COMMIT, started),
+        }
+        result
+    }
+
+    async fn delete(&self, base_path: &Path) -> lance::Result<()> {
+        self.inner.delete(base_path).await
+    }
+}
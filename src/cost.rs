@@ -0,0 +1,132 @@
+//! What each phase's requests would cost, priced per request and per GB read
+//! out of the store. The defaults are S3 standard's list prices in us-east-1;
+//! `--price-per-get`, `--price-per-put` and `--price-per-gb-egress` set others.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::cli::Args;
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+use crate::summary::{PhaseSummary, SummaryFormat};
+
+const GB: f64 = 1e9;
+
+/// Dollars per request and per GB transferred.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceModel {
+    /// gets and heads
+    pub per_get: f64,
+    /// puts, copies, renames and listings
+    pub per_put: f64,
+    pub per_gb_egress: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Cost {
+    gets: u64,
+    puts: u64,
+    egress_bytes: u64,
+    requests: f64,
+    egress: f64,
+}
+
+impl PriceModel {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            per_get: args.price_per_get,
+            per_put: args.price_per_put,
+            per_gb_egress: args.price_per_gb_egress,
+        }
+    }
+
+    /// deletes are free, and only what's read counts as egress
+    fn cost(&self, summary: &PhaseSummary) -> Cost {
+        let mut cost = Cost::default();
+        for (op, s) in summary {
+            match op {
+                ObjectStoreOp::Get | ObjectStoreOp::Head => cost.gets += s.count,
+                ObjectStoreOp::Put
+                | ObjectStoreOp::Copy
+                | ObjectStoreOp::Rename
+                | ObjectStoreOp::List => cost.puts += s.count,
+                ObjectStoreOp::Delete => {}
+            }
+            if *op == ObjectStoreOp::Get {
+                cost.egress_bytes += s.bytes;
+            }
+        }
+        cost.requests = cost.gets as f64 * self.per_get + cost.puts as f64 * self.per_put;
+        cost.egress = cost.egress_bytes as f64 / GB * self.per_gb_egress;
+        cost
+    }
+}
+
+impl Cost {
+    fn total(&self) -> f64 {
+        self.requests + self.egress
+    }
+
+    fn add(&mut self, other: &Cost) {
+        self.gets += other.gets;
+        self.puts += other.puts;
+        self.egress_bytes += other.egress_bytes;
+        self.requests += other.requests;
+        self.egress += other.egress;
+    }
+}
+
+/// the estimated cost of each phase in dollars, and of the whole run
+pub fn render(
+    format: SummaryFormat,
+    prices: &PriceModel,
+    phases: &BTreeMap<Phase, PhaseSummary>,
+) -> String {
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => {
+            out.push_str("phase,gets,puts,egress_gb,request_usd,egress_usd,total_usd\n")
+        }
+        SummaryFormat::Text => writeln!(
+            out,
+            "{:<14} {:>10} {:>10} {:>10} {:>12} {:>12} {:>12}",
+            "phase", "gets", "puts", "egress_gb", "request_usd", "egress_usd", "total_usd"
+        )
+        .unwrap(),
+    }
+    let mut total = Cost::default();
+    let costs = phases
+        .iter()
+        .map(|(phase, summary)| (phase.as_str(), prices.cost(summary)))
+        .collect::<Vec<_>>();
+    for (_, cost) in &costs {
+        total.add(cost);
+    }
+    for (name, c) in costs.iter().chain([("total", total)].iter()) {
+        let egress_gb = c.egress_bytes as f64 / GB;
+        match format {
+            SummaryFormat::Csv => writeln!(
+                out,
+                "{name},{},{},{egress_gb:.6},{:.6},{:.6},{:.6}",
+                c.gets,
+                c.puts,
+                c.requests,
+                c.egress,
+                c.total()
+            ),
+            SummaryFormat::Text => writeln!(
+                out,
+                "{:<14} {:>10} {:>10} {:>10.3} {:>12.6} {:>12.6} {:>12.6}",
+                name,
+                c.gets,
+                c.puts,
+                egress_gb,
+                c.requests,
+                c.egress,
+                c.total()
+            ),
+        }
+        .unwrap();
+    }
+    out
+}
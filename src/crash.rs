@@ -0,0 +1,43 @@
+//! Writing out the profiles of the phase that was running when something
+//! panicked, since a run lance panicked part way through (an index build, say)
+//! is the one most worth a look.
+
+use parking_lot::Mutex;
+
+use crate::fork::PerProcess;
+use crate::registry::registry;
+use crate::run::{Phase, RunDir};
+
+/// the phase being recorded, and where its profiles go. a forked child that
+/// panics has no phase of its own in flight, it doesn't write the parent's
+static IN_FLIGHT: PerProcess<Mutex<Option<(RunDir, Phase)>>> = PerProcess::new(Mutex::default);
+
+/// chains onto the current panic hook, so the panic gets reported first
+pub fn install_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        write_in_flight();
+    }));
+}
+
+pub fn set_in_flight(dir: &RunDir, phase: Phase) {
+    *IN_FLIGHT.get().lock() = Some((dir.clone(), phase));
+}
+
+pub fn clear_in_flight() {
+    IN_FLIGHT.get().lock().take();
+}
+
+/// writes the profiles recorded so far by the phase in flight, if it hasn't
+/// been already
+pub fn write_in_flight() {
+    let Some((dir, phase)) = IN_FLIGHT.get().lock().take() else {
+        return;
+    };
+    log::error!(
+        "panicked during the {} phase, writing the profiles it recorded so far",
+        phase.as_str()
+    );
+    registry().flush_all(&dir, phase);
+}
@@ -0,0 +1,367 @@
+use std::iter::{repeat, repeat_with};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::error::Result;
+use arrow_array::{
+    ArrayRef, FixedSizeListArray, Float32Array, Int64Array, Int8Array, LargeBinaryArray, ListArray,
+    RecordBatch, StringArray, TimestampMicrosecondArray, UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use clap::ValueEnum;
+use lance_arrow::FixedSizeListArrayExt;
+use lance_linalg::distance::MetricType;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::fork::PerProcess;
+use crate::index::metric_type;
+
+/// `ts` of the first row, 2024-01-01T00:00:00Z
+const TS_START_MICROS: i64 = 1_704_067_200_000_000;
+/// rows are a second apart
+const TS_STEP_MICROS: i64 = 1_000_000;
+
+/// rows generated, and the time spent generating them, since the last
+/// [`take_generated`]
+static GENERATED_ROWS: PerProcess<AtomicU64> = PerProcess::new(AtomicU64::default);
+static GENERATION_US: PerProcess<AtomicU64> = PerProcess::new(AtomicU64::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorType {
+    Float32,
+    /// already quantized vectors, e.g. from an embedding model with int8 output
+    Int8,
+    /// packed binary vectors, `--vector-dims` bits per vector, compared with
+    /// hamming distance
+    Binary,
+}
+
+// for the PPROF_DEV_VECTOR_TYPE override
+impl FromStr for VectorType {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+impl VectorType {
+    fn data_type(&self) -> DataType {
+        match self {
+            VectorType::Float32 => DataType::Float32,
+            VectorType::Int8 => DataType::Int8,
+            VectorType::Binary => DataType::UInt8,
+        }
+    }
+
+    /// number of values per vector for `dims` dimensions
+    pub fn list_size(&self, dims: i32) -> i32 {
+        match self {
+            VectorType::Binary => {
+                assert!(dims % 8 == 0, "binary vector dims must be a multiple of 8");
+                dims / 8
+            }
+            _ => dims,
+        }
+    }
+
+    fn values<R: Rng>(&self, n: usize, rng: &mut R) -> ArrayRef {
+        match self {
+            VectorType::Float32 => Arc::new(Float32Array::from_iter_values(
+                repeat_with(|| rng.gen::<f32>()).take(n),
+            )),
+            VectorType::Int8 => Arc::new(Int8Array::from_iter_values(
+                repeat_with(|| rng.gen::<i8>()).take(n),
+            )),
+            VectorType::Binary => Arc::new(UInt8Array::from_iter_values(
+                repeat_with(|| rng.gen::<u8>()).take(n),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeDistribution {
+    /// every value is exactly the mean size
+    Fixed,
+    /// between 0 and twice the mean size
+    Uniform,
+    /// log-normal around the mean size, mostly small values with a long tail
+    /// of large ones
+    LogNormal,
+}
+
+// for the PPROF_DEV_*_DISTRIBUTION overrides
+impl FromStr for SizeDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+impl SizeDistribution {
+    fn sample<R: Rng>(&self, mean: usize, rng: &mut R) -> usize {
+        match self {
+            SizeDistribution::Fixed => mean,
+            SizeDistribution::Uniform => rng.gen_range(0..=2 * mean),
+            SizeDistribution::LogNormal => {
+                // sigma of 1, mu picked so the mean comes out at `mean`
+                let mu = (mean.max(1) as f64).ln() - 0.5;
+                LogNormal::new(mu, 1.0).unwrap().sample(rng) as usize
+            }
+        }
+    }
+}
+
+/// Another vector column beside `vector`, e.g. `image=512` for image
+/// embeddings next to text ones. It has the same vector type, but dims and an
+/// index of its own, and isn't a multivector.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExtraVector {
+    pub name: String,
+    pub dims: i32,
+}
+
+/// the columns an extra vector column can't be named after
+const RESERVED_COLUMNS: [&str; 5] = ["id", "ts", "vector", "blob", "label"];
+
+// for `--extra-vector` and the PPROF_DEV_EXTRA_VECTOR override
+impl FromStr for ExtraVector {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, dims) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=DIMS, got {s:?}"))?;
+        if RESERVED_COLUMNS.contains(&name) {
+            return Err(format!("{name} is already a column"));
+        }
+        let dims = dims
+            .parse()
+            .map_err(|e| format!("invalid dims {dims:?}: {e}"))?;
+        Ok(Self {
+            name: name.to_string(),
+            dims,
+        })
+    }
+}
+
+fn vector_item_type(args: &Args, dims: i32) -> DataType {
+    DataType::FixedSizeList(
+        Arc::new(Field::new("item", args.vector_type.data_type(), true)),
+        args.vector_type.list_size(dims),
+    )
+}
+
+/// the values of `n` random vectors of `dims`, of unit length when searched
+/// by cosine
+fn vector_values<R: Rng>(args: &Args, dims: i32, n: usize, rng: &mut R) -> ArrayRef {
+    let list_size = args.vector_type.list_size(dims) as usize;
+    if args.vector_type != VectorType::Float32 || metric_type(args) != MetricType::Cosine {
+        return args.vector_type.values(list_size * n, rng);
+    }
+
+    let mut values = repeat_with(|| rng.gen::<f32>())
+        .take(list_size * n)
+        .collect::<Vec<_>>();
+    for vector in values.chunks_mut(list_size) {
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    Arc::new(Float32Array::from(values))
+}
+
+/// `n` random vectors of `dims`
+fn vectors<R: Rng>(args: &Args, dims: i32, n: usize, rng: &mut R) -> FixedSizeListArray {
+    let list_size = args.vector_type.list_size(dims);
+    let values = vector_values(args, dims, n, rng);
+    <FixedSizeListArray as FixedSizeListArrayExt>::try_new_from_values(values, list_size).unwrap()
+}
+
+pub fn create_schema(args: &Args) -> Schema {
+    let vector_type = match args.multivector {
+        Some(_) => DataType::List(Arc::new(Field::new(
+            "item",
+            vector_item_type(args, args.vector_dims),
+            true,
+        ))),
+        None => vector_item_type(args, args.vector_dims),
+    };
+    let nullable = args.null_fraction > 0.0;
+    let mut fields = vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            false,
+        ),
+        Field::new("vector", vector_type, nullable),
+    ];
+    for extra in &args.extra_vector {
+        fields.push(Field::new(
+            &extra.name,
+            vector_item_type(args, extra.dims),
+            nullable,
+        ));
+    }
+    if args.blob_size.is_some() {
+        fields.push(Field::new("blob", DataType::LargeBinary, nullable));
+    }
+    if args.string_cardinality.is_some() {
+        fields.push(Field::new("label", DataType::Utf8, nullable));
+    }
+
+    Schema::new(fields)
+}
+
+/// generates the rows with ids in `ids`
+pub fn generate_data(args: &Args, schema: Arc<Schema>, ids: Range<u64>) -> Result<RecordBatch> {
+    let started = Instant::now();
+    let mut rng = rand::thread_rng();
+    let rows = (ids.end - ids.start) as usize;
+    let nulls = nulls(args, rows, &mut rng);
+
+    let vectors: ArrayRef = match args.multivector {
+        Some(per_row) => {
+            let item = Arc::new(Field::new(
+                "item",
+                vector_item_type(args, args.vector_dims),
+                true,
+            ));
+            Arc::new(ListArray::try_new(
+                item,
+                OffsetBuffer::from_lengths(repeat(per_row).take(rows)),
+                Arc::new(vectors(args, args.vector_dims, per_row * rows, &mut rng)),
+                nulls.clone(),
+            )?)
+        }
+        None => nullable_vectors(args, args.vector_dims, rows, &nulls, &mut rng)?,
+    };
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(
+            ids.clone().map(|id| id as i64),
+        )),
+        Arc::new(TimestampMicrosecondArray::from_iter_values(
+            ids.map(row_timestamp),
+        )),
+        vectors,
+    ];
+    for extra in &args.extra_vector {
+        columns.push(nullable_vectors(args, extra.dims, rows, &nulls, &mut rng)?);
+    }
+    if let Some(mean) = args.blob_size {
+        let blobs = (0..rows)
+            .map(|i| {
+                if nulls.as_ref().is_some_and(|nulls| nulls.is_null(i)) {
+                    return None;
+                }
+                let len = args.blob_size_distribution.sample(mean, &mut rng);
+                Some(
+                    repeat_with(|| rng.gen::<u8>())
+                        .take(len)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        columns.push(Arc::new(LargeBinaryArray::from_iter(blobs)));
+    }
+    if let Some(cardinality) = args.string_cardinality {
+        let distinct = (0..cardinality.max(1))
+            .map(|_| {
+                let len = args
+                    .string_length_distribution
+                    .sample(args.string_length, &mut rng);
+                (&mut rng)
+                    .sample_iter(Alphanumeric)
+                    .take(len)
+                    .map(char::from)
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        let labels = (0..rows)
+            .map(|i| {
+                if nulls.as_ref().is_some_and(|nulls| nulls.is_null(i)) {
+                    return None;
+                }
+                Some(distinct[rng.gen_range(0..distinct.len())].as_str())
+            })
+            .collect::<StringArray>();
+        columns.push(Arc::new(labels));
+    }
+
+    let batch = RecordBatch::try_new(schema, columns)?;
+    GENERATED_ROWS
+        .get()
+        .fetch_add(rows as u64, Ordering::Relaxed);
+    GENERATION_US
+        .get()
+        .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+    Ok(batch)
+}
+
+/// `rows` random vectors of `dims` with the rows' `nulls`
+fn nullable_vectors<R: Rng>(
+    args: &Args,
+    dims: i32,
+    rows: usize,
+    nulls: &Option<NullBuffer>,
+    rng: &mut R,
+) -> Result<ArrayRef> {
+    let (item, size, values, _) = vectors(args, dims, rows, rng).into_parts();
+    Ok(Arc::new(FixedSizeListArray::try_new(
+        item,
+        size,
+        values,
+        nulls.clone(),
+    )?))
+}
+
+/// rows generated and the time spent generating them since the last call
+pub fn take_generated() -> (u64, Duration) {
+    let rows = GENERATED_ROWS.get().swap(0, Ordering::Relaxed);
+    let us = GENERATION_US.get().swap(0, Ordering::Relaxed);
+    (rows, Duration::from_micros(us))
+}
+
+/// the `ts` of a row, in microseconds since the epoch. increases with the row
+/// id so time ranges map to contiguous rows, like an append-only event table
+pub fn row_timestamp(row: u64) -> i64 {
+    TS_START_MICROS + row as i64 * TS_STEP_MICROS
+}
+
+/// validity for the nullable columns, `--null-fraction` of rows are null
+fn nulls<R: Rng>(args: &Args, rows: usize, rng: &mut R) -> Option<NullBuffer> {
+    if args.null_fraction <= 0.0 {
+        return None;
+    }
+    let valid = (0..rows)
+        .map(|_| !rng.gen_bool(args.null_fraction))
+        .collect::<Vec<_>>();
+    Some(NullBuffer::from(valid))
+}
+
+/// a random query of the same type as the generated vectors, for multivector
+/// columns this is a set of `--multivector` query vectors
+pub fn query_vector<R: Rng>(args: &Args, rng: &mut R) -> ArrayRef {
+    match args.multivector {
+        Some(per_row) => Arc::new(vectors(args, args.vector_dims, per_row, rng)),
+        None => vector_values(args, args.vector_dims, 1, rng),
+    }
+}
+
+/// a random query for the `--extra-vector` column `extra`
+pub fn extra_query_vector<R: Rng>(args: &Args, extra: &ExtraVector, rng: &mut R) -> ArrayRef {
+    vector_values(args, extra.dims, 1, rng)
+}
@@ -0,0 +1,75 @@
+//! Which rows/query vectors each iteration of a workload picks. Skewed access
+//! is what makes caching and range coalescing interesting to profile.
+
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use rand::Rng;
+use rand_distr::{Distribution, Zipf};
+use serde::Serialize;
+
+use crate::cli::Args;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessDistribution {
+    Uniform,
+    /// key `i` is picked with probability proportional to `1 / (i + 1)^s`
+    Zipfian,
+    /// `--hotspot-probability` of picks land on the first
+    /// `--hotspot-fraction` of keys, the rest are uniform over the others
+    Hotspot,
+}
+
+// for the PPROF_DEV_DISTRIBUTION override
+impl FromStr for AccessDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+/// Picks keys in `0..n` following the configured distribution. the hottest
+/// keys are always the lowest ones.
+pub enum KeySampler {
+    Uniform { n: u64 },
+    Zipfian { zipf: Zipf<f64> },
+    Hotspot { n: u64, hot: u64, probability: f64 },
+}
+
+impl KeySampler {
+    pub fn new(args: &Args, n: u64) -> Self {
+        assert!(n > 0, "can't sample keys from an empty set");
+        match args.distribution {
+            AccessDistribution::Uniform => KeySampler::Uniform { n },
+            AccessDistribution::Zipfian => KeySampler::Zipfian {
+                zipf: Zipf::new(n, args.zipf_exponent).unwrap(),
+            },
+            AccessDistribution::Hotspot => KeySampler::Hotspot {
+                n,
+                hot: ((n as f64 * args.hotspot_fraction) as u64).clamp(1, n),
+                probability: args.hotspot_probability,
+            },
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> u64 {
+        match self {
+            KeySampler::Uniform { n } => rng.gen_range(0..*n),
+            // zipf samples ranks starting at 1
+            KeySampler::Zipfian { zipf } => zipf.sample(rng) as u64 - 1,
+            KeySampler::Hotspot {
+                n,
+                hot,
+                probability,
+            } => {
+                if *hot == *n || rng.gen_bool(*probability) {
+                    rng.gen_range(0..*hot)
+                } else {
+                    rng.gen_range(*hot..*n)
+                }
+            }
+        }
+    }
+}
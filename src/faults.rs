@@ -0,0 +1,143 @@
+//! A layer failing a share of requests before they reach the store
+//! underneath, for the `faults` layer of `--store-layers`, to see how lance's
+//! retries and error paths behave and what they cost in requests. Streamed
+//! listings are never failed.
+
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use object_store::path::Path;
+use object_store::{
+    Error, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult, Result,
+};
+use rand::Rng;
+
+use crate::op::ObjectStoreOp;
+
+const STORE: &str = "faults";
+
+#[derive(Debug)]
+pub struct FaultyStore {
+    inner: Arc<dyn ObjectStore>,
+    error_rate: f64,
+    /// the ops that can fail, all of them if empty
+    ops: Vec<ObjectStoreOp>,
+}
+
+impl FaultyStore {
+    /// fails `error_rate` of the requests of `ops`
+    pub fn new(inner: Arc<dyn ObjectStore>, error_rate: f64, ops: Vec<ObjectStoreOp>) -> Self {
+        Self {
+            inner,
+            error_rate,
+            ops,
+        }
+    }
+
+    /// an injected error, for this share of requests
+    fn fail(&self, op: ObjectStoreOp, location: &Path) -> Result<()> {
+        let applies = self.ops.is_empty() || self.ops.contains(&op);
+        if applies && rand::thread_rng().gen_bool(self.error_rate) {
+            return Err(Error::Generic {
+                store: STORE,
+                source: format!("injected {op} fault for {location}").into(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Display for FaultyStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FaultyStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for FaultyStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.fail(ObjectStoreOp::Put, location)?;
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.fail(ObjectStoreOp::Put, location)?;
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        self.fail(ObjectStoreOp::Get, location)?;
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.fail(ObjectStoreOp::Get, location)?;
+        self.inner.get_range(location, range).await
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.fail(ObjectStoreOp::Get, location)?;
+        self.inner.get_ranges(location, ranges).await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.fail(ObjectStoreOp::Head, location)?;
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.fail(ObjectStoreOp::Delete, location)?;
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list_with_offset(prefix, offset)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.fail(ObjectStoreOp::List, prefix.unwrap_or(&Path::default()))?;
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.fail(ObjectStoreOp::Copy, from)?;
+        self.inner.copy(from, to).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.fail(ObjectStoreOp::Rename, from)?;
+        self.inner.rename(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.fail(ObjectStoreOp::Copy, from)?;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.fail(ObjectStoreOp::Rename, from)?;
+        self.inner.rename_if_not_exists(from, to).await
+    }
+}
@@ -0,0 +1,99 @@
+//! Flamegraphs rendered from the pprof profiles written to a run directory.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use pprof::protos::{Message, Profile};
+
+pub fn read_profile(path: &Path) -> io::Result<Profile> {
+    Profile::parse_from_bytes(&fs::read(path)?).map_err(io::Error::other)
+}
+
+/// folds the samples of `profile` into `root;..;leaf value` lines, one per
+/// distinct stack, using the values of its first sample type
+pub fn collapse(profile: &Profile) -> Vec<String> {
+    let strings = &profile.string_table;
+    let functions: HashMap<u64, &str> = profile
+        .function
+        .iter()
+        .map(|f| (f.id, strings[f.name as usize].as_str()))
+        .collect();
+    let locations: HashMap<u64, Vec<&str>> = profile
+        .location
+        .iter()
+        .map(|location| {
+            // the last line is the caller the preceding ones were inlined into
+            let frames = location
+                .line
+                .iter()
+                .rev()
+                .filter_map(|line| functions.get(&line.function_id).copied())
+                .collect();
+            (location.id, frames)
+        })
+        .collect();
+
+    let mut stacks: BTreeMap<String, i64> = BTreeMap::new();
+    for sample in profile.sample.iter() {
+        let Some(value) = sample.value.first().copied() else {
+            continue;
+        };
+        // location ids are leaf first
+        let stack = sample
+            .location_id
+            .iter()
+            .rev()
+            .filter_map(|id| locations.get(id))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>()
+            .join(";");
+        if !stack.is_empty() && value > 0 {
+            *stacks.entry(stack).or_default() += value;
+        }
+    }
+
+    stacks
+        .into_iter()
+        .map(|(stack, value)| format!("{stack} {value}"))
+        .collect()
+}
+
+pub fn render_svg(profile: &Profile, title: &str) -> io::Result<Vec<u8>> {
+    let lines = collapse(profile);
+    let mut options = inferno::flamegraph::Options::default();
+    options.title = title.to_string();
+    if let Some(sample_type) = profile.sample_type.first() {
+        options.count_name = profile.string_table[sample_type.field_type as usize].clone();
+    }
+
+    let mut svg = vec![];
+    inferno::flamegraph::from_lines(&mut options, lines.iter().map(String::as_str), &mut svg)?;
+    Ok(svg)
+}
+
+/// a flamegraph of `after`, colored by how much each frame grew (red) or shrank
+/// (blue) since `before`
+pub fn render_diff_svg(before: &Profile, after: &Profile, title: &str) -> io::Result<Vec<u8>> {
+    let (before, after_lines) = (collapse(before).join("\n"), collapse(after).join("\n"));
+    let mut folded = vec![];
+    inferno::differential::from_readers(
+        inferno::differential::Options::default(),
+        before.as_bytes(),
+        after_lines.as_bytes(),
+        &mut folded,
+    )?;
+    let folded = String::from_utf8(folded).map_err(io::Error::other)?;
+
+    let mut options = inferno::flamegraph::Options::default();
+    options.title = title.to_string();
+    if let Some(sample_type) = after.sample_type.first() {
+        options.count_name = after.string_table[sample_type.field_type as usize].clone();
+    }
+    let mut svg = vec![];
+    inferno::flamegraph::from_lines(&mut options, folded.lines(), &mut svg)?;
+    Ok(svg)
+}
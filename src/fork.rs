@@ -0,0 +1,145 @@
+//! Process wide state that survives forks. A process forked without exec'ing,
+//! by a test framework that forks per test say, starts with a copy of
+//! everything the parent had recorded, including locks held by threads that
+//! didn't make it across. Writing that out again would clobber the parent's
+//! profiles, and taking the locks can hang, so each process gets state of its
+//! own instead: the first use in a child starts over with fresh state and
+//! leaves the parent's copy alone.
+//!
+//! The same goes for the stores already handed to lance. The profiles' sinks
+//! keep their state per process, so a child's profiles are of its own
+//! requests, and the other hooks are only called in the process that made
+//! the store, the collectors behind them being the parent's.
+
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use object_store::path::Path;
+
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+
+struct Owned<T> {
+    pid: u32,
+    value: T,
+}
+
+/// A `T` for each process, created by `init` on first use in it. The `T`s of
+/// earlier processes are never freed, a dropped one only frees its own
+/// process's.
+pub struct PerProcess<T> {
+    current: AtomicPtr<Owned<T>>,
+    init: fn() -> T,
+    _value: PhantomData<T>,
+}
+
+impl<T> PerProcess<T> {
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            current: AtomicPtr::new(ptr::null_mut()),
+            init,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        let pid = std::process::id();
+        let current = self.current.load(Ordering::Acquire);
+        // SAFETY: only ever set from `Box::into_raw` below, and only freed
+        // by `drop`, which can't run while `self` is borrowed
+        if let Some(owned) = unsafe { current.as_ref() } {
+            if owned.pid == pid {
+                return &owned.value;
+            }
+        }
+        // the first use in this process, the parent's copy (if it's a fork)
+        // is left as it is, it may be locked for good
+        let fresh = Box::into_raw(Box::new(Owned {
+            pid,
+            value: (self.init)(),
+        }));
+        match self
+            .current
+            .compare_exchange(current, fresh, Ordering::AcqRel, Ordering::Acquire)
+        {
+            // SAFETY: as above
+            Ok(_) => unsafe { &(*fresh).value },
+            Err(winner) => {
+                // another thread of this process got there first, `fresh` was
+                // never shared
+                drop(unsafe { Box::from_raw(fresh) });
+                unsafe { &(*winner).value }
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for PerProcess<T> {
+    fn default() -> Self {
+        Self::new(T::default)
+    }
+}
+
+impl<T> Drop for PerProcess<T> {
+    fn drop(&mut self) {
+        let current = *self.current.get_mut();
+        // SAFETY: as in `get`, and nothing can be borrowing it any more
+        if let Some(owned) = unsafe { current.as_ref() } {
+            if owned.pid == std::process::id() {
+                drop(unsafe { Box::from_raw(current) });
+            }
+        }
+    }
+}
+
+/// Hooks only called in the process that made them, see the module docs.
+pub struct ProcessLocal {
+    hooks: Arc<dyn StoreHooks>,
+    pid: u32,
+}
+
+impl ProcessLocal {
+    pub fn new(hooks: Arc<dyn StoreHooks>) -> Self {
+        Self {
+            hooks,
+            pid: std::process::id(),
+        }
+    }
+
+    fn hooks(&self) -> Option<&dyn StoreHooks> {
+        (self.pid == std::process::id()).then_some(self.hooks.as_ref())
+    }
+}
+
+impl StoreHooks for ProcessLocal {
+    fn on_request(&self, op: ObjectStoreOp, path: &Path, range: Option<Range<usize>>) {
+        if let Some(hooks) = self.hooks() {
+            hooks.on_request(op, path, range);
+        }
+    }
+
+    fn on_first_byte(&self, op: ObjectStoreOp, path: &Path, ttfb: Duration, labels: &Labels) {
+        if let Some(hooks) = self.hooks() {
+            hooks.on_first_byte(op, path, ttfb, labels);
+        }
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        if let Some(hooks) = self.hooks() {
+            hooks.on_complete(op, path, range, bytes, duration, outcome, labels);
+        }
+    }
+}
@@ -0,0 +1,914 @@
+//! Callbacks fired around every object store request, for plugging in metrics
+//! systems of your own without forking the profiling wrapper.
+
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use object_store::path::Path;
+use object_store::{
+    Attribute, Attributes, GetOptions, GetRange, GetResult, GetResultPayload, ListResult,
+    MultipartUpload, ObjectMeta, ObjectStore, PutMode, PutMultipartOpts, PutOptions, PutPayload,
+    PutResult, Result, TagSet, UploadPart,
+};
+use tracing::Instrument;
+
+use crate::clock::{Clock, MonotonicClock, Started};
+use crate::op::ObjectStoreOp;
+
+/// How a request finished.
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome<'a> {
+    Success,
+    Error(&'a object_store::Error),
+    /// a multipart upload was aborted, or a listing or streamed get was
+    /// dropped before it was read to the end
+    Aborted,
+}
+
+/// Labels describing a request beyond its op and path, such as how many
+/// entries a listing returned. They end up on the request's samples in the
+/// profiles and on its trace event.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Labels(Vec<(&'static str, LabelValue)>);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelValue {
+    Str(String),
+    Num(i64),
+}
+
+impl Labels {
+    pub const NONE: &'static Labels = &Labels(Vec::new());
+
+    pub fn str(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.0.push((key, LabelValue::Str(value.into())));
+        self
+    }
+
+    pub fn num(mut self, key: &'static str, value: i64) -> Self {
+        self.0.push((key, LabelValue::Num(value)));
+        self
+    }
+
+    /// these labels followed by `other`'s
+    pub fn with(mut self, other: &Labels) -> Self {
+        self.0.extend(other.0.iter().cloned());
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(&'static str, LabelValue)> {
+        self.0.iter()
+    }
+}
+
+impl Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            match value {
+                LabelValue::Str(value) => write!(f, "{sep}{key}={value}")?,
+                LabelValue::Num(value) => write!(f, "{sep}{key}={value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait StoreHooks: Send + Sync + 'static {
+    /// called before the request is sent. `range` is set for ranged gets
+    fn on_request(&self, _op: ObjectStoreOp, _path: &Path, _range: Option<Range<usize>>) {}
+
+    /// called when the first chunk of a streamed get arrives, `ttfb` after the
+    /// request was sent. `on_complete` follows once the stream has been read
+    fn on_first_byte(&self, _op: ObjectStoreOp, _path: &Path, _ttfb: Duration, _labels: &Labels) {}
+
+    /// called once the request has finished, successfully or not. `range` is
+    /// the one `on_request` was called with
+    #[allow(clippy::too_many_arguments)]
+    fn on_complete(
+        &self,
+        _op: ObjectStoreOp,
+        _path: &Path,
+        _range: Option<Range<usize>>,
+        _bytes: usize,
+        _duration: Duration,
+        _outcome: Outcome<'_>,
+        _labels: &Labels,
+    ) {
+    }
+}
+
+/// Several hooks fired one after the other.
+pub struct HookSet(pub Vec<Arc<dyn StoreHooks>>);
+
+impl StoreHooks for HookSet {
+    fn on_request(&self, op: ObjectStoreOp, path: &Path, range: Option<Range<usize>>) {
+        for hooks in &self.0 {
+            hooks.on_request(op, path, range.clone());
+        }
+    }
+
+    fn on_first_byte(&self, op: ObjectStoreOp, path: &Path, ttfb: Duration, labels: &Labels) {
+        for hooks in &self.0 {
+            hooks.on_first_byte(op, path, ttfb, labels);
+        }
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        for hooks in &self.0 {
+            hooks.on_complete(op, path, range.clone(), bytes, duration, outcome, labels);
+        }
+    }
+}
+
+/// Hooks that log every request, enabled with `--log-requests`.
+pub struct LoggingHooks;
+
+impl StoreHooks for LoggingHooks {
+    fn on_request(&self, op: ObjectStoreOp, path: &Path, range: Option<Range<usize>>) {
+        match range {
+            Some(range) => log::info!("{op} {path} {range:?}"),
+            None => log::info!("{op} {path}"),
+        }
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        _range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        log::info!("{op} {path} finished in {duration:?} ({bytes} bytes): {outcome:?} {labels}");
+    }
+}
+
+/// Object store that fires `hooks` around every call to `inner`, and runs each
+/// call in an `object_store` tracing span.
+pub struct HookedObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    hooks: Arc<dyn StoreHooks>,
+    /// what the requests are timed by
+    clock: Arc<dyn Clock>,
+}
+
+impl HookedObjectStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, hooks: Arc<dyn StoreHooks>) -> Self {
+        Self {
+            inner,
+            hooks,
+            clock: Arc::new(MonotonicClock::default()),
+        }
+    }
+
+    /// times the requests by `clock` instead of the real time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn complete<T>(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        started: Started,
+        result: &Result<T>,
+        bytes: impl FnOnce(&T) -> usize,
+    ) {
+        self.complete_labelled(op, path, started, result, bytes, Labels::NONE);
+    }
+
+    fn complete_labelled<T>(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        started: Started,
+        result: &Result<T>,
+        bytes: impl FnOnce(&T) -> usize,
+        labels: &Labels,
+    ) {
+        let (bytes, outcome) = match result {
+            Ok(value) => (bytes(value), Outcome::Success),
+            Err(e) => (0, Outcome::Error(e)),
+        };
+        self.hooks
+            .on_complete(op, path, None, bytes, started.elapsed(), outcome, labels);
+    }
+
+    fn hooked_stream<'a, T: 'a>(
+        &self,
+        op: ObjectStoreOp,
+        path: Path,
+        kind: &'static str,
+        inner: BoxStream<'a, Result<T>>,
+    ) -> BoxStream<'a, Result<T>> {
+        HookedStream {
+            inner,
+            hooks: self.hooks.clone(),
+            op,
+            path,
+            started: Started::now(&self.clock),
+            kind,
+            entries: 0,
+            pages: 0,
+            waiting: false,
+            done: false,
+        }
+        .boxed()
+    }
+}
+
+/// the labels of a put made with these options. multipart uploads have no mode
+fn put_labels(mode: Option<&PutMode>, tags: &TagSet, attributes: &Attributes) -> Labels {
+    let mut labels = Labels::default();
+    if let Some(mode) = mode {
+        let mode = match mode {
+            PutMode::Overwrite => "overwrite",
+            PutMode::Create => "create",
+            PutMode::Update(_) => "update",
+        };
+        labels = labels.str("mode", mode);
+    }
+
+    let tags = tags
+        .encoded()
+        .split('&')
+        .filter(|tag| !tag.is_empty())
+        .count();
+    let mut names = attributes
+        .iter()
+        .map(|(attribute, _)| match attribute {
+            Attribute::ContentDisposition => "content_disposition",
+            Attribute::ContentEncoding => "content_encoding",
+            Attribute::ContentLanguage => "content_language",
+            Attribute::ContentType => "content_type",
+            Attribute::CacheControl => "cache_control",
+            Attribute::Metadata(_) => "metadata",
+            _ => "other",
+        })
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+    let attributes = if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(",")
+    };
+    labels
+        .num("tags", tags as i64)
+        .str("attributes", attributes)
+}
+
+/// the preconditions a get was made with, if any
+fn preconditions(options: &GetOptions) -> Option<String> {
+    let preconditions = [
+        ("if_match", options.if_match.is_some()),
+        ("if_none_match", options.if_none_match.is_some()),
+        ("if_modified_since", options.if_modified_since.is_some()),
+        ("if_unmodified_since", options.if_unmodified_since.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, set)| set.then_some(name))
+    .collect::<Vec<_>>();
+    (!preconditions.is_empty()).then(|| preconditions.join(","))
+}
+
+/// labels a conditional get with its preconditions and whether they held
+fn precondition_labels<T>(preconditions: &str, result: &Result<T>) -> Labels {
+    let outcome = match result {
+        Ok(_) => "passed",
+        Err(object_store::Error::NotModified { .. }) => "not_modified",
+        Err(object_store::Error::Precondition { .. }) => "failed",
+        Err(_) => "error",
+    };
+    Labels::default()
+        .str("precondition", preconditions)
+        .str("precondition_result", outcome)
+}
+
+fn request_span(op: ObjectStoreOp, path: &Path) -> tracing::Span {
+    tracing::info_span!("object_store", op = op.as_str(), path = %path)
+}
+
+impl Display for HookedObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HookedObjectStore({})", self.inner)
+    }
+}
+
+impl fmt::Debug for HookedObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookedObjectStore")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl ObjectStore for HookedObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.hooks.on_request(ObjectStoreOp::Put, location, None);
+        let bytes = payload.content_length();
+        let labels = put_labels(Some(&opts.mode), &opts.tags, &opts.attributes);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .put_opts(location, payload, opts)
+            .instrument(request_span(ObjectStoreOp::Put, location))
+            .await;
+        self.complete_labelled(
+            ObjectStoreOp::Put,
+            location,
+            started,
+            &result,
+            |_| bytes,
+            &labels,
+        );
+        result
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.hooks.on_request(ObjectStoreOp::Put, location, None);
+        let labels = put_labels(None, &opts.tags, &opts.attributes);
+        let started = Started::now(&self.clock);
+        match self
+            .inner
+            .put_multipart_opts(location, opts)
+            .instrument(request_span(ObjectStoreOp::Put, location))
+            .await
+        {
+            Ok(upload) => Ok(Box::new(HookedUpload {
+                inner: upload,
+                hooks: self.hooks.clone(),
+                path: location.clone(),
+                started,
+                bytes: 0,
+                labels,
+                done: false,
+            })),
+            Err(e) => {
+                self.hooks.on_complete(
+                    ObjectStoreOp::Put,
+                    location,
+                    None,
+                    0,
+                    started.elapsed(),
+                    Outcome::Error(&e),
+                    &labels,
+                );
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let op = if options.head {
+            ObjectStoreOp::Head
+        } else {
+            ObjectStoreOp::Get
+        };
+        let range = match &options.range {
+            Some(GetRange::Bounded(range)) => Some(range.clone()),
+            _ => None,
+        };
+        self.hooks.on_request(op, location, range.clone());
+        let preconditions = preconditions(&options);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .get_opts(location, options)
+            .instrument(request_span(op, location))
+            .await;
+        let labels = match &preconditions {
+            Some(preconditions) => precondition_labels(preconditions, &result),
+            None => Labels::default(),
+        };
+        let mut result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                self.hooks.on_complete(
+                    op,
+                    location,
+                    range,
+                    0,
+                    started.elapsed(),
+                    Outcome::Error(&e),
+                    &labels,
+                );
+                return Err(e);
+            }
+        };
+
+        let expected = result.range.end - result.range.start;
+        result.payload = match result.payload {
+            GetResultPayload::Stream(stream) => GetResultPayload::Stream(
+                GetStream {
+                    inner: stream,
+                    hooks: self.hooks.clone(),
+                    op,
+                    path: location.clone(),
+                    range,
+                    started,
+                    expected,
+                    read: 0,
+                    labels,
+                    first_byte: false,
+                    done: false,
+                }
+                .boxed(),
+            ),
+            // local files are read by the caller, there's no transfer to time
+            file @ GetResultPayload::File(..) => {
+                let elapsed = started.elapsed();
+                self.hooks.on_first_byte(op, location, elapsed, &labels);
+                self.hooks.on_complete(
+                    op,
+                    location,
+                    range,
+                    expected,
+                    elapsed,
+                    Outcome::Success,
+                    &labels,
+                );
+                file
+            }
+        };
+        Ok(result)
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.hooks
+            .on_request(ObjectStoreOp::Get, location, Some(range.clone()));
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .get_range(location, range.clone())
+            .instrument(request_span(ObjectStoreOp::Get, location))
+            .await;
+        let (bytes, outcome) = match &result {
+            Ok(bytes) => (bytes.len(), Outcome::Success),
+            Err(e) => (0, Outcome::Error(e)),
+        };
+        self.hooks.on_complete(
+            ObjectStoreOp::Get,
+            location,
+            Some(range),
+            bytes,
+            started.elapsed(),
+            outcome,
+            Labels::NONE,
+        );
+        result
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        for range in ranges {
+            self.hooks
+                .on_request(ObjectStoreOp::Get, location, Some(range.clone()));
+        }
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .get_ranges(location, ranges)
+            .instrument(request_span(ObjectStoreOp::Get, location))
+            .await;
+
+        // the ranges are fetched together so they all get the same duration
+        let elapsed = started.elapsed();
+        match &result {
+            Ok(chunks) => {
+                for (range, chunk) in ranges.iter().zip(chunks) {
+                    self.hooks.on_complete(
+                        ObjectStoreOp::Get,
+                        location,
+                        Some(range.clone()),
+                        chunk.len(),
+                        elapsed,
+                        Outcome::Success,
+                        Labels::NONE,
+                    );
+                }
+            }
+            Err(e) => {
+                for range in ranges {
+                    self.hooks.on_complete(
+                        ObjectStoreOp::Get,
+                        location,
+                        Some(range.clone()),
+                        0,
+                        elapsed,
+                        Outcome::Error(e),
+                        Labels::NONE,
+                    );
+                }
+            }
+        }
+        result
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.hooks.on_request(ObjectStoreOp::Head, location, None);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .head(location)
+            .instrument(request_span(ObjectStoreOp::Head, location))
+            .await;
+        self.complete(ObjectStoreOp::Head, location, started, &result, |_| 0);
+        result
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.hooks.on_request(ObjectStoreOp::Delete, location, None);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .delete(location)
+            .instrument(request_span(ObjectStoreOp::Delete, location))
+            .await;
+        self.complete(ObjectStoreOp::Delete, location, started, &result, |_| 0);
+        result
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        let path = prefix.cloned().unwrap_or_default();
+        self.hooks.on_request(ObjectStoreOp::List, &path, None);
+        self.hooked_stream(ObjectStoreOp::List, path, "prefix", self.inner.list(prefix))
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        let path = prefix.cloned().unwrap_or_default();
+        self.hooks.on_request(ObjectStoreOp::List, &path, None);
+        self.hooked_stream(
+            ObjectStoreOp::List,
+            path,
+            "offset",
+            self.inner.list_with_offset(prefix, offset),
+        )
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let path = prefix.cloned().unwrap_or_default();
+        self.hooks.on_request(ObjectStoreOp::List, &path, None);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .list_with_delimiter(prefix)
+            .instrument(request_span(ObjectStoreOp::List, &path))
+            .await;
+        // the pages of a delimited listing are all fetched before it returns
+        let (outcome, labels) = match &result {
+            Ok(list) => (
+                Outcome::Success,
+                Labels::default().str("list", "delimiter").num(
+                    "entries",
+                    (list.objects.len() + list.common_prefixes.len()) as i64,
+                ),
+            ),
+            Err(e) => (
+                Outcome::Error(e),
+                Labels::default().str("list", "delimiter"),
+            ),
+        };
+        self.hooks.on_complete(
+            ObjectStoreOp::List,
+            &path,
+            None,
+            0,
+            started.elapsed(),
+            outcome,
+            &labels,
+        );
+        result
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.hooks.on_request(ObjectStoreOp::Copy, from, None);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .copy(from, to)
+            .instrument(request_span(ObjectStoreOp::Copy, from))
+            .await;
+        self.complete(ObjectStoreOp::Copy, from, started, &result, |_| 0);
+        result
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.hooks.on_request(ObjectStoreOp::Rename, from, None);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .rename(from, to)
+            .instrument(request_span(ObjectStoreOp::Rename, from))
+            .await;
+        self.complete(ObjectStoreOp::Rename, from, started, &result, |_| 0);
+        result
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.hooks.on_request(ObjectStoreOp::Copy, from, None);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .copy_if_not_exists(from, to)
+            .instrument(request_span(ObjectStoreOp::Copy, from))
+            .await;
+        self.complete(ObjectStoreOp::Copy, from, started, &result, |_| 0);
+        result
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.hooks.on_request(ObjectStoreOp::Rename, from, None);
+        let started = Started::now(&self.clock);
+        let result = self
+            .inner
+            .rename_if_not_exists(from, to)
+            .instrument(request_span(ObjectStoreOp::Rename, from))
+            .await;
+        self.complete(ObjectStoreOp::Rename, from, started, &result, |_| 0);
+        result
+    }
+}
+
+/// A listing. Fires `on_complete` once the stream ends, fails, or is dropped
+/// early, labelled with the kind of listing, the entries it returned and the
+/// pages it waited on.
+struct HookedStream<'a, T> {
+    inner: BoxStream<'a, Result<T>>,
+    hooks: Arc<dyn StoreHooks>,
+    op: ObjectStoreOp,
+    path: Path,
+    started: Started,
+    kind: &'static str,
+    entries: usize,
+    /// entries that weren't ready when first polled for. each of those is
+    /// (at least) one page being fetched, items from the same page come
+    /// back without waiting
+    pages: usize,
+    waiting: bool,
+    done: bool,
+}
+
+impl<T> HookedStream<'_, T> {
+    fn labels(&self) -> Labels {
+        Labels::default()
+            .str("list", self.kind)
+            .num("entries", self.entries as i64)
+            .num("pages", self.pages as i64)
+    }
+}
+
+impl<T> Stream for HookedStream<'_, T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = match self.inner.poll_next_unpin(cx) {
+            Poll::Ready(item) => item,
+            Poll::Pending => {
+                self.waiting = true;
+                return Poll::Pending;
+            }
+        };
+        if let Some(Ok(_)) = &item {
+            self.entries += 1;
+            if std::mem::take(&mut self.waiting) {
+                self.pages += 1;
+            }
+        }
+        if !self.done {
+            let outcome = match &item {
+                Some(Ok(_)) => None,
+                Some(Err(e)) => Some(Outcome::Error(e)),
+                None => Some(Outcome::Success),
+            };
+            if let Some(outcome) = outcome {
+                self.done = true;
+                let labels = self.labels();
+                self.hooks.on_complete(
+                    self.op,
+                    &self.path,
+                    None,
+                    0,
+                    self.started.elapsed(),
+                    outcome,
+                    &labels,
+                );
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl<T> Drop for HookedStream<'_, T> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.hooks.on_complete(
+                self.op,
+                &self.path,
+                None,
+                0,
+                self.started.elapsed(),
+                Outcome::Aborted,
+                &self.labels(),
+            );
+        }
+    }
+}
+
+/// The payload of a streamed get. Fires `on_first_byte` when the first chunk
+/// arrives, if one does, and `on_complete` once the stream ends, fails, or is dropped early,
+/// with the bytes that were actually read from it.
+struct GetStream {
+    inner: BoxStream<'static, Result<Bytes>>,
+    hooks: Arc<dyn StoreHooks>,
+    op: ObjectStoreOp,
+    path: Path,
+    range: Option<Range<usize>>,
+    started: Started,
+    /// the length of the range that was requested
+    expected: usize,
+    read: usize,
+    labels: Labels,
+    first_byte: bool,
+    done: bool,
+}
+
+impl Stream for GetStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.inner.poll_next_unpin(cx));
+        if let Some(Ok(chunk)) = &item {
+            // a stream that fails or ends before its first chunk has no first
+            // byte
+            if !self.first_byte {
+                self.first_byte = true;
+                self.hooks
+                    .on_first_byte(self.op, &self.path, self.started.elapsed(), &self.labels);
+            }
+            self.read += chunk.len();
+        }
+        if !self.done {
+            let outcome = match &item {
+                Some(Ok(_)) => None,
+                Some(Err(e)) => Some(Outcome::Error(e)),
+                None => Some(Outcome::Success),
+            };
+            if let Some(outcome) = outcome {
+                self.done = true;
+                self.hooks.on_complete(
+                    self.op,
+                    &self.path,
+                    self.range.clone(),
+                    self.read,
+                    self.started.elapsed(),
+                    outcome,
+                    &self.labels,
+                );
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl Drop for GetStream {
+    fn drop(&mut self) {
+        if !self.done {
+            log::debug!(
+                "{} {} dropped after reading {} of {} bytes",
+                self.op,
+                self.path,
+                self.read,
+                self.expected
+            );
+            self.hooks.on_complete(
+                self.op,
+                &self.path,
+                self.range.clone(),
+                self.read,
+                self.started.elapsed(),
+                Outcome::Aborted,
+                &self.labels,
+            );
+        }
+    }
+}
+
+/// Fires `on_complete` when the upload is completed, aborted or dropped
+/// before either, with the total size of the parts that were put.
+struct HookedUpload {
+    inner: Box<dyn MultipartUpload>,
+    hooks: Arc<dyn StoreHooks>,
+    path: Path,
+    started: Started,
+    bytes: usize,
+    labels: Labels,
+    done: bool,
+}
+
+impl fmt::Debug for HookedUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookedUpload")
+            .field("inner", &self.inner)
+            .field("path", &self.path)
+            .field("bytes", &self.bytes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for HookedUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        self.bytes += data.content_length();
+        self.inner.put_part(data)
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        let result = self.inner.complete().await;
+        self.done = true;
+        let outcome = match &result {
+            Ok(_) => Outcome::Success,
+            Err(e) => Outcome::Error(e),
+        };
+        self.hooks.on_complete(
+            ObjectStoreOp::Put,
+            &self.path,
+            None,
+            self.bytes,
+            self.started.elapsed(),
+            outcome,
+            &self.labels,
+        );
+        result
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        let result = self.inner.abort().await;
+        self.done = true;
+        self.hooks.on_complete(
+            ObjectStoreOp::Put,
+            &self.path,
+            None,
+            self.bytes,
+            self.started.elapsed(),
+            Outcome::Aborted,
+            &self.labels,
+        );
+        result
+    }
+}
+
+impl Drop for HookedUpload {
+    fn drop(&mut self) {
+        if !self.done {
+            log::debug!(
+                "upload of {} dropped after putting {} bytes",
+                self.path,
+                self.bytes
+            );
+            self.hooks.on_complete(
+                ObjectStoreOp::Put,
+                &self.path,
+                None,
+                self.bytes,
+                self.started.elapsed(),
+                Outcome::Aborted,
+                &self.labels,
+            );
+        }
+    }
+}
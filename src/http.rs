@@ -0,0 +1,42 @@
+//! Just enough HTTP/1.1 for the small servers here (`view`'s viewer and the
+//! `--admin-addr` endpoint): the path of each request, and one response to it
+//! before the connection is closed.
+
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// bytes of a request read before giving up on finding the end of its head
+const MAX_REQUEST: usize = 8192;
+
+/// the path `stream`'s request is for, `/` if it didn't say
+pub async fn request_path(stream: &mut TcpStream) -> io::Result<String> {
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    // e.g. `GET /flamegraph/0.svg HTTP/1.1`
+    Ok(request.split_whitespace().nth(1).unwrap_or("/").to_string())
+}
+
+/// writes the response and closes the connection
+pub async fn respond(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
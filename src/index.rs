@@ -0,0 +1,133 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use lance::index::vector::VectorIndexParams;
+use lance_index::vector::hnsw::builder::HnswBuildParams;
+use lance_index::vector::ivf::IvfBuildParams;
+use lance_index::vector::pq::PQBuildParams;
+use lance_index::vector::sq::builder::SQBuildParams;
+use lance_index::IndexType;
+use lance_linalg::distance::MetricType;
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::data::VectorType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorIndexType {
+    IvfFlat,
+    IvfPq,
+    /// ivf with scalar quantization, `--num-bits` per dimension
+    IvfSq,
+    IvfHnswPq,
+    IvfHnswSq,
+}
+
+// for the PPROF_DEV_INDEX_TYPE override
+impl FromStr for VectorIndexType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    L2,
+    /// generated float32 vectors are normalized to unit length
+    Cosine,
+    Dot,
+    Hamming,
+}
+
+// for the PPROF_DEV_METRIC override
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+/// The scalar index `--scalar-index` builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalarIndexType {
+    Btree,
+    /// for low cardinality columns such as `label`
+    Bitmap,
+}
+
+// for the PPROF_DEV_SCALAR_INDEX_TYPE override
+impl FromStr for ScalarIndexType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+impl From<ScalarIndexType> for IndexType {
+    fn from(index_type: ScalarIndexType) -> Self {
+        match index_type {
+            ScalarIndexType::Btree => IndexType::BTree,
+            ScalarIndexType::Bitmap => IndexType::Bitmap,
+        }
+    }
+}
+
+impl From<Metric> for MetricType {
+    fn from(metric: Metric) -> Self {
+        match metric {
+            Metric::L2 => MetricType::L2,
+            Metric::Cosine => MetricType::Cosine,
+            Metric::Dot => MetricType::Dot,
+            Metric::Hamming => MetricType::Hamming,
+        }
+    }
+}
+
+/// distance used to build and query the vector index
+pub fn metric_type(args: &Args) -> MetricType {
+    if let Some(metric) = args.metric {
+        return metric.into();
+    }
+    match args.vector_type {
+        VectorType::Binary => MetricType::Hamming,
+        // lance only supports cosine for multivector indexes
+        _ if args.multivector.is_some() => MetricType::Cosine,
+        _ => MetricType::L2,
+    }
+}
+
+pub fn vector_index_params(args: &Args) -> VectorIndexParams {
+    let metric = metric_type(args);
+    let mut ivf = IvfBuildParams::new(args.num_partitions);
+    ivf.max_iters = args.max_iterations;
+    let pq = PQBuildParams::new(args.num_sub_vectors, args.num_bits as usize);
+    let sq = SQBuildParams {
+        num_bits: args.num_bits,
+        ..Default::default()
+    };
+
+    match args.index_type {
+        VectorIndexType::IvfFlat => VectorIndexParams::ivf_flat(args.num_partitions, metric),
+        VectorIndexType::IvfPq => VectorIndexParams::ivf_pq(
+            args.num_partitions,
+            args.num_bits as u8,
+            args.num_sub_vectors,
+            metric,
+            args.max_iterations,
+        ),
+        VectorIndexType::IvfSq => VectorIndexParams::with_ivf_sq_params(metric, ivf, sq),
+        VectorIndexType::IvfHnswPq => {
+            VectorIndexParams::with_ivf_hnsw_pq_params(metric, ivf, HnswBuildParams::default(), pq)
+        }
+        VectorIndexType::IvfHnswSq => {
+            VectorIndexParams::with_ivf_hnsw_sq_params(metric, ivf, HnswBuildParams::default(), sq)
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Lance's own account of the IO its scanners did, reconciled with what the
+//! profiling wrapper saw of the same phase. The two are measured at different
+//! layers, so when they disagree one of them is missing something.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use lance::dataset::scanner::{ExecutionStatsCallback, ExecutionSummaryCounts, Scanner};
+use serde::{Deserialize, Serialize};
+
+use crate::fork::PerProcess;
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+use crate::summary::{PhaseSummary, SummaryFormat};
+
+/// relative difference past which the two layers are flagged as disagreeing.
+/// the wrapper also sees reads the scanners don't count (manifests, say), so
+/// they're never quite equal
+const TOLERANCE: f64 = 0.1;
+
+/// what every scanner reported since the last [`take`]
+static IOPS: PerProcess<AtomicU64> = PerProcess::new(AtomicU64::default);
+static REQUESTS: PerProcess<AtomicU64> = PerProcess::new(AtomicU64::default);
+static BYTES_READ: PerProcess<AtomicU64> = PerProcess::new(AtomicU64::default);
+
+/// The IO lance counted over a phase's scans.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanceCounts {
+    /// reads lance asked for
+    pub iops: u64,
+    /// requests those were coalesced into
+    pub requests: u64,
+    pub bytes_read: u64,
+}
+
+/// has `scanner` add what it read to the counts when it finishes
+pub fn track(scanner: &mut Scanner) {
+    let callback: ExecutionStatsCallback = Arc::new(|counts: &ExecutionSummaryCounts| {
+        IOPS.get().fetch_add(counts.iops as u64, Ordering::Relaxed);
+        REQUESTS
+            .get()
+            .fetch_add(counts.requests as u64, Ordering::Relaxed);
+        BYTES_READ
+            .get()
+            .fetch_add(counts.bytes_read as u64, Ordering::Relaxed);
+    });
+    scanner.scan_stats_callback(callback);
+}
+
+/// what the scanners read since the last call
+pub fn take() -> LanceCounts {
+    LanceCounts {
+        iops: IOPS.get().swap(0, Ordering::Relaxed),
+        requests: REQUESTS.get().swap(0, Ordering::Relaxed),
+        bytes_read: BYTES_READ.get().swap(0, Ordering::Relaxed),
+    }
+}
+
+impl LanceCounts {
+    /// whether the wrapper's gets in `summary` account for these
+    pub fn agrees_with(&self, summary: &PhaseSummary) -> bool {
+        let (gets, bytes) = gets(summary);
+        close(self.requests, gets) && close(self.bytes_read, bytes)
+    }
+}
+
+fn gets(summary: &PhaseSummary) -> (u64, u64) {
+    summary
+        .get(&ObjectStoreOp::Get)
+        .map_or((0, 0), |s| (s.count, s.bytes))
+}
+
+fn close(a: u64, b: u64) -> bool {
+    a.abs_diff(b) as f64 <= TOLERANCE * a.max(b) as f64
+}
+
+/// lance's counts next to the wrapper's, for each phase lance counted any
+pub fn render(
+    format: SummaryFormat,
+    phases: &BTreeMap<Phase, PhaseSummary>,
+    counts: &BTreeMap<Phase, LanceCounts>,
+) -> String {
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => {
+            out.push_str("phase,lance_iops,lance_requests,lance_bytes,gets,get_bytes,agrees\n")
+        }
+        SummaryFormat::Text => writeln!(
+            out,
+            "{:<14} {:>10} {:>14} {:>14} {:>8} {:>14} {}",
+            "phase", "lance_iops", "lance_requests", "lance_bytes", "gets", "get_bytes", "agrees"
+        )
+        .unwrap(),
+    }
+    let empty = PhaseSummary::new();
+    for (phase, c) in counts {
+        let summary = phases.get(phase).unwrap_or(&empty);
+        let (gets, bytes) = gets(summary);
+        let agrees = c.agrees_with(summary);
+        match format {
+            SummaryFormat::Csv => writeln!(
+                out,
+                "{},{},{},{},{gets},{bytes},{agrees}",
+                phase.as_str(),
+                c.iops,
+                c.requests,
+                c.bytes_read
+            ),
+            SummaryFormat::Text => writeln!(
+                out,
+                "{:<14} {:>10} {:>14} {:>14} {:>8} {:>14} {}",
+                phase.as_str(),
+                c.iops,
+                c.requests,
+                c.bytes_read,
+                gets,
+                bytes,
+                if agrees { "yes" } else { "NO" }
+            ),
+        }
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,177 @@
+//! `--store-layers layers.toml`: the layers between lance and the real store,
+//! in any order, instead of the fixed order the flags put them in. Each
+//! `[[layer]]` table is one layer, outermost (nearest lance) first:
+//!
+//! ```toml
+//! [[layer]]
+//! kind = "profiling"
+//!
+//! [[layer]]
+//! kind = "head_cache"
+//! ttl_ms = 5000
+//!
+//! [[layer]]
+//! kind = "throttle"
+//! latency_ms = 20
+//! bytes_per_sec = 100_000_000
+//!
+//! [[layer]]
+//! kind = "faults"
+//! error_rate = 0.01
+//! ops = ["get"]
+//! ```
+//!
+//! `profiling` is where the profiles' sink and every hook (summaries, stats,
+//! traces, ...) see the requests, and has to be there exactly once. Requests
+//! answered by a layer above it never show up in any of those.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use object_store::limit::LimitStore;
+use object_store::ObjectStore;
+use serde::Deserialize;
+
+use crate::cache::{CachedObjectStore, HeadCache};
+use crate::faults::FaultyStore;
+use crate::mmap::MmapStore;
+use crate::op::ObjectStoreOp;
+use crate::prefetch::{Prefetcher, PrefetchingObjectStore};
+use crate::throttle::ThrottledStore;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayersFile {
+    layer: Vec<LayerConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum LayerConfig {
+    Profiling,
+    /// like `--head-cache-ttl-ms`
+    HeadCache {
+        ttl_ms: u64,
+    },
+    /// like `--prefetch-bytes`
+    Prefetch {
+        bytes: usize,
+    },
+    /// like `--store-permits`
+    Limit {
+        permits: usize,
+    },
+    /// like `--mmap`
+    Mmap,
+    Throttle {
+        #[serde(default)]
+        latency_ms: u64,
+        bytes_per_sec: Option<u64>,
+    },
+    Faults {
+        error_rate: f64,
+        /// all of them if not given
+        #[serde(default)]
+        ops: Vec<ObjectStoreOp>,
+    },
+}
+
+/// One layer of the store handed to lance. The head cache and prefetcher are
+/// shared with the run so it can write their reports.
+#[derive(Clone)]
+pub enum StoreLayer {
+    Profiling,
+    HeadCache(Arc<HeadCache>),
+    Prefetch(Arc<Prefetcher>),
+    Limit(usize),
+    Mmap,
+    Throttle {
+        latency: Duration,
+        bytes_per_sec: Option<u64>,
+    },
+    Faults {
+        error_rate: f64,
+        ops: Vec<ObjectStoreOp>,
+    },
+}
+
+impl StoreLayer {
+    /// `store` with this layer on top, or `store` itself for the profiling
+    /// layer, which the wrapper adds
+    pub fn wrap(&self, store: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        match self {
+            StoreLayer::Profiling => store,
+            StoreLayer::HeadCache(cache) => Arc::new(CachedObjectStore::new(store, cache.clone())),
+            StoreLayer::Prefetch(prefetcher) => {
+                Arc::new(PrefetchingObjectStore::new(store, prefetcher.clone()))
+            }
+            StoreLayer::Limit(permits) => Arc::new(LimitStore::new(store, *permits)),
+            StoreLayer::Mmap => Arc::new(MmapStore::new(store)),
+            StoreLayer::Throttle {
+                latency,
+                bytes_per_sec,
+            } => Arc::new(ThrottledStore::new(store, *latency, *bytes_per_sec)),
+            StoreLayer::Faults { error_rate, ops } => {
+                Arc::new(FaultyStore::new(store, *error_rate, ops.clone()))
+            }
+        }
+    }
+}
+
+/// the layers in `path`, outermost first
+pub fn load(path: &Path) -> io::Result<Vec<StoreLayer>> {
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {message}", path.display()),
+        )
+    };
+    let file: LayersFile =
+        toml::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?;
+
+    let profiling = file
+        .layer
+        .iter()
+        .filter(|layer| matches!(layer, LayerConfig::Profiling))
+        .count();
+    if profiling != 1 {
+        return Err(invalid(format!(
+            "expected exactly one profiling layer, found {profiling}"
+        )));
+    }
+
+    file.layer
+        .into_iter()
+        .map(|layer| {
+            Ok(match layer {
+                LayerConfig::Profiling => StoreLayer::Profiling,
+                LayerConfig::HeadCache { ttl_ms } => {
+                    StoreLayer::HeadCache(Arc::new(HeadCache::new(Duration::from_millis(ttl_ms))))
+                }
+                LayerConfig::Prefetch { bytes } => {
+                    StoreLayer::Prefetch(Arc::new(Prefetcher::new(bytes)))
+                }
+                LayerConfig::Limit { permits } => StoreLayer::Limit(permits),
+                LayerConfig::Mmap => StoreLayer::Mmap,
+                LayerConfig::Throttle {
+                    latency_ms,
+                    bytes_per_sec,
+                } => StoreLayer::Throttle {
+                    latency: Duration::from_millis(latency_ms),
+                    bytes_per_sec,
+                },
+                LayerConfig::Faults { error_rate, ops } => {
+                    if !(0.0..=1.0).contains(&error_rate) {
+                        return Err(invalid(format!(
+                            "error_rate is {error_rate}, it has to be between 0 and 1"
+                        )));
+                    }
+                    StoreLayer::Faults { error_rate, ops }
+                }
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,84 @@
+//! Drives an operation from `--concurrency` tasks at once, optionally paced to
+//! a target `--qps` and run for `--duration-secs` instead of a fixed count.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use tracing::Instrument;
+
+use crate::cli::Args;
+
+/// runs `op` `count` times (or until `--duration-secs` is up) across
+/// `--concurrency` tasks, returning how many ran
+pub async fn drive<F, Fut>(args: &Args, count: usize, op: F) -> usize
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let op = Arc::new(op);
+    let started = Instant::now();
+    let deadline = args
+        .duration_secs
+        .map(|secs| started + Duration::from_secs(secs));
+    let remaining = Arc::new(AtomicUsize::new(count));
+    let completed = Arc::new(AtomicUsize::new(0));
+    // one ticker shared by every task so the rate is for the whole phase,
+    // not per task. a slow store delays ticks rather than bursting after
+    let ticker: Option<Arc<Mutex<Interval>>> = args.qps.map(|qps| {
+        let mut ticker = interval(Duration::from_secs_f64(1.0 / qps));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Arc::new(Mutex::new(ticker))
+    });
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..args.concurrency.max(1) {
+        let op = op.clone();
+        let remaining = remaining.clone();
+        let completed = completed.clone();
+        let ticker = ticker.clone();
+        // in the current span so the requests nest under the phase
+        tasks.spawn(
+            async move {
+                loop {
+                    match deadline {
+                        Some(deadline) if Instant::now() >= deadline => break,
+                        Some(_) => {}
+                        None => {
+                            let claimed =
+                                remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                                    n.checked_sub(1)
+                                });
+                            if claimed.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    if let Some(ticker) = &ticker {
+                        ticker.lock().await.tick().await;
+                    }
+                    op().await;
+                    completed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            .in_current_span(),
+        );
+    }
+    while let Some(result) = tasks.join_next().await {
+        result.unwrap();
+    }
+
+    let completed = completed.load(Ordering::Relaxed);
+    let elapsed = started.elapsed();
+    log::info!(
+        "ran {completed} ops in {:.2}s ({:.1}/s) from {} tasks",
+        elapsed.as_secs_f64(),
+        completed as f64 / elapsed.as_secs_f64(),
+        args.concurrency.max(1)
+    );
+    completed
+}
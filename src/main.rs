@@ -1,156 +1,1375 @@
-use std::fs::File;
-use std::io::Write;
-use std::iter::repeat_with;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use arrow::error::Result;
-use arrow_array::{Float32Array, RecordBatch, RecordBatchIterator};
-use arrow_schema::{DataType, Field, Schema};
-use lance::{dataset::WriteMode, index::vector::VectorIndexParams, Dataset};
-use object_store::ObjectStore;
-use lance::dataset::{ReadParams, WriteParams};
+use admin::LiveCounters;
+use agent::Agent;
+use arrow_array::{Array, RecordBatch, RecordBatchIterator};
+use clap::ValueEnum;
+use futures::TryStreamExt;
+use lance::dataset::builder::DatasetBuilder;
+use lance::dataset::optimize::{commit_compaction, plan_compaction, CompactionOptions};
+use lance::dataset::scanner::Scanner;
+use lance::dataset::{ColumnAlteration, NewColumnTransform, ReadParams, WriteParams};
+use lance::index::DatasetIndexRemapperOptions;
+use lance::io::ObjectStoreParams;
+use lance::{dataset::WriteMode, Dataset};
+use lance_index::optimize::OptimizeOptions;
+use lance_index::scalar::ScalarIndexParams;
 use lance_index::traits::DatasetIndexExt;
-use lance_linalg::distance::MetricType;
-use lance::io::{ObjectStoreParams, WrappingObjectStore};
-use lance_arrow::FixedSizeListArrayExt;
-use parking_lot::RwLock;
-use pprof::{protos::Message, Profiler, ReportBuilder, ReportTiming, SampleType, SampleTypes};
-use pprof_object_store::ProfilingObjectStore;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::Instrument;
+
+mod admin;
+mod agent;
+mod backend;
+mod bench;
+mod breakdown;
+mod cache;
+mod cli;
+mod clock;
+mod coalesce;
+mod columns;
+mod commit;
+mod cost;
+mod crash;
+mod data;
+mod distribution;
+mod faults;
+#[cfg(feature = "profiling")]
+mod flamegraph;
+mod fork;
+mod hooks;
+mod http;
+mod index;
+mod lance_stats;
+mod layers;
+mod load;
+mod merge;
+mod metadata;
+mod metrics;
+mod mmap;
+mod multi;
+mod op;
+mod otel;
+mod outlier;
+mod prefetch;
+mod profiling;
+mod reconcile;
+mod registry;
+mod report;
+mod run;
+mod scope;
+mod shared;
+mod simulate;
+mod sql;
+mod stacks;
+mod stats;
+mod steady;
+mod summary;
+mod sweep;
+mod table;
+mod tenant;
+#[cfg(test)]
+mod testing;
+mod throttle;
+mod tiers;
+mod timeline;
+mod toggles;
+mod trace;
+mod upload;
+mod view;
+
+use breakdown::FileBreakdown;
+use cache::HeadCache;
+use cli::{Args, Cli, Command};
+use coalesce::CoalescingAnalyzer;
+use columns::ColumnLayout;
+use cost::PriceModel;
+use distribution::KeySampler;
+use hooks::{Labels, LoggingHooks};
+use layers::StoreLayer;
+use metrics::MetricsExporter;
+use outlier::{OutlierRequests, Threshold};
+use prefetch::Prefetcher;
+use profiling::{ProfiledOp, ProfilingObjectStoreWrapper};
+use registry::registry;
+use run::{OnTimeout, Phase, RunDir};
+use scope::{NodeCollector, NodeSummaries};
+use stats::StatsCollector;
+use summary::{PhaseSummary, PhaseTiming, SummaryCollector, SummaryFormat};
+use tenant::{TenantCollector, TenantKey, TenantSummaries};
+use timeline::Timeline;
+use trace::TraceRecorder;
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let vector_dims = 1536;
-    let rows = 20_000;
-    let schema = Arc::new(create_schema(vector_dims));
+    crash::install_hook();
+    let cli = Cli::load().unwrap_or_else(|e| e.exit());
+
+    match cli.command {
+        None => run_workload(cli.run).await,
+        Some(Command::Report(args)) => report::write_report(&args).unwrap(),
+        Some(Command::Diff(args)) => report::write_diff(&args).unwrap(),
+        Some(Command::Merge(args)) => merge::write_merge(&args).unwrap(),
+        Some(Command::Finalize(args)) => shared::finalize(&args).unwrap(),
+        Some(Command::View(args)) => view::view(&args).await.unwrap(),
+        Some(Command::Collect(args)) => agent::collect(&args).await.unwrap(),
+        Some(Command::Bench(args)) => bench::run_bench(args).await,
+        Some(Command::Reconcile(args)) => reconcile::reconcile(&args).unwrap(),
+        Some(Command::Simulate(args)) => simulate::simulate(&args).unwrap(),
+        Some(Command::Sweep(args)) => sweep::run_sweep(args).await,
+        Some(Command::Scale(args)) => sweep::run_scale(args).await,
+        Some(Command::Compare(args)) => sweep::run_compare(args).await,
+        Some(Command::Runtimes(args)) => sweep::run_runtimes(args).await,
+        Some(Command::Parallelism(args)) => sweep::run_parallelism(args).await,
+        Some(Command::Projections(args)) => sweep::run_projections(args).await,
+        Some(Command::ScanTuning(args)) => sweep::run_scan_tuning(args).await,
+        Some(Command::Selectivity(args)) => sweep::run_selectivity(args).await,
+        Some(Command::Knn(args)) => sweep::run_knn(args).await,
+        Some(Command::Deletes(args)) => sweep::run_deletes(args).await,
+        Some(Command::Mmap(args)) => sweep::run_mmap(args).await,
+        Some(Command::Multi(args)) => multi::run_multi(args).await,
+        Some(Command::Tiers(args)) => tiers::run_tiers(args).await,
+    }
+}
+
+async fn run_workload(args: Args) {
+    let _otel = otel::Exporter::start(&args);
+    let mut run = Run::new(&args);
+    log::info!("starting run {}", run.dir.run_id());
+    let span = tracing::info_span!("run", run_id = run.dir.run_id());
+    let uri = run.dir.dataset_uri().to_string();
+    if let (Some(addr), Some(live)) = (&args.admin_addr, &run.live) {
+        admin::spawn(addr, live.clone()).await.unwrap();
+    }
+
+    let profile_os_wrapper = registry().register("dataset", profiling_wrapper(&args, &run));
+    for &op in &args.disable_ops {
+        registry().toggles().set_enabled(op, false);
+    }
+    if !args.toggle_ops.is_empty() {
+        toggles::spawn_signal_toggle(args.toggle_ops.clone());
+        log::info!(
+            "SIGUSR1 to pid {} toggles profiling of {:?}",
+            std::process::id(),
+            args.toggle_ops
+        );
+    }
+
+    let interrupted = tokio::select! {
+        // an aborted run has already said why
+        _ = run_phases(&args, &mut run, &uri, profile_os_wrapper).instrument(span.clone()) => false,
+        _ = tokio::signal::ctrl_c() => true,
+    };
+    if interrupted {
+        // a second ctrl-c gives up on writing anything out
+        tokio::spawn(async {
+            tokio::signal::ctrl_c().await.unwrap();
+            std::process::exit(130);
+        });
+        run.interrupt().instrument(span).await;
+    }
+    run.finish();
+    if interrupted {
+        if args.upload_to.is_some() {
+            log::warn!("not uploading the interrupted run");
+        }
+        return;
+    }
+
+    if let Some(destination) = &args.upload_to {
+        let destination = format!("{}/{}", destination.trim_end_matches('/'), run.dir.run_id());
+        let uploaded = upload::upload_dir(
+            run.dir.path(),
+            &destination,
+            args.storage_options().unwrap_or_default(),
+        )
+        .await
+        .unwrap();
+        println!("uploaded {uploaded} files to {destination}");
+    }
+}
+
+/// every phase of the workload that hasn't completed yet, in order
+async fn run_phases(
+    args: &Args,
+    run: &mut Run,
+    uri: &str,
+    profile_os_wrapper: Arc<ProfilingObjectStoreWrapper>,
+) -> Result<(), Aborted> {
+    let mut ds = if run.dir.is_complete(Phase::Write) {
+        log::info!("resuming run, reusing dataset at {uri}");
+        open_dataset(args, uri, profile_os_wrapper.clone()).await
+    } else {
+        let written = run
+            .run_phase(
+                Phase::Write,
+                write_phase(args, uri, profile_os_wrapper.clone()),
+            )
+            .await?;
+        // there's no dataset for the rest of the phases to carry on with
+        written.ok_or(Aborted)?
+    };
+
+    if !run.dir.is_complete(Phase::Index) {
+        run.run_phase(Phase::Index, index_phase(args, &mut ds))
+            .await?;
+    }
+    if let Some(columns) = &run.columns {
+        columns.load(&ds).await.unwrap();
+    }
+
+    if !run.dir.is_complete(Phase::Query) {
+        if let Some(warmup) = warmup_args(args) {
+            query_phase(&warmup, &ds).await;
+        }
+        let mut query_args = args.clone();
+        if let Some(steady_after) = args.steady_after {
+            if !run.dir.is_complete(Phase::QueryStartup) {
+                let summary = run.summary.clone();
+                let startup = steady::startup_phase(args, &ds, steady_after, &summary);
+                if let Some(startup) = run.run_phase(Phase::QueryStartup, startup).await? {
+                    query_args = steady::steady_args(args, &startup);
+                }
+            }
+        }
+        run.run_phase(Phase::Query, query_phase(&query_args, &ds))
+            .await?;
+
+        let query = data::query_vector(args, &mut rand::thread_rng());
+        let scanner = knn_scanner(args, &ds, "vector", query.as_ref());
+        write_plan(run, Phase::Query, &scanner).await;
+    }
+
+    if args.cold_warm {
+        cold_warm_phases(args, uri, &profile_os_wrapper, run).await?;
+    }
+
+    if args.prime_index {
+        prime_phases(args, uri, &profile_os_wrapper, run).await?;
+    }
+
+    if let Some(filter) = &args.prefilter {
+        if !run.dir.is_complete(Phase::Prefilter) {
+            if let Some(warmup) = warmup_args(args) {
+                prefilter_phase(&warmup, &ds, filter).await;
+            }
+            run.run_phase(Phase::Prefilter, prefilter_phase(args, &ds, filter))
+                .await?;
+
+            let query = data::query_vector(args, &mut rand::thread_rng());
+            let mut scanner = knn_scanner(args, &ds, "vector", query.as_ref());
+            prefilter(&mut scanner, filter);
+            write_plan(run, Phase::Prefilter, &scanner).await;
+        }
+    }
+
+    if !run.dir.is_complete(Phase::Take) {
+        run.run_phase(Phase::Take, take_phase(args, &ds)).await?;
+    }
+
+    if !run.dir.is_complete(Phase::Scan) {
+        if let Some(warmup) = warmup_args(args) {
+            scan_phase(&warmup, &ds).await;
+        }
+        run.run_phase(Phase::Scan, scan_phase(args, &ds)).await?;
+
+        write_plan(run, Phase::Scan, &scan_scanner(args, &ds).0).await;
+    }
+
+    if !run.dir.is_complete(Phase::TimeRange) {
+        if let Some(warmup) = warmup_args(args) {
+            time_range_phase(&warmup, &ds).await;
+        }
+        run.run_phase(Phase::TimeRange, time_range_phase(args, &ds))
+            .await?;
+
+        let width = time_range_width(args, ds.count_rows(None).await.unwrap() as u64);
+        write_plan(run, Phase::TimeRange, &time_range_scanner(&ds, 0, width)).await;
+    }
+
+    if !args.sql.is_empty() && !run.dir.is_complete(Phase::Sql) {
+        if let Some(runs) = run.run_phase(Phase::Sql, sql::sql_phase(args, &ds)).await? {
+            let path = run.dir.output_path(Phase::Sql, "sql", "txt").unwrap();
+            std::fs::write(path, sql::render(&runs)).unwrap();
+        }
+    }
+
+    if !run.dir.is_complete(Phase::OptimizeIndex) {
+        // like the version creation for cleanup, the append isn't measured
+        append_rows(args, &mut ds).await;
+        if let Some(columns) = &run.columns {
+            columns.load(&ds).await.unwrap();
+        }
+        run.run_phase(Phase::OptimizeIndex, optimize_index_phase(args, &mut ds))
+            .await?;
+    }
+
+    if !run.dir.is_complete(Phase::Evolve) {
+        run.run_phase(Phase::Evolve, evolve_phase(args, &mut ds))
+            .await?;
+    }
+
+    if !run.dir.is_complete(Phase::Cleanup) {
+        // creating the versions isn't what's being measured, so happens before
+        // the phase starts recording
+        create_versions(args, &mut ds).await;
+        run.run_phase(Phase::Cleanup, cleanup_phase(&ds)).await?;
+    }
+
+    if !run.dir.is_complete(Phase::Rewrite) {
+        run.run_phase(Phase::Rewrite, rewrite_phase(args, &mut ds))
+            .await?;
+    }
+    Ok(())
+}
+
+/// the workload of a phase cut down to `--warmup` iterations, run before the
+/// phase starts recording so one-time loads don't end up in its profiles
+fn warmup_args(args: &Args) -> Option<Args> {
+    if args.warmup == 0 {
+        return None;
+    }
+    let mut warmup = args.clone();
+    warmup.queries = args.warmup;
+    warmup.scans = args.warmup;
+    warmup.time_range_queries = args.warmup;
+    warmup.duration_secs = None;
+    warmup.qps = None;
+    Some(warmup)
+}
+
+/// the wrapper to give lance for a dataset, recording into `run`. it still
+/// has to be registered
+fn profiling_wrapper(args: &Args, run: &Run) -> ProfilingObjectStoreWrapper {
+    let mut wrapper = ProfilingObjectStoreWrapper::new()
+        .with_stack_options(args.max_stack_depth, args.frame_resolution);
+    if args.count_only {
+        wrapper = wrapper.with_count_only();
+    }
+    if args.latency_profiles {
+        wrapper = wrapper.with_latency_profiles();
+    }
+    if args.task_labels {
+        wrapper = wrapper.with_task_labels();
+    }
+    if args.request_sizes {
+        wrapper = wrapper.with_request_sizes();
+    }
+    if args.log_requests {
+        wrapper = wrapper.with_hooks(Arc::new(LoggingHooks));
+    }
+    if let Some(trace) = &run.trace {
+        wrapper = wrapper.with_hooks(trace.clone());
+    }
+    for outliers in &run.outliers {
+        wrapper = wrapper.with_hooks(outliers.clone());
+    }
+    if let Some(coalescing) = &run.coalescing {
+        wrapper = wrapper.with_hooks(coalescing.clone());
+    }
+    if let Some(columns) = &run.columns {
+        wrapper = wrapper.with_column_layout(columns.clone());
+    }
+    if let Some(key) = TenantKey::from_args(args) {
+        wrapper = wrapper.with_tenant_labels(key);
+    }
+    if let Some(tenants) = &run.tenants {
+        wrapper = wrapper.with_hooks(tenants.clone());
+    }
+    wrapper = wrapper.with_hooks(run.summary.clone());
+    wrapper = wrapper.with_hooks(run.nodes.clone());
+    wrapper = wrapper.with_hooks(run.stats.clone());
+    wrapper = wrapper.with_hooks(run.files.clone());
+    wrapper = wrapper.with_hooks(run.timeline.clone());
+    if let Some(live) = &run.live {
+        wrapper = wrapper.with_hooks(live.clone());
+    }
+    if let Some(layers) = &run.layers {
+        wrapper = wrapper.with_layers(layers.clone());
+    }
+    if args.mmap {
+        wrapper = wrapper.with_mmap();
+    }
+    if let Some(permits) = args.store_permits {
+        wrapper = wrapper.with_permits(permits);
+    }
+    if let Some(prefetcher) = &run.prefetcher {
+        wrapper = wrapper.with_prefetch(prefetcher.clone());
+    }
+    if let Some(cache) = &run.head_cache {
+        wrapper = wrapper.with_head_cache(cache.clone());
+    }
+    wrapper
+}
+
+/// Returned by a run that timed out a phase with `--on-timeout abort`.
+struct Aborted;
+
+/// Everything recorded over the course of a run, and where it gets written.
+struct Run {
+    dir: RunDir,
+    metrics: Option<MetricsExporter>,
+    summary_format: SummaryFormat,
+    prices: PriceModel,
+    summary: Arc<SummaryCollector>,
+    nodes: Arc<NodeCollector>,
+    stats: Arc<StatsCollector>,
+    /// index versus data file requests, for the phases that read an index
+    files: Arc<FileBreakdown>,
+    timeline: Arc<Timeline>,
+    /// for `--admin-addr`, counting over the whole run
+    live: Option<Arc<LiveCounters>>,
+    /// for `--agent-addr`, sending the profiles of each phase
+    agent: Option<Agent>,
+    /// from `--store-layers`, sharing the head cache and prefetcher
+    layers: Option<Vec<StoreLayer>>,
+    head_cache: Option<Arc<HeadCache>>,
+    prefetcher: Option<Arc<Prefetcher>>,
+    coalescing: Option<Arc<CoalescingAnalyzer>>,
+    /// for `--column-attribution`, loaded once there's an index
+    columns: Option<Arc<ColumnLayout>>,
+    /// per operator summaries of the phases run by this process, these aren't
+    /// kept for resumed phases
+    node_summaries: NodeSummaries,
+    /// for `--tenant-labels`, per phase like the node summaries
+    tenants: Option<Arc<TenantCollector>>,
+    tenant_summaries: TenantSummaries,
+    trace: Option<Arc<TraceRecorder>>,
+    /// requests over `--slow-request-ms` and over `--huge-request-bytes`
+    outliers: Vec<Arc<OutlierRequests>>,
+    /// whether any of the run's files failed to be written
+    write_failed: AtomicBool,
+    phase_started: Option<Instant>,
+    /// the phase that has started and not yet finished
+    current_phase: Option<Phase>,
+    timeouts: BTreeMap<Phase, Duration>,
+    on_timeout: OnTimeout,
+}
+
+impl Run {
+    fn new(args: &Args) -> Self {
+        let dir = RunDir::open(args).unwrap();
+        let outliers = [
+            args.slow_request_ms.map(|ms| {
+                (
+                    Threshold::Latency(Duration::from_millis(ms)),
+                    "slow_requests.jsonl",
+                )
+            }),
+            args.huge_request_bytes
+                .map(|bytes| (Threshold::Bytes(bytes), "huge_requests.jsonl")),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|(threshold, file)| {
+            Arc::new(OutlierRequests::new(threshold, &dir.run_file(file)).unwrap())
+        })
+        .collect();
+        let layers = args
+            .store_layers
+            .as_ref()
+            .map(|path| layers::load(path).unwrap());
+        // the configured layers' own, for their reports
+        let (head_cache, prefetcher) = match &layers {
+            Some(layers) => (
+                layers.iter().find_map(|layer| match layer {
+                    StoreLayer::HeadCache(cache) => Some(cache.clone()),
+                    _ => None,
+                }),
+                layers.iter().find_map(|layer| match layer {
+                    StoreLayer::Prefetch(prefetcher) => Some(prefetcher.clone()),
+                    _ => None,
+                }),
+            ),
+            None => (
+                args.head_cache_ttl_ms
+                    .map(|ttl| Arc::new(HeadCache::new(Duration::from_millis(ttl)))),
+                args.prefetch_bytes
+                    .map(|bytes| Arc::new(Prefetcher::new(bytes))),
+            ),
+        };
+        let live = args
+            .admin_addr
+            .is_some()
+            .then(|| Arc::new(LiveCounters::new(dir.run_id())));
+        let agent = args
+            .agent_addr
+            .as_ref()
+            .map(|addr| Agent::new(addr, args, &dir));
+        Self {
+            dir,
+            metrics: args
+                .metrics_sink
+                .as_ref()
+                .map(|url| MetricsExporter::new(url, args.metrics_format).unwrap()),
+            summary_format: args.format,
+            prices: PriceModel::new(args),
+            summary: Arc::new(SummaryCollector::default()),
+            nodes: Arc::new(NodeCollector::default()),
+            stats: Arc::new(StatsCollector::default()),
+            files: Arc::new(FileBreakdown::default()),
+            timeline: Arc::new(Timeline::new(args.queue_depth)),
+            live,
+            agent,
+            head_cache,
+            prefetcher,
+            layers,
+            coalescing: args.coalescing.then(|| {
+                Arc::new(CoalescingAnalyzer::new(
+                    args.coalesce_gap_bytes,
+                    Duration::from_millis(args.coalesce_window_ms),
+                ))
+            }),
+            columns: args
+                .column_attribution
+                .then(|| Arc::new(ColumnLayout::default())),
+            node_summaries: NodeSummaries::new(),
+            tenants: TenantKey::from_args(args).map(|key| Arc::new(TenantCollector::new(key))),
+            tenant_summaries: TenantSummaries::new(),
+            trace: args
+                .trace
+                .then(|| Arc::new(TraceRecorder::new(args.trace_stacks))),
+            outliers,
+            write_failed: AtomicBool::new(false),
+            phase_started: None,
+            current_phase: None,
+            timeouts: Phase::value_variants()
+                .iter()
+                .filter_map(|&phase| Some((phase, args.phase_timeout(phase)?)))
+                .collect(),
+            on_timeout: args.on_timeout,
+        }
+    }
+
+    fn start_phase(&mut self, phase: Phase) {
+        registry().reset_all();
+        data::take_generated();
+        lance_stats::take();
+        self.summary.take();
+        self.nodes.take();
+        self.stats.take();
+        self.files.take_report();
+        if let Some(tenants) = &self.tenants {
+            tenants.take();
+        }
+        if let Some(cache) = &self.head_cache {
+            cache.take_report();
+        }
+        if let Some(prefetcher) = &self.prefetcher {
+            prefetcher.take_report();
+        }
+        if let Some(coalescing) = &self.coalescing {
+            coalescing.take_report();
+        }
+        if let Some(columns) = &self.columns {
+            columns.take_report();
+        }
+        if let Some(trace) = &self.trace {
+            trace.begin_phase(phase);
+        }
+        for outliers in &self.outliers {
+            outliers.set_phase(Some(phase));
+        }
+        self.timeline.begin_phase(phase);
+        self.phase_started = Some(Instant::now());
+        self.current_phase = Some(phase);
+        if let Some(live) = &self.live {
+            live.set_phase(Some(phase));
+        }
+        crash::set_in_flight(&self.dir, phase);
+    }
+
+    /// runs `workload` as `phase`, cutting it short once the phase's timeout
+    /// is up. `None` if it timed out and the run carries on without it
+    async fn run_phase<T>(
+        &mut self,
+        phase: Phase,
+        workload: impl Future<Output = T>,
+    ) -> Result<Option<T>, Aborted> {
+        self.start_phase(phase);
+        let workload = workload.instrument(phase.span());
+        let output = match self.timeouts.get(&phase) {
+            Some(&timeout) => tokio::time::timeout(timeout, workload).await.ok(),
+            None => Some(workload.await),
+        };
+        if output.is_some() {
+            self.finish_phase(phase).await;
+            return Ok(output);
+        }
+
+        log::warn!(
+            "{} phase timed out after {:?}, writing what it recorded so far",
+            phase.as_str(),
+            self.timeouts[&phase]
+        );
+        let (summary, mut timing) = self.flush_phase(phase).await;
+        timing.timed_out = true;
+        self.dir.record_partial(phase, summary, timing).unwrap();
+        match self.on_timeout {
+            OnTimeout::Continue => Ok(None),
+            OnTimeout::Abort => {
+                log::warn!("aborting the run");
+                Err(Aborted)
+            }
+        }
+    }
+
+    /// writes the profiles collected during `phase` and records the phase as
+    /// complete so a resumed run won't repeat it
+    async fn finish_phase(&mut self, phase: Phase) {
+        let (summary, timing) = self.flush_phase(phase).await;
+        self.dir.mark_complete(phase, summary, timing).unwrap();
+    }
+
+    /// writes what the phase that was running when the run got interrupted
+    /// recorded so far, leaving it incomplete so a resumed run repeats it
+    async fn interrupt(&mut self) {
+        let Some(phase) = self.current_phase else {
+            return;
+        };
+        log::warn!(
+            "interrupted during the {} phase, writing what it recorded so far",
+            phase.as_str()
+        );
+        let (summary, timing) = self.flush_phase(phase).await;
+        self.dir.record_partial(phase, summary, timing).unwrap();
+    }
+
+    async fn flush_phase(&mut self, phase: Phase) -> (PhaseSummary, PhaseTiming) {
+        crash::clear_in_flight();
+        let (summary, timing) = self.end_phase(phase);
+        log::info!(
+            "{} phase done, writing profiles for {:?}",
+            phase.as_str(),
+            registry().names()
+        );
+        registry().flush_all(&self.dir, phase);
+        if let Some(agent) = &mut self.agent {
+            // like the metrics, the run carries on without the collector
+            match agent.send_profiles().await {
+                Ok(sent) => log::info!("sent {sent} profiles to the collector"),
+                Err(e) => log::warn!(
+                    "failed to send the {} profiles to the collector: {e}",
+                    phase.as_str()
+                ),
+            }
+        }
+
+        if let Some(metrics) = &self.metrics {
+            // the dashboards are nice to have, not worth failing the run over
+            if let Err(e) = metrics.send(self.dir.run_id(), phase, &summary).await {
+                log::warn!("failed to send {} metrics: {e}", phase.as_str());
+            }
+        }
+        (summary, timing)
+    }
+
+    /// writes everything recorded during `phase` other than its profiles
+    fn end_phase(&mut self, phase: Phase) -> (PhaseSummary, PhaseTiming) {
+        self.current_phase = None;
+        if let Some(live) = &self.live {
+            live.set_phase(None);
+        }
+        // taken before writing anything out, which isn't part of the phase
+        let (rows, data_gen) = data::take_generated();
+        let wall_us = self
+            .phase_started
+            .take()
+            .map_or(0, |started| started.elapsed().as_micros() as u64);
+        let timing = PhaseTiming {
+            started_ms: chrono::Utc::now().timestamp_millis() - (wall_us / 1000) as i64,
+            wall_us,
+            data_gen_us: data_gen.as_micros() as u64,
+            rows,
+            timed_out: false,
+        };
+        if let Some(trace) = &self.trace {
+            trace.end_phase(phase);
+        }
+        for outliers in &self.outliers {
+            outliers.set_phase(None);
+        }
+        self.timeline.end_phase(phase);
+
+        let stats = self.stats.take();
+        self.write_phase_file(phase, "stats", "txt", stats::render_text(&stats));
+        self.write_phase_file(phase, "stats", "json", stats::render_json(&stats));
+        if let Some(files) = self.files.take_report() {
+            self.write_phase_file(phase, "index_data", "txt", files.to_string());
+        }
+
+        if let Some(cache) = &self.head_cache {
+            let report = cache.take_report().to_string();
+            self.write_phase_file(phase, "head_cache", "txt", report);
+        }
+        if let Some(prefetcher) = &self.prefetcher {
+            let report = prefetcher.take_report().to_string();
+            self.write_phase_file(phase, "prefetch", "txt", report);
+        }
+        if let Some(coalescing) = &self.coalescing {
+            let report = coalescing.take_report().to_string();
+            self.write_phase_file(phase, "coalescing", "txt", report);
+        }
+        if let Some(columns) = &self.columns {
+            let report = columns.take_report().to_string();
+            self.write_phase_file(phase, "columns", "txt", report);
+        }
+
+        let summary = self.summary.take();
+        self.node_summaries.insert(phase, self.nodes.take());
+        if let Some(tenants) = &self.tenants {
+            self.tenant_summaries.insert(phase, tenants.take());
+        }
+        let lance = lance_stats::take();
+        if lance != Default::default() {
+            if !lance.agrees_with(&summary) {
+                log::warn!(
+                    "lance counted {lance:?} in the {} phase, which the requests it made \
+                     don't add up to",
+                    phase.as_str()
+                );
+            }
+            let recorded = self.dir.record_lance_counts(phase, lance);
+            self.check_written("the lance counts", recorded);
+        }
+        (summary, timing)
+    }
+
+    /// `contents` as `{phase}_{name}.{ext}`, see [`Self::write_file`]
+    fn write_phase_file(&self, phase: Phase, name: &str, ext: &str, contents: impl AsRef<[u8]>) {
+        match self.dir.output_path(phase, name, ext) {
+            Ok(path) => self.write_file(&path, contents),
+            Err(e) => self.check_written(&format!("{}_{name}.{ext}", phase.as_str()), Err(e)),
+        }
+    }
+
+    /// writes `contents` to `path`, logging rather than failing the run over
+    /// one of its files, or panicking again while a panic unwinds through
+    /// [`Run`]. once something failed to be written during a panic the rest
+    /// isn't attempted
+    fn write_file(&self, path: &std::path::Path, contents: impl AsRef<[u8]>) {
+        if std::thread::panicking() && self.write_failed.load(Ordering::Relaxed) {
+            return;
+        }
+        let written = std::fs::write(path, contents);
+        self.check_written(&path.display().to_string(), written);
+    }
+
+    fn check_written(&self, what: &str, written: std::io::Result<()>) {
+        if let Err(e) = written {
+            tracing::warn!("couldn't write {what}: {e}");
+            self.write_failed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// writes the artifacts that cover the whole run
+    fn finish(&self) {
+        if let Some(trace) = &self.trace {
+            let path = self.dir.run_file("trace.json");
+            self.check_written(&path.display().to_string(), trace.write(&path));
+        }
+        self.write_file(
+            &self.dir.run_file("timeline.svg"),
+            self.timeline.render_svg(),
+        );
+
+        let summary = summary::render(self.summary_format, self.dir.summaries());
+        print!(
+            "{}",
+            summary::render(SummaryFormat::Text, self.dir.summaries())
+        );
+        let path = self
+            .dir
+            .run_file(&format!("summary.{}", self.summary_format.ext()));
+        self.write_file(&path, summary);
+
+        let phases = summary::render_phases(
+            self.summary_format,
+            self.dir.summaries(),
+            self.dir.timings(),
+        );
+        print!(
+            "{}",
+            summary::render_phases(
+                SummaryFormat::Text,
+                self.dir.summaries(),
+                self.dir.timings()
+            )
+        );
+        let path = self
+            .dir
+            .run_file(&format!("phases.{}", self.summary_format.ext()));
+        self.write_file(&path, phases);
+
+        let path = self
+            .dir
+            .run_file(&format!("nodes.{}", self.summary_format.ext()));
+        self.write_file(
+            &path,
+            scope::render(self.summary_format, &self.node_summaries),
+        );
 
-    let record_batch = generate_data(rows, vector_dims, schema.clone()).unwrap();
+        if self.tenants.is_some() {
+            let path = self
+                .dir
+                .run_file(&format!("tenants.{}", self.summary_format.ext()));
+            self.write_file(
+                &path,
+                tenant::render(self.summary_format, &self.tenant_summaries),
+            );
+        }
+
+        let render = |format| cost::render(format, &self.prices, self.dir.summaries());
+        print!("{}", render(SummaryFormat::Text));
+        let path = self
+            .dir
+            .run_file(&format!("cost.{}", self.summary_format.ext()));
+        self.write_file(&path, render(self.summary_format));
+
+        if !self.dir.lance_counts().is_empty() {
+            let render =
+                |format| lance_stats::render(format, self.dir.summaries(), self.dir.lance_counts());
+            print!("{}", render(SummaryFormat::Text));
+            let path = self
+                .dir
+                .run_file(&format!("lance_stats.{}", self.summary_format.ext()));
+            self.write_file(&path, render(self.summary_format));
+        }
+    }
+}
+
+impl Drop for Run {
+    /// best effort at keeping what a phase recorded when a panic unwinds
+    /// through the run, the panic hook having written its profiles already
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            return;
+        }
+        let Some(phase) = self.current_phase else {
+            return;
+        };
+        // in case something replaced the hook
+        crash::write_in_flight();
+        let (summary, timing) = self.end_phase(phase);
+        // what's failing to write may be what panicked
+        if self.write_failed.load(Ordering::Relaxed) {
+            return;
+        }
+        match self.dir.record_partial(phase, summary, timing) {
+            Ok(()) => self.finish(),
+            Err(e) => tracing::warn!("couldn't record the {} phase: {e}", phase.as_str()),
+        }
+    }
+}
 
+async fn write_phase(
+    args: &Args,
+    uri: &str,
+    profile_os_wrapper: Arc<ProfilingObjectStoreWrapper>,
+) -> Dataset {
+    let schema = Arc::new(data::create_schema(args));
+
+    let record_batch = data::generate_data(args, schema, 0..args.rows as u64).unwrap();
+    write_batch(args, uri, profile_os_wrapper, record_batch).await
+}
+
+/// writes `record_batch` as the whole dataset at `uri`
+async fn write_batch(
+    args: &Args,
+    uri: &str,
+    profile_os_wrapper: Arc<ProfilingObjectStoreWrapper>,
+    record_batch: RecordBatch,
+) -> Dataset {
+    let schema = record_batch.schema();
     let reader = RecordBatchIterator::new(vec![record_batch].into_iter().map(Ok), schema);
 
     let mut write_params = WriteParams::default();
     write_params.mode = WriteMode::Overwrite;
+    if let Some(max_rows_per_file) = args.max_rows_per_file {
+        write_params.max_rows_per_file = max_rows_per_file;
+    }
     if write_params.store_params.is_none() {
         write_params.store_params = Some(ObjectStoreParams::default());
     }
     let store_params = write_params.store_params.as_mut().unwrap();
+    store_params.object_store_wrapper = Some(profile_os_wrapper);
+    store_params.storage_options = args.storage_options();
+    write_params.commit_handler = Some(commit::commit_handler(args, uri).await);
 
-    let profile_os_wrapper = Arc::new(ProfilingObjectStoreWrapper::new());
-    store_params.object_store_wrapper = Some(profile_os_wrapper.clone());
+    let ds = Dataset::write(reader, uri, Some(write_params))
+        .await
+        .unwrap();
+    track_version(&ds);
+    ds
+}
 
-    let report_timing = ReportTiming::default();
+async fn open_dataset(
+    args: &Args,
+    uri: &str,
+    profile_os_wrapper: Arc<ProfilingObjectStoreWrapper>,
+) -> Dataset {
+    let read_params = ReadParams {
+        store_options: Some(ObjectStoreParams {
+            object_store_wrapper: Some(profile_os_wrapper),
+            storage_options: args.storage_options(),
+            ..Default::default()
+        }),
+        commit_handler: Some(commit::commit_handler(args, uri).await),
+        ..Default::default()
+    };
 
-    let mut ds = Dataset::write(reader, "~/Desktop/lance_datasets/test_pprof.lance", Some(write_params)).await.unwrap();
+    let ds = DatasetBuilder::from_uri(uri)
+        .with_read_params(read_params)
+        .load()
+        .await
+        .unwrap();
+    track_version(&ds);
+    ds
+}
 
-    let params = VectorIndexParams::ivf_pq(4, 8, 2, MetricType::L2, 1);
-    ds.create_index(
-        &["vector"],
-        lance_index::IndexType::Vector,
-        None,
-        &params,
-        true
-    ).await.unwrap();
-
-    let report_builder = ReportBuilder::new(
-        &profile_os_wrapper.get_profile,
-        report_timing.clone(),
-        SampleTypes::new(vec![
-            SampleType::new(
-                "object_store_get".to_string(),
-                pprof::Unit::Count,
-            )
-        ])
-    );
-    let report = report_builder.build().unwrap();
-    let mut file = File::create("get_profile.pb").unwrap();
-    let profile = report.pprof().unwrap();
-
-    let mut content = Vec::new();
-    profile.write_to_vec(&mut content).unwrap();
-    file.write_all(&content).unwrap();
-
-    let report_builder = ReportBuilder::new(
-        &profile_os_wrapper.put_profile,
-        report_timing,
-        SampleTypes::new(vec![
-            SampleType::new(
-                "object_store_put".to_string(),
-                pprof::Unit::Count,
-            )
-        ])
-    );
-    let report = report_builder.build().unwrap();
-    let mut file = File::create("put_profile.pb").unwrap();
-    let profile = report.pprof().unwrap();
+/// labels the samples recorded from here on with the version `ds` has checked
+/// out, call after anything that commits a new version
+fn track_version(ds: &Dataset) {
+    registry().set_dataset_version(ds.version().version);
+}
+
+/// an index on `vector` and another on each `--extra-vector` column, then the
+/// `--scalar-index` ones
+async fn index_phase(args: &Args, ds: &mut Dataset) {
+    let params = index::vector_index_params(args);
+    let extra = args.extra_vector.iter().map(|extra| extra.name.as_str());
+    for column in std::iter::once("vector").chain(extra) {
+        ds.create_index(
+            &[column],
+            lance_index::IndexType::Vector,
+            None,
+            &params,
+            true,
+        )
+        .await
+        .unwrap();
+    }
+    let params = ScalarIndexParams::default();
+    for column in &args.scalar_index {
+        ds.create_index(
+            &[column.as_str()],
+            args.scalar_index_type.into(),
+            None,
+            &params,
+            true,
+        )
+        .await
+        .unwrap();
+    }
+}
+
+/// a whole knn search, so the time spent searching sits next to the requests
+/// the searches made
+struct Search;
+
+impl ProfiledOp for Search {
+    fn name(&self) -> &'static str {
+        "search"
+    }
 
-    let mut content = Vec::new();
-    profile.write_to_vec(&mut content).unwrap();
-    file.write_all(&content).unwrap();
+    fn value_type(&self) -> (&'static str, &'static str) {
+        ("latency", "nanoseconds")
+    }
+}
 
+/// runs the queries, returning how many ran
+async fn query_phase(args: &Args, ds: &Dataset) -> usize {
+    knn_load(args, ds, None).await
 }
 
+/// runs the queries with `filter` applied before the search, so the scalar
+/// index is read to pick the rows and the vector index only searched for
+/// those
+async fn prefilter_phase(args: &Args, ds: &Dataset, filter: &str) -> usize {
+    knn_load(args, ds, Some(filter)).await
+}
 
-fn create_schema(vector_dims: i32) -> Schema {
-    let fields = vec![Field::new(
-        "vector",
-        DataType::FixedSizeList(
-            Arc::new(Field::new("item", DataType::Float32, true)),
-            vector_dims,
-        ),
-        false,
+/// the searches of the query phase, prefiltered by `filter` if there is one
+async fn knn_load(args: &Args, ds: &Dataset, filter: Option<&str>) -> usize {
+    let mut rng = match args.query_seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    // a pool per vector column, with `--extra-vector` the queries are spread
+    // evenly over the columns
+    let mut pools = vec![(
+        "vector".to_string(),
+        (0..args.query_pool)
+            .map(|_| data::query_vector(args, &mut rng))
+            .collect::<Vec<_>>(),
     )];
+    for extra in &args.extra_vector {
+        let pool = (0..args.query_pool)
+            .map(|_| data::extra_query_vector(args, extra, &mut rng))
+            .collect();
+        pools.push((extra.name.clone(), pool));
+    }
+    let sampler = KeySampler::new(args, args.query_pool as u64);
+    let ds = ds.clone();
+    let query_args = args.clone();
+    let filter = filter.map(str::to_string);
+    let node = if filter.is_some() { "prefilter" } else { "knn" };
+
+    load::drive(args, args.queries, move || {
+        let mut rng = rand::thread_rng();
+        let (column, pool) = &pools[rng.gen_range(0..pools.len())];
+        let i = sampler.sample(&mut rng) as usize;
+        let mut scanner = knn_scanner(&query_args, &ds, column, pool[i].as_ref());
+        if let Some(filter) = &filter {
+            prefilter(&mut scanner, filter);
+        }
+        // which of the pool's vectors was searched for
+        let mut labels = Labels::default().num("query", i as i64);
+        if pools.len() > 1 {
+            labels = labels.str("column", column.clone());
+        }
+        async move {
+            let started = Instant::now();
+            scope::with_labels(labels, scope::in_node(node, scanner.try_into_batch()))
+                .await
+                .unwrap();
+            registry().record(&Search, started.elapsed().as_nanos() as i64);
+        }
+    })
+    .await
+}
 
-    Schema::new(fields)
+fn knn_scanner(args: &Args, ds: &Dataset, column: &str, query: &dyn Array) -> Scanner {
+    let mut scanner = ds.scan();
+    scanner
+        .nearest(column, query, args.k)
+        .unwrap()
+        .distance_metric(index::metric_type(args));
+    lance_stats::track(&mut scanner);
+    scanner
 }
 
-fn generate_data(rows: i32, vector_dims: i32, schema: Arc<Schema>) -> Result<RecordBatch> {
-    let mut rng = rand::thread_rng();
-    let vector_data = Float32Array::from_iter_values(
-        repeat_with(|| rng.gen::<f32>()).take((vector_dims * rows) as usize),
+/// filters the rows `scanner` searches by `filter` before searching them
+fn prefilter(scanner: &mut Scanner, filter: &str) {
+    scanner.filter(filter).unwrap().prefilter(true);
+}
+
+/// writes the plan lance picked for `scanner`, both as planned and analyzed
+/// (that is, run once more with its metrics), next to the profiles of `phase`
+async fn write_plan(run: &Run, phase: Phase, scanner: &Scanner) {
+    let explained = scanner.explain_plan(true).await.unwrap();
+    let analyzed = scanner.analyze_plan().await.unwrap();
+    let path = run.dir.output_path(phase, "plan", "txt").unwrap();
+    std::fs::write(
+        path,
+        format!("physical plan:\n{explained}\n\nanalyzed plan:\n{analyzed}\n"),
+    )
+    .unwrap();
+}
+
+/// queries a freshly opened dataset, so nothing is cached yet, then queries
+/// it again and writes how the two compare
+async fn cold_warm_phases(
+    args: &Args,
+    uri: &str,
+    profile_os_wrapper: &Arc<ProfilingObjectStoreWrapper>,
+    run: &mut Run,
+) -> Result<(), Aborted> {
+    let ds = open_dataset(args, uri, profile_os_wrapper.clone()).await;
+    for phase in [Phase::QueryCold, Phase::QueryWarm] {
+        if !run.dir.is_complete(phase) {
+            run.run_phase(phase, query_phase(args, &ds)).await?;
+        }
+    }
+
+    let summaries = run.dir.summaries();
+    let empty = Default::default();
+    let delta = summary::render_delta(
+        run.summary_format,
+        (
+            Phase::QueryCold,
+            summaries.get(&Phase::QueryCold).unwrap_or(&empty),
+        ),
+        (
+            Phase::QueryWarm,
+            summaries.get(&Phase::QueryWarm).unwrap_or(&empty),
+        ),
     );
+    print!("{delta}");
+    let path = run
+        .dir
+        .run_file(&format!("cold_warm.{}", run.summary_format.ext()));
+    std::fs::write(path, delta).unwrap();
+    Ok(())
+}
 
-    let vectors = Arc::new(
-        <arrow_array::FixedSizeListArray as FixedSizeListArrayExt>::try_new_from_values(
-            vector_data,
-            vector_dims,
-        )
-        .unwrap(),
+/// queries against a freshly opened dataset, unless `--cold-warm` already
+/// did, then against another after priming its index cache, and the deltas
+/// between the two
+async fn prime_phases(
+    args: &Args,
+    uri: &str,
+    profile_os_wrapper: &Arc<ProfilingObjectStoreWrapper>,
+    run: &mut Run,
+) -> Result<(), Aborted> {
+    if !run.dir.is_complete(Phase::QueryCold) {
+        let ds = open_dataset(args, uri, profile_os_wrapper.clone()).await;
+        run.run_phase(Phase::QueryCold, query_phase(args, &ds))
+            .await?;
+    }
+    let ds = open_dataset(args, uri, profile_os_wrapper.clone()).await;
+    // priming without the queries after it would leave nothing to compare
+    if !run.dir.is_complete(Phase::QueryPrimed) {
+        run.run_phase(Phase::IndexPrime, prime_phase(args, &ds))
+            .await?;
+        run.run_phase(Phase::QueryPrimed, query_phase(args, &ds))
+            .await?;
+    }
+
+    let summaries = run.dir.summaries();
+    let empty = Default::default();
+    let delta = summary::render_delta(
+        run.summary_format,
+        (
+            Phase::QueryCold,
+            summaries.get(&Phase::QueryCold).unwrap_or(&empty),
+        ),
+        (
+            Phase::QueryPrimed,
+            summaries.get(&Phase::QueryPrimed).unwrap_or(&empty),
+        ),
     );
+    print!("{delta}");
+    let path = run
+        .dir
+        .run_file(&format!("prime.{}", run.summary_format.ext()));
+    std::fs::write(path, delta).unwrap();
+    Ok(())
+}
 
-    Ok(RecordBatch::try_new(schema, vec![vectors])?)
+/// loads the index metadata, then searches each vector column once probing
+/// every partition, which leaves all of them in the index cache as far as it
+/// fits them
+async fn prime_phase(args: &Args, ds: &Dataset) {
+    scope::in_node("prime", ds.load_indices()).await.unwrap();
+    let mut rng = rand::thread_rng();
+    let mut columns = vec![("vector", data::query_vector(args, &mut rng))];
+    for extra in &args.extra_vector {
+        columns.push((
+            extra.name.as_str(),
+            data::extra_query_vector(args, extra, &mut rng),
+        ));
+    }
+    for (column, query) in columns {
+        let mut scanner = knn_scanner(args, ds, column, query.as_ref());
+        scanner.nprobs(args.num_partitions);
+        scope::in_node("prime", scanner.try_into_batch())
+            .await
+            .unwrap();
+    }
 }
 
-struct ProfilingObjectStoreWrapper {
-    get_profile: Arc<RwLock<pprof::Result<Profiler>>>,
-    put_profile: Arc<RwLock<pprof::Result<Profiler>>>,
+async fn take_phase(args: &Args, ds: &Dataset) {
+    let mut rng = rand::thread_rng();
+    let rows = ds.count_rows(None).await.unwrap() as u64;
+    if rows == 0 {
+        log::warn!("the dataset is empty, there's nothing to take");
+        return;
+    }
+    let sampler = KeySampler::new(args, rows);
+
+    for _ in 0..args.takes {
+        let indices = (0..args.take_size)
+            .map(|_| sampler.sample(&mut rng))
+            .collect::<Vec<_>>();
+        scope::in_node("take", ds.take(&indices, ds.schema().clone()))
+            .await
+            .unwrap();
+    }
 }
 
-impl std::fmt::Debug for ProfilingObjectStoreWrapper {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("ProfilingObjectStoreWrapper{}")?; // TODO?
-        Ok(())
+async fn scan_phase(args: &Args, ds: &Dataset) {
+    for _ in 0..args.scans {
+        let (scanner, node) = scan_scanner(args, ds);
+
+        // stream rather than collect, blob columns can be much bigger than memory
+        scope::in_node(node, async {
+            let mut stream = scanner.try_into_stream().await.unwrap();
+            while stream.try_next().await.unwrap().is_some() {}
+        })
+        .await;
     }
 }
 
-impl ProfilingObjectStoreWrapper {
-    fn new() -> Self {
-        Self {
-            // TODO is this a dumb way to initialize this? at least rethink it
-            // TODO no unwrap
-            get_profile: Arc::new(RwLock::new(Ok(Profiler::new().unwrap()))),
-            put_profile: Arc::new(RwLock::new(Ok(Profiler::new().unwrap()))),
-        }
+/// the scanner the scan phase runs, with the operator it's attributed to
+fn scan_scanner(args: &Args, ds: &Dataset) -> (Scanner, &'static str) {
+    let mut scanner = ds.scan();
+    if let Some(columns) = &args.scan_columns {
+        scanner
+            .project(&columns.split(',').map(str::trim).collect::<Vec<_>>())
+            .unwrap();
     }
+    if let Some(batch_size) = args.scan_batch_size {
+        scanner.batch_size(batch_size);
+    }
+    if let Some(readahead) = args.fragment_readahead {
+        scanner.fragment_readahead(readahead);
+    }
+    let mut node = "scan";
+    if let Some(filter) = &args.scan_filter {
+        scanner.filter(filter).unwrap();
+        node = "filter";
+    }
+    lance_stats::track(&mut scanner);
+    (scanner, node)
 }
 
-impl WrappingObjectStore for ProfilingObjectStoreWrapper {
-    fn wrap(&self, original: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
-        println!("wrapping the object store");
-        Arc::new(ProfilingObjectStore {
-            inner: original,
-            get_profiler: self.get_profile.clone(),
-            put_profiler: self.put_profile.clone(),
+async fn time_range_phase(args: &Args, ds: &Dataset) {
+    let mut rng = rand::thread_rng();
+    let rows = ds.count_rows(None).await.unwrap() as u64;
+    let width = time_range_width(args, rows);
+    // ranges start at a sampled row, so with a skewed distribution the hot
+    // ranges are the oldest ones
+    let sampler = KeySampler::new(args, rows.saturating_sub(width).max(1));
+
+    for _ in 0..args.time_range_queries {
+        let scanner = time_range_scanner(ds, sampler.sample(&mut rng), width);
+        scope::in_node("filter", async {
+            let mut stream = scanner.try_into_stream().await.unwrap();
+            while stream.try_next().await.unwrap().is_some() {}
         })
+        .await;
+    }
+}
+
+async fn append_rows(args: &Args, ds: &mut Dataset) {
+    let rows = ds.count_rows(None).await.unwrap() as u64;
+    // a resumed run may have appended them already
+    if rows > args.rows as u64 {
+        return;
     }
-}
\ No newline at end of file
+
+    append_range(args, ds, rows..rows + args.append_rows).await;
+}
+
+/// appends the rows with the given ids to `ds`
+async fn append_range(args: &Args, ds: &mut Dataset, ids: Range<u64>) {
+    let schema = Arc::new(data::create_schema(args));
+    let batch = data::generate_data(args, schema.clone(), ids).unwrap();
+    let reader = RecordBatchIterator::new(vec![batch].into_iter().map(Ok), schema);
+    ds.append(reader, None).await.unwrap();
+    track_version(ds);
+}
+
+async fn optimize_index_phase(args: &Args, ds: &mut Dataset) {
+    let options = OptimizeOptions {
+        num_indices_to_merge: args.merge_indices,
+        ..Default::default()
+    };
+    ds.optimize_indices(&options).await.unwrap();
+    track_version(ds);
+}
+
+const EVOLVED_COLUMN: &str = "evolved";
+
+async fn evolve_phase(args: &Args, ds: &mut Dataset) {
+    // a resumed run may have got as far as adding the column last time
+    if ds.schema().field(EVOLVED_COLUMN).is_some() {
+        ds.drop_columns(&[EVOLVED_COLUMN]).await.unwrap();
+    }
+
+    ds.add_columns(
+        NewColumnTransform::SqlExpressions(vec![(
+            EVOLVED_COLUMN.to_string(),
+            args.add_column_expr.clone(),
+        )]),
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+    track_version(ds);
+    ds.alter_columns(&[ColumnAlteration::new(EVOLVED_COLUMN.to_string()).set_nullable(true)])
+        .await
+        .unwrap();
+    track_version(ds);
+}
+
+async fn create_versions(args: &Args, ds: &mut Dataset) {
+    let rows = ds.count_rows(None).await.unwrap();
+    for i in 0..args.cleanup_versions.min(rows) {
+        ds.delete(&format!("id = {}", rows - 1 - i)).await.unwrap();
+        track_version(ds);
+    }
+}
+
+async fn cleanup_phase(ds: &Dataset) {
+    let stats = ds
+        .cleanup_old_versions(chrono::Duration::zero(), Some(true), None)
+        .await
+        .unwrap();
+    log::info!(
+        "removed {} old versions, {} bytes",
+        stats.old_versions,
+        stats.bytes_removed
+    );
+}
+
+/// rewrites only the first `--rewrite-tasks` tasks of a compaction plan, as
+/// opposed to `compact_files` which would rewrite everything it plans
+async fn rewrite_phase(args: &Args, ds: &mut Dataset) {
+    let options = CompactionOptions {
+        // every fragment smaller than the whole dataset is a candidate
+        target_rows_per_fragment: args.rows as usize,
+        materialize_deletions_threshold: 0.0,
+        ..Default::default()
+    };
+    let plan = plan_compaction(ds, &options).await.unwrap();
+    log::info!("compaction plan has {} tasks", plan.num_tasks());
+
+    let mut results = Vec::new();
+    for task in plan.compaction_tasks().take(args.rewrite_tasks) {
+        results.push(task.execute(ds).await.unwrap());
+    }
+    if results.is_empty() {
+        return;
+    }
+    commit_compaction(
+        ds,
+        results,
+        Arc::new(DatasetIndexRemapperOptions::default()),
+        &options,
+    )
+    .await
+    .unwrap();
+    track_version(ds);
+}
+
+fn time_range_width(args: &Args, rows: u64) -> u64 {
+    ((rows as f64 * args.time_range_fraction) as u64).max(1)
+}
+
+/// scans the `width` rows starting at row `start`, by their timestamps
+fn time_range_scanner(ds: &Dataset, start: u64, width: u64) -> Scanner {
+    let filter = format!(
+        "ts >= {} AND ts < {}",
+        timestamp_literal(data::row_timestamp(start)),
+        timestamp_literal(data::row_timestamp(start + width)),
+    );
+    let mut scanner = ds.scan();
+    scanner.filter(&filter).unwrap();
+    lance_stats::track(&mut scanner);
+    scanner
+}
+
+fn timestamp_literal(micros: i64) -> String {
+    let ts = chrono::DateTime::from_timestamp_micros(micros).unwrap();
+    format!("TIMESTAMP '{}'", ts.format("%Y-%m-%d %H:%M:%S%.6f"))
+}
@@ -0,0 +1,417 @@
+//! `pprof-dev merge`: several pprof profiles combined into one, e.g. the same
+//! phase's profiles from every worker of a distributed run, to see the IO of
+//! the whole fleet. Samples with the same stack and labels are summed, each
+//! value with the values of the same sample type in the other profiles.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cli::MergeArgs;
+
+#[cfg(feature = "profiling")]
+pub use merged::merge_profiles;
+
+pub fn write_merge(args: &MergeArgs) -> io::Result<()> {
+    merge_files(&args.profiles, &args.output)?;
+    println!("wrote {}", args.output.display());
+    Ok(())
+}
+
+/// merges the profiles at `paths` into one at `output`
+#[cfg(feature = "profiling")]
+pub fn merge_files(paths: &[PathBuf], output: &Path) -> io::Result<()> {
+    let profiles = paths
+        .iter()
+        .map(|path| crate::flamegraph::read_profile(path))
+        .collect::<io::Result<Vec<_>>>()?;
+    let merged = merge_profiles(&profiles)?;
+    let comments = [format!("merged from {} profiles", profiles.len())];
+    crate::profiling::write_profile(merged, &comments, output)
+}
+
+#[cfg(not(feature = "profiling"))]
+pub fn merge_files(_paths: &[PathBuf], _output: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "built without the profiling feature, can't read profiles",
+    ))
+}
+
+#[cfg(feature = "profiling")]
+mod merged {
+    use std::collections::HashMap;
+    use std::io;
+
+    use pprof::protos::{Function, Label, Line, Location, Profile, Sample, ValueType};
+
+    /// A sample's labels, by their strings rather than a profile's indices.
+    type LabelKey = Vec<(String, String, i64, String)>;
+
+    /// Builds the merged profile, interning everything the inputs refer to by
+    /// index or id anew.
+    #[derive(Default)]
+    struct Merger {
+        profile: Profile,
+        strings: HashMap<String, i64>,
+        functions: HashMap<(i64, i64, i64, i64), u64>,
+        mappings: HashMap<(u64, u64, u64, i64, i64), u64>,
+        locations: HashMap<(u64, u64, Vec<(u64, i64)>, bool), u64>,
+        samples: HashMap<(Vec<u64>, LabelKey), usize>,
+    }
+
+    impl Merger {
+        fn new() -> Self {
+            let mut merger = Self::default();
+            // pprof requires the first string to be the empty one
+            merger.intern("");
+            merger
+        }
+
+        fn intern(&mut self, s: &str) -> i64 {
+            if let Some(&i) = self.strings.get(s) {
+                return i;
+            }
+            let i = self.profile.string_table.len() as i64;
+            self.profile.string_table.push(s.to_string());
+            self.strings.insert(s.to_string(), i);
+            i
+        }
+
+        /// the index of the sample type `value_type` of `from` is merged into,
+        /// added if the profiles before didn't have it
+        fn sample_type(&mut self, from: &Profile, value_type: &ValueType) -> io::Result<usize> {
+            let name = &from.string_table[value_type.field_type as usize];
+            let unit = &from.string_table[value_type.unit as usize];
+            let existing = self
+                .profile
+                .sample_type
+                .iter()
+                .position(|t| &self.profile.string_table[t.field_type as usize] == name);
+            if let Some(i) = existing {
+                let existing =
+                    &self.profile.string_table[self.profile.sample_type[i].unit as usize];
+                if existing != unit {
+                    return Err(io::Error::other(format!(
+                        "can't merge {name} in {unit} with {name} in {existing}"
+                    )));
+                }
+                return Ok(i);
+            }
+            let mut merged = ValueType::new();
+            merged.field_type = self.intern(name);
+            merged.unit = self.intern(unit);
+            self.profile.sample_type.push(merged);
+            // the samples merged so far had none of it
+            for sample in self.profile.sample.iter_mut() {
+                sample.value.push(0);
+            }
+            Ok(self.profile.sample_type.len() - 1)
+        }
+
+        fn add(&mut self, from: &Profile) -> io::Result<()> {
+            let string = |i: i64| from.string_table[i as usize].as_str();
+            let values = from
+                .sample_type
+                .iter()
+                .map(|value_type| self.sample_type(from, value_type))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            let mut function_ids = HashMap::new();
+            for function in &from.function {
+                let key = (
+                    self.intern(string(function.name)),
+                    self.intern(string(function.system_name)),
+                    self.intern(string(function.filename)),
+                    function.start_line,
+                );
+                let next = self.profile.function.len() as u64 + 1;
+                let id = *self.functions.entry(key).or_insert(next);
+                if id == next {
+                    let mut merged = Function::new();
+                    merged.id = id;
+                    (merged.name, merged.system_name, merged.filename) = (key.0, key.1, key.2);
+                    merged.start_line = key.3;
+                    self.profile.function.push(merged);
+                }
+                function_ids.insert(function.id, id);
+            }
+
+            let mut mapping_ids = HashMap::new();
+            for mapping in &from.mapping {
+                let key = (
+                    mapping.memory_start,
+                    mapping.memory_limit,
+                    mapping.file_offset,
+                    self.intern(string(mapping.filename)),
+                    self.intern(string(mapping.build_id)),
+                );
+                let next = self.profile.mapping.len() as u64 + 1;
+                let id = *self.mappings.entry(key).or_insert(next);
+                if id == next {
+                    let mut merged = mapping.clone();
+                    merged.id = id;
+                    (merged.filename, merged.build_id) = (key.3, key.4);
+                    self.profile.mapping.push(merged);
+                }
+                mapping_ids.insert(mapping.id, id);
+            }
+
+            let mut location_ids = HashMap::new();
+            for location in &from.location {
+                let lines: Vec<_> = location
+                    .line
+                    .iter()
+                    .map(|line| (function_ids[&line.function_id], line.line))
+                    .collect();
+                let mapping_id = mapping_ids.get(&location.mapping_id).copied().unwrap_or(0);
+                let key = (mapping_id, location.address, lines, location.is_folded);
+                let next = self.profile.location.len() as u64 + 1;
+                let id = *self.locations.entry(key.clone()).or_insert(next);
+                if id == next {
+                    let mut merged = Location::new();
+                    merged.id = id;
+                    merged.mapping_id = mapping_id;
+                    merged.address = location.address;
+                    merged.is_folded = location.is_folded;
+                    merged.line = key
+                        .2
+                        .iter()
+                        .map(|&(function_id, number)| {
+                            let mut line = Line::new();
+                            line.function_id = function_id;
+                            line.line = number;
+                            line
+                        })
+                        .collect();
+                    self.profile.location.push(merged);
+                }
+                location_ids.insert(location.id, id);
+            }
+
+            for sample in &from.sample {
+                let stack: Vec<u64> = sample
+                    .location_id
+                    .iter()
+                    .map(|id| location_ids[id])
+                    .collect();
+                let mut labels: LabelKey = sample
+                    .label
+                    .iter()
+                    .map(|label| {
+                        (
+                            string(label.key).to_string(),
+                            string(label.str).to_string(),
+                            label.num,
+                            string(label.num_unit).to_string(),
+                        )
+                    })
+                    .collect();
+                labels.sort();
+                let i = match self.samples.get(&(stack.clone(), labels.clone())) {
+                    Some(&i) => i,
+                    None => {
+                        let mut merged = Sample::new();
+                        merged.location_id = stack.clone();
+                        merged.value = vec![0; self.profile.sample_type.len()];
+                        for (key, str, num, num_unit) in &labels {
+                            let mut label = Label::new();
+                            label.key = self.intern(key);
+                            label.str = self.intern(str);
+                            label.num = *num;
+                            label.num_unit = self.intern(num_unit);
+                            merged.label.push(label);
+                        }
+                        self.profile.sample.push(merged);
+                        self.samples
+                            .insert((stack, labels), self.profile.sample.len() - 1);
+                        self.profile.sample.len() - 1
+                    }
+                };
+                for (value, &merged) in sample.value.iter().zip(&values) {
+                    self.profile.sample[i].value[merged] += value;
+                }
+            }
+
+            for &comment in &from.comment {
+                let comment = self.intern(string(comment));
+                if !self.profile.comment.contains(&comment) {
+                    self.profile.comment.push(comment);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// `profiles` combined into one, their samples with the same stack and
+    /// labels summed. the sample types are the first profile's followed by
+    /// any the others add, and a sample type in two profiles has to have the
+    /// same unit in both. the time span covers all of them
+    pub fn merge_profiles(profiles: &[Profile]) -> io::Result<Profile> {
+        let Some(first) = profiles.first() else {
+            return Err(io::Error::other("no profiles to merge"));
+        };
+        let mut merger = Merger::new();
+        for profile in profiles {
+            merger.add(profile)?;
+        }
+
+        let string = |i: i64| first.string_table[i as usize].as_str();
+        if let Some(period_type) = first.period_type.as_ref() {
+            let mut value_type = ValueType::new();
+            value_type.field_type = merger.intern(string(period_type.field_type));
+            value_type.unit = merger.intern(string(period_type.unit));
+            merger.profile.period_type = Some(value_type).into();
+        }
+        merger.profile.period = first.period;
+        merger.profile.default_sample_type = merger.intern(string(first.default_sample_type));
+
+        let mut merged = merger.profile;
+        let started = profiles
+            .iter()
+            .map(|p| p.time_nanos)
+            .filter(|&t| t > 0)
+            .min();
+        let ended = profiles
+            .iter()
+            .map(|p| p.time_nanos + p.duration_nanos)
+            .max();
+        if let (Some(started), Some(ended)) = (started, ended) {
+            merged.time_nanos = started;
+            merged.duration_nanos = ended - started;
+        }
+        Ok(merged)
+    }
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod tests {
+    use pprof::protos::{Function, Line, Location, Profile, Sample, ValueType};
+
+    use super::merge_profiles;
+    use crate::testing::totals;
+
+    const SAMPLE_TYPES: &[(&str, &str)] = &[("requests", "count"), ("bytes", "bytes")];
+
+    fn intern(profile: &mut Profile, s: &str) -> i64 {
+        match profile.string_table.iter().position(|t| t == s) {
+            Some(i) => i as i64,
+            None => {
+                profile.string_table.push(s.to_string());
+                profile.string_table.len() as i64 - 1
+            }
+        }
+    }
+
+    /// a profile of `samples`, each a stack of function names, leaf first,
+    /// and its values. strings and functions are numbered in the order they
+    /// first come up, so profiles of different samples number them
+    /// differently
+    fn profile(sample_types: &[(&str, &str)], samples: &[(&[&str], &[i64])]) -> Profile {
+        let mut profile = Profile::new();
+        profile.string_table.push(String::new());
+        for (name, unit) in sample_types {
+            let mut sample_type = ValueType::new();
+            sample_type.field_type = intern(&mut profile, name);
+            sample_type.unit = intern(&mut profile, unit);
+            profile.sample_type.push(sample_type);
+        }
+        for (stack, values) in samples {
+            let mut sample = Sample::new();
+            sample.value = values.to_vec();
+            for name in *stack {
+                let name = intern(&mut profile, name);
+                let id = match profile.function.iter().find(|f| f.name == name) {
+                    Some(function) => function.id,
+                    None => {
+                        // a location per function, with the same id
+                        let id = profile.function.len() as u64 + 1;
+                        let mut function = Function::new();
+                        (function.id, function.name) = (id, name);
+                        profile.function.push(function);
+                        let mut line = Line::new();
+                        line.function_id = id;
+                        let mut location = Location::new();
+                        location.id = id;
+                        location.line.push(line);
+                        profile.location.push(location);
+                        id
+                    }
+                };
+                sample.location_id.push(id);
+            }
+            profile.sample.push(sample);
+        }
+        profile
+    }
+
+    /// the function names of each sample's stack, leaf first, and its values
+    fn samples(profile: &Profile) -> Vec<(Vec<String>, Vec<i64>)> {
+        let strings = &profile.string_table;
+        let function = |id: u64| {
+            let function = profile.function.iter().find(|f| f.id == id).unwrap();
+            strings[function.name as usize].clone()
+        };
+        let location = |id: u64| profile.location.iter().find(|l| l.id == id).unwrap();
+        let mut samples: Vec<_> = profile
+            .sample
+            .iter()
+            .map(|sample| {
+                let stack = sample
+                    .location_id
+                    .iter()
+                    .flat_map(|&id| location(id).line.iter())
+                    .map(|line| function(line.function_id))
+                    .collect();
+                (stack, sample.value.clone())
+            })
+            .collect();
+        samples.sort();
+        samples
+    }
+
+    #[test]
+    fn overlapping_tables_are_remapped_and_summed() {
+        let scan = profile(SAMPLE_TYPES, &[(&["read", "scan"], &[1, 10])]);
+        // take is function 1 here, and read's name a different string
+        let take = profile(
+            SAMPLE_TYPES,
+            &[(&["take", "read"], &[2, 20]), (&["read", "scan"], &[3, 30])],
+        );
+        let merged = merge_profiles(&[scan, take]).unwrap();
+
+        assert_eq!(
+            samples(&merged),
+            [
+                (vec!["read".to_string(), "scan".to_string()], vec![4, 40]),
+                (vec!["take".to_string(), "read".to_string()], vec![2, 20]),
+            ]
+        );
+        assert_eq!(totals(&merged), [6, 60]);
+        let functions: Vec<_> = merged
+            .function
+            .iter()
+            .map(|f| (f.id, merged.string_table[f.name as usize].as_str()))
+            .collect();
+        assert_eq!(functions, [(1, "read"), (2, "scan"), (3, "take")]);
+        assert_eq!(merged.location.len(), 3);
+        let mut strings = merged.string_table.clone();
+        strings.sort();
+        strings.dedup();
+        assert_eq!(
+            strings.len(),
+            merged.string_table.len(),
+            "strings interned twice"
+        );
+    }
+
+    #[test]
+    fn sample_types_of_different_units_are_rejected() {
+        let requests = profile(&[("bytes", "bytes")], &[(&["read"], &[10])]);
+        let counted = profile(&[("bytes", "count")], &[(&["read"], &[1])]);
+        let e = merge_profiles(&[requests, counted]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "can't merge bytes in count with bytes in bytes"
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! What produced a set of results: this binary's version and commit, and the
+//! versions of the dependencies whose IO is being profiled. The commit and
+//! dependency versions are captured by `build.rs`, the commit with a `-dirty`
+//! suffix if the binary was built with uncommitted changes.
+
+pub const GIT_SHA: &str = env!("PPROF_DEV_GIT_SHA");
+pub const LANCE_VERSION: &str = env!("PPROF_DEV_LANCE_VERSION");
+pub const LANCE_INDEX_VERSION: &str = env!("PPROF_DEV_LANCE_INDEX_VERSION");
+pub const OBJECT_STORE_VERSION: &str = env!("PPROF_DEV_OBJECT_STORE_VERSION");
+
+pub fn build_info() -> [(&'static str, &'static str); 5] {
+    [
+        ("pprof_dev", env!("CARGO_PKG_VERSION")),
+        ("git_sha", GIT_SHA),
+        ("lance", LANCE_VERSION),
+        ("lance_index", LANCE_INDEX_VERSION),
+        ("object_store", OBJECT_STORE_VERSION),
+    ]
+}
+
+/// [`build_info`] as `key=value` pairs on one line
+pub fn describe() -> String {
+    build_info()
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
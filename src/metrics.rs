@@ -0,0 +1,154 @@
+//! Per-phase aggregate metrics pushed to an external dashboard as influx line
+//! protocol or graphite plaintext, over udp, tcp or http.
+
+use std::fmt::Write as _;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use url::Url;
+
+use crate::run::Phase;
+use crate::summary::PhaseSummary;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const MEASUREMENT: &str = "pprof_dev";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsFormat {
+    Influx,
+    Graphite,
+}
+
+// for the PPROF_DEV_METRICS_FORMAT override
+impl FromStr for MetricsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+pub struct MetricsExporter {
+    url: Url,
+    format: MetricsFormat,
+}
+
+impl MetricsExporter {
+    /// `url` is `udp://host:port`, `tcp://host:port` or an http(s) write
+    /// endpoint such as `http://localhost:8086/api/v2/write?bucket=bench`
+    pub fn new(url: &str, format: MetricsFormat) -> Result<Self, Error> {
+        let url = Url::parse(url)?;
+        match url.scheme() {
+            "udp" | "tcp" | "http" | "https" => Ok(Self { url, format }),
+            scheme => Err(format!("unsupported metrics sink scheme {scheme}").into()),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        run_id: &str,
+        phase: Phase,
+        summary: &PhaseSummary,
+    ) -> Result<(), Error> {
+        let lines = render(self.format, run_id, phase, summary, SystemTime::now());
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        match self.url.scheme() {
+            "udp" => {
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(self.socket_addr()?).await?;
+                // a datagram per line keeps each one well under the mtu
+                for line in &lines {
+                    socket.send(format!("{line}\n").as_bytes()).await?;
+                }
+            }
+            "tcp" => {
+                let mut stream = TcpStream::connect(self.socket_addr()?).await?;
+                stream.write_all(lines.join("\n").as_bytes()).await?;
+                stream.write_all(b"\n").await?;
+                stream.shutdown().await?;
+            }
+            _ => {
+                reqwest::Client::new()
+                    .post(self.url.clone())
+                    .body(lines.join("\n"))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn socket_addr(&self) -> Result<String, Error> {
+        let host = self.url.host_str().ok_or("metrics sink url has no host")?;
+        let port = self.url.port().ok_or("metrics sink url has no port")?;
+        Ok(format!("{host}:{port}"))
+    }
+}
+
+fn render(
+    format: MetricsFormat,
+    run_id: &str,
+    phase: Phase,
+    summary: &PhaseSummary,
+    now: SystemTime,
+) -> Vec<String> {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut lines = vec![];
+    for (op, s) in summary {
+        let fields = [
+            ("count", s.count),
+            ("bytes", s.bytes),
+            ("errors", s.errors),
+            ("p50_us", s.p50_us),
+            ("p99_us", s.p99_us),
+        ];
+        match format {
+            MetricsFormat::Influx => {
+                let mut line = format!(
+                    "{MEASUREMENT},run_id={},phase={},op={op} ",
+                    escape_influx_tag(run_id),
+                    phase.as_str()
+                );
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(line, "{sep}{name}={value}i").unwrap();
+                }
+                write!(line, " {}", since_epoch.as_nanos()).unwrap();
+                lines.push(line);
+            }
+            MetricsFormat::Graphite => {
+                // tagged series, so the run id doesn't end up in the metric path
+                for (name, value) in fields {
+                    lines.push(format!(
+                        "{MEASUREMENT}.{name};run_id={};phase={};op={op} {value} {}",
+                        escape_graphite_tag(run_id),
+                        phase.as_str(),
+                        since_epoch.as_secs()
+                    ));
+                }
+            }
+        }
+    }
+    lines
+}
+
+fn escape_influx_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn escape_graphite_tag(value: &str) -> String {
+    value.replace([';', '~', ' '], "_")
+}
@@ -0,0 +1,205 @@
+//! Gets of a local dataset served from memory mapped files, enabled with
+//! `--mmap`, to compare lance's read pattern and latency against reading the
+//! same files through `LocalFileSystem`. Each file is mapped the first time
+//! it's read and stays mapped; everything other than a get, and gets with
+//! preconditions, go to the store underneath.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::ops::Range;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{self, BoxStream, StreamExt};
+use memmap2::Mmap;
+use object_store::path::Path;
+use object_store::{
+    Attributes, Error, GetOptions, GetRange, GetResult, GetResultPayload, ListResult,
+    MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+    Result,
+};
+use parking_lot::Mutex;
+
+const STORE: &str = "mmap";
+
+/// Object store mapping the files `inner` keeps under `/`, as lance's
+/// `file-object-store://` stores do. anything that writes to a path unmaps it
+#[derive(Debug)]
+pub struct MmapStore {
+    inner: Arc<dyn ObjectStore>,
+    maps: Mutex<HashMap<Path, (ObjectMeta, Bytes)>>,
+}
+
+impl MmapStore {
+    pub fn new(inner: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            inner,
+            maps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// the file at `location` with its metadata, mapping it if it isn't yet
+    async fn mapped(&self, location: &Path) -> Result<(ObjectMeta, Bytes)> {
+        if let Some(mapped) = self.maps.lock().get(location) {
+            return Ok(mapped.clone());
+        }
+        let meta = self.inner.head(location).await?;
+        let path = std::path::Path::new("/").join(location.as_ref());
+        let bytes = match meta.size {
+            // there's nothing to map
+            0 => Bytes::new(),
+            _ => tokio::task::spawn_blocking(move || {
+                let file = File::open(path)?;
+                // SAFETY: lance never modifies a file it has written, it writes
+                // a new one instead
+                unsafe { Mmap::map(&file) }
+            })
+            .await
+            .unwrap()
+            .map(Bytes::from_owner)
+            .map_err(|e| Error::Generic {
+                store: STORE,
+                source: Box::new(e),
+            })?,
+        };
+        self.maps
+            .lock()
+            .insert(location.clone(), (meta.clone(), bytes.clone()));
+        Ok((meta, bytes))
+    }
+
+    fn unmap(&self, location: &Path) {
+        self.maps.lock().remove(location);
+    }
+}
+
+/// `range` of `bytes`, refusing any that runs past the end like
+/// `LocalFileSystem` does
+fn slice(location: &Path, bytes: &Bytes, range: Range<usize>) -> Result<Bytes> {
+    if range.start > range.end || range.end > bytes.len() {
+        return Err(Error::Generic {
+            store: STORE,
+            source: format!(
+                "range {range:?} is out of bounds of {location}, which is {} bytes",
+                bytes.len()
+            )
+            .into(),
+        });
+    }
+    Ok(bytes.slice(range))
+}
+
+impl Display for MmapStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MmapStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MmapStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.unmap(location);
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.unmap(location);
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let conditional = options.if_match.is_some()
+            || options.if_none_match.is_some()
+            || options.if_modified_since.is_some()
+            || options.if_unmodified_since.is_some()
+            || options.version.is_some();
+        if conditional || options.head {
+            return self.inner.get_opts(location, options).await;
+        }
+        let (meta, bytes) = self.mapped(location).await?;
+        let range = match options.range {
+            None => 0..meta.size,
+            Some(GetRange::Bounded(range)) => range.start..range.end.min(meta.size),
+            Some(GetRange::Offset(offset)) => offset..meta.size,
+            Some(GetRange::Suffix(suffix)) => meta.size.saturating_sub(suffix)..meta.size,
+        };
+        let payload = slice(location, &bytes, range.clone())?;
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(stream::once(async move { Ok(payload) }).boxed()),
+            meta,
+            range,
+            attributes: Attributes::default(),
+        })
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        let (_, bytes) = self.mapped(location).await?;
+        slice(location, &bytes, range)
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        let (_, bytes) = self.mapped(location).await?;
+        ranges
+            .iter()
+            .map(|range| slice(location, &bytes, range.clone()))
+            .collect()
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.unmap(location);
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list_with_offset(prefix, offset)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.unmap(to);
+        self.inner.copy(from, to).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.unmap(from);
+        self.unmap(to);
+        self.inner.rename(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.unmap(to);
+        self.inner.copy_if_not_exists(from, to).await
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.unmap(from);
+        self.unmap(to);
+        self.inner.rename_if_not_exists(from, to).await
+    }
+}
@@ -0,0 +1,96 @@
+//! `pprof-dev multi`: the write, index and query phases run against several
+//! datasets at once, on the same runtime and in the same bucket, to see how
+//! tenants of a shared service get in each other's way.
+
+use futures::future::join_all;
+use tracing::Instrument;
+
+use crate::cli::MultiArgs;
+use crate::hooks::Labels;
+use crate::otel;
+use crate::registry::registry;
+use crate::run::Phase;
+use crate::summary::{DatasetSummaries, SummaryFormat};
+use crate::table::Table;
+use crate::{index_phase, profiling_wrapper, query_phase, write_phase, Run};
+
+pub async fn run_multi(multi: MultiArgs) {
+    let args = multi.run;
+    let _otel = otel::Exporter::start(&args);
+    let mut run = Run::new(&args);
+    log::info!(
+        "starting run {} against {} datasets",
+        run.dir.run_id(),
+        multi.datasets
+    );
+    let base = run.dir.dataset_uri().trim_end_matches('/').to_string();
+
+    let mut summaries = DatasetSummaries::default();
+    let mut uris = vec![];
+    let mut wrappers = vec![];
+    for i in 0..multi.datasets {
+        let name = format!("dataset{i}");
+        let wrapper = profiling_wrapper(&args, &run)
+            .with_hooks(summaries.add(&name))
+            .with_labels(Labels::default().str("dataset", name.clone()));
+        wrappers.push(registry().register(&name, wrapper));
+        uris.push(format!("{base}-{i}"));
+    }
+
+    run.start_phase(Phase::Write);
+    summaries.start_phase();
+    let mut datasets = join_all(
+        uris.iter()
+            .zip(&wrappers)
+            .map(|(uri, wrapper)| write_phase(&args, uri, wrapper.clone())),
+    )
+    .instrument(Phase::Write.span())
+    .await;
+    run.finish_phase(Phase::Write).await;
+    summaries.finish_phase(Phase::Write);
+
+    run.start_phase(Phase::Index);
+    summaries.start_phase();
+    join_all(datasets.iter_mut().map(|ds| index_phase(&args, ds)))
+        .instrument(Phase::Index.span())
+        .await;
+    run.finish_phase(Phase::Index).await;
+    summaries.finish_phase(Phase::Index);
+
+    run.start_phase(Phase::Query);
+    summaries.start_phase();
+    join_all(datasets.iter().map(|ds| query_phase(&args, ds)))
+        .instrument(Phase::Query.span())
+        .await;
+    run.finish_phase(Phase::Query).await;
+    summaries.finish_phase(Phase::Query);
+
+    run.finish();
+    let table = table(&summaries);
+    let path = run.dir.run_file(&format!("datasets.{}", args.format.ext()));
+    std::fs::write(path, table.render(args.format)).unwrap();
+    print!("{}", table.render(SummaryFormat::Text));
+}
+
+fn table(summaries: &DatasetSummaries) -> Table {
+    let mut table = Table::new(&[
+        "phase", "dataset", "op", "count", "bytes", "p50_us", "p99_us", "errors",
+    ]);
+    for (phase, datasets) in summaries.phases() {
+        for (dataset, ops) in datasets {
+            for (op, s) in ops {
+                table.row(vec![
+                    phase.as_str().into(),
+                    dataset.as_str().into(),
+                    op.as_str().into(),
+                    s.count.into(),
+                    s.bytes.into(),
+                    s.p50_us.into(),
+                    s.p99_us.into(),
+                    s.errors.into(),
+                ]);
+            }
+        }
+    }
+    table
+}
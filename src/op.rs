@@ -0,0 +1,67 @@
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum ObjectStoreOp {
+    Get,
+    Put,
+    Head,
+    List,
+    Delete,
+    Copy,
+    Rename,
+}
+
+impl ObjectStoreOp {
+    pub const ALL: [ObjectStoreOp; 7] = [
+        ObjectStoreOp::Get,
+        ObjectStoreOp::Put,
+        ObjectStoreOp::Head,
+        ObjectStoreOp::List,
+        ObjectStoreOp::Delete,
+        ObjectStoreOp::Copy,
+        ObjectStoreOp::Rename,
+    ];
+
+    /// the ops the profiling store keeps a pprof profile for
+    pub const PROFILED: [ObjectStoreOp; 2] = [ObjectStoreOp::Get, ObjectStoreOp::Put];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectStoreOp::Get => "get",
+            ObjectStoreOp::Put => "put",
+            ObjectStoreOp::Head => "head",
+            ObjectStoreOp::List => "list",
+            ObjectStoreOp::Delete => "delete",
+            ObjectStoreOp::Copy => "copy",
+            ObjectStoreOp::Rename => "rename",
+        }
+    }
+
+    /// name of the sample type in this op's profile
+    pub fn sample_type(&self) -> String {
+        format!("object_store_{}", self.as_str())
+    }
+}
+
+impl fmt::Display for ObjectStoreOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// for the PPROF_DEV_DISABLE_OPS and PPROF_DEV_TOGGLE_OPS overrides
+impl FromStr for ObjectStoreOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
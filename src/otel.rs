@@ -0,0 +1,160 @@
+//! Exports tracing spans over OTLP, e.g. to Jaeger: a span per run and phase,
+//! one per object store request, and whatever lance instruments itself.
+//!
+//! The subscriber also keeps where each task was spawned, for the labels
+//! `--task-labels` puts on requests. tokio only traces its tasks when built
+//! with `RUSTFLAGS="--cfg tokio_unstable"` and its `tracing` feature.
+
+use crate::cli::Args;
+
+/// Sends spans to `--otlp-endpoint` until dropped.
+pub struct Exporter {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Exporter {
+    #[cfg(feature = "otel")]
+    pub fn start(args: &Args) -> Self {
+        Self {
+            provider: args
+                .otlp_endpoint
+                .as_deref()
+                .map(|endpoint| enabled::init(endpoint).unwrap()),
+        }
+    }
+
+    #[cfg(not(feature = "otel"))]
+    pub fn start(args: &Args) -> Self {
+        if let Some(endpoint) = &args.otlp_endpoint {
+            log::warn!("built without the otel feature, not exporting spans to {endpoint}");
+        }
+        Self {}
+    }
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        // flushes the spans still batched up
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                log::warn!("failed to flush spans: {e}");
+            }
+        }
+    }
+}
+
+/// whether the task making the current request was spawned as a `task` or
+/// with `spawn_blocking`, and where
+#[cfg(feature = "otel")]
+pub fn spawn_location() -> Option<(String, String)> {
+    enabled::spawn_location()
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn spawn_location() -> Option<(String, String)> {
+    None
+}
+
+#[cfg(feature = "otel")]
+mod enabled {
+    use std::fmt::Debug;
+
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::TracerProvider;
+    use opentelemetry_sdk::{runtime, Resource};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::{LookupSpan, Registry};
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    /// installs a subscriber sending every span to the otlp grpc `endpoint`
+    pub fn init(endpoint: &str) -> Result<TracerProvider, Error> {
+        let exporter = SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?;
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                "pprof-dev",
+            )]))
+            .build();
+
+        tracing_subscriber::registry()
+            .with(SpawnLocations)
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("pprof-dev")))
+            .try_init()?;
+        Ok(provider)
+    }
+
+    /// The kind and location of a task, kept on the `runtime.spawn` span tokio
+    /// enters whenever it polls the task.
+    #[derive(Clone)]
+    struct SpawnedAt(String, String);
+
+    struct SpawnLocations;
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpawnLocations {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            if attrs.metadata().name() != "runtime.spawn" {
+                return;
+            }
+            let mut fields = SpawnFields::default();
+            attrs.record(&mut fields);
+            if let (Some(file), Some(line)) = (fields.file, fields.line) {
+                let kind = fields.kind.unwrap_or_default();
+                if let Some(span) = ctx.span(id) {
+                    span.extensions_mut()
+                        .insert(SpawnedAt(kind, format!("{file}:{line}")));
+                }
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct SpawnFields {
+        kind: Option<String>,
+        file: Option<String>,
+        line: Option<u64>,
+    }
+
+    impl Visit for SpawnFields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "loc.file" {
+                self.file = Some(value.to_string());
+            }
+        }
+
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "loc.line" {
+                self.line = Some(value);
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            if field.name() == "kind" {
+                self.kind = Some(format!("{value:?}"));
+            }
+        }
+    }
+
+    pub fn spawn_location() -> Option<(String, String)> {
+        tracing::dispatcher::get_default(|dispatch| {
+            let registry = dispatch.downcast_ref::<Registry>()?;
+            let span = registry.span(registry.current_span().id()?)?;
+            let spawned = span
+                .scope()
+                .find_map(|span| span.extensions().get::<SpawnedAt>().cloned())?;
+            Some((spawned.0, spawned.1))
+        })
+    }
+}
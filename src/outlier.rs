@@ -0,0 +1,120 @@
+//! Requests that took longer than `--slow-request-ms` or moved more than
+//! `--huge-request-bytes`, each written to a jsonl file in the run directory
+//! with the stack it was made from. Slow requests can then be looked into one
+//! at a time rather than through aggregates, and huge ones (a whole file read
+//! because a range wasn't pushed down, say) traced to exactly what made them.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::ops::Range;
+use std::path::Path as FsPath;
+use std::time::Duration;
+
+use object_store::path::Path;
+use parking_lot::Mutex;
+use serde_json::json;
+
+use crate::hooks::{LabelValue, Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+use crate::stacks;
+
+/// What makes a request an outlier.
+#[derive(Debug, Clone, Copy)]
+pub enum Threshold {
+    Latency(Duration),
+    Bytes(usize),
+}
+
+pub struct OutlierRequests {
+    threshold: Threshold,
+    /// a line per request, written as they complete so a run that dies still
+    /// leaves the ones before it
+    file: Mutex<LineWriter<File>>,
+    phase: Mutex<Option<Phase>>,
+}
+
+impl OutlierRequests {
+    /// appends to `path`, so a resumed run keeps the requests from before
+    pub fn new(threshold: Threshold, path: &FsPath) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            threshold,
+            file: Mutex::new(LineWriter::new(file)),
+            phase: Mutex::new(None),
+        })
+    }
+
+    /// the phase requests are made in from now on, `None` between phases
+    pub fn set_phase(&self, phase: Option<Phase>) {
+        *self.phase.lock() = phase;
+    }
+}
+
+/// the stack `on_complete` was called from, without the frames recording it
+fn stack() -> Vec<String> {
+    let frames = stacks::resolve(&stacks::capture());
+    let own = stacks::own_frames(
+        &frames,
+        &[
+            "pprof_dev::outlier::",
+            "pprof_dev::hooks::",
+            "pprof_dev::scope::",
+        ],
+    );
+    frames
+        .into_iter()
+        .skip(own)
+        .flatten()
+        .map(|symbol| symbol.name)
+        .collect()
+}
+
+impl StoreHooks for OutlierRequests {
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        let outlier = match self.threshold {
+            Threshold::Latency(latency) => duration >= latency,
+            Threshold::Bytes(size) => bytes >= size,
+        };
+        if !outlier {
+            return;
+        }
+        let outcome = match outcome {
+            Outcome::Success => "ok".to_string(),
+            Outcome::Error(e) => e.to_string(),
+            Outcome::Aborted => "aborted".to_string(),
+        };
+        let mut request = json!({
+            "time": chrono::Local::now().to_rfc3339(),
+            "phase": self.phase.lock().map(|phase| phase.as_str()),
+            "op": op.as_str(),
+            "path": path.as_ref(),
+            "range": range.map(|range| [range.start, range.end]),
+            "bytes": bytes,
+            "latency_ms": duration.as_secs_f64() * 1000.0,
+            "outcome": outcome,
+            "labels": {},
+            "stack": stack(),
+        });
+        for (key, value) in labels.iter() {
+            request["labels"][*key] = match value {
+                LabelValue::Str(value) => json!(value),
+                LabelValue::Num(value) => json!(value),
+            };
+        }
+
+        let mut file = self.file.lock();
+        if let Err(e) = writeln!(file, "{request}") {
+            log::warn!("failed to write an outlying {op} request: {e}");
+        }
+    }
+}
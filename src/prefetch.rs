@@ -0,0 +1,410 @@
+//! An experimental read-ahead layer in front of the store, enabled with
+//! `--prefetch-bytes`, to see whether prefetching on the client would help
+//! lance's scans. Every ranged get also fetches the `--prefetch-bytes` after
+//! it in the background, and later gets that fall entirely within a
+//! prefetched range are answered from it without reaching the store. Bytes
+//! prefetched that no get ever asked for are wasted, and are put down to the
+//! call stack of the get that triggered the prefetch.
+//!
+//! Prefetches are requests like any other to the layers underneath, so they
+//! show up in the summary, stats and profiles. Only `get_range` and
+//! `get_ranges` are prefetched for and answered from prefetches.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use futures::stream::BoxStream;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetRange, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult, Result,
+};
+use parking_lot::Mutex;
+
+use crate::stacks;
+
+/// stacks shown in the report, most wasted bytes first
+const REPORT_STACKS: usize = 20;
+
+/// prefetches kept per object, the oldest are dropped (and accounted for)
+/// first
+const MAX_PREFETCHES: usize = 16;
+
+/// the prefetched bytes, or `None` if the prefetch failed, e.g. because the
+/// object ends where the get did
+type Fetch = Shared<BoxFuture<'static, Option<Bytes>>>;
+
+struct Prefetch {
+    range: Range<usize>,
+    fetch: Fetch,
+    /// the stack of the get that triggered it
+    stack: Vec<usize>,
+    /// the parts of it gets were answered from
+    used: Vec<Range<usize>>,
+}
+
+#[derive(Default)]
+struct StackStats {
+    prefetches: u64,
+    /// bytes the prefetches got back, which can be short of what was asked
+    /// for at the end of an object
+    prefetched_bytes: u64,
+    /// of those, the bytes at least one get was answered from
+    used_bytes: u64,
+    /// gets answered from the prefetches
+    hits: u64,
+}
+
+/// The prefetched ranges of each object, and how useful the prefetches of
+/// each call stack turned out to be.
+pub struct Prefetcher {
+    readahead: usize,
+    prefetches: Mutex<HashMap<Path, VecDeque<Prefetch>>>,
+    stacks: Mutex<HashMap<Vec<usize>, StackStats>>,
+}
+
+impl Prefetcher {
+    /// prefetches `readahead` bytes past each ranged get
+    pub fn new(readahead: usize) -> Self {
+        Self {
+            readahead,
+            prefetches: Mutex::new(HashMap::new()),
+            stacks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// the range and bytes of the prefetch `range` falls within, if there is
+    /// one
+    fn lookup(&self, location: &Path, range: &Range<usize>) -> Option<(Range<usize>, Fetch)> {
+        let prefetches = self.prefetches.lock();
+        prefetches
+            .get(location)?
+            .iter()
+            .find(|p| p.range.start <= range.start && range.end <= p.range.end)
+            .map(|p| (p.range.clone(), p.fetch.clone()))
+    }
+
+    /// notes that the get of `range` was answered from the prefetch of
+    /// `prefetched`
+    fn mark_used(&self, location: &Path, prefetched: &Range<usize>, range: Range<usize>) {
+        let mut prefetches = self.prefetches.lock();
+        let Some(prefetch) = prefetches
+            .get_mut(location)
+            .and_then(|prefetches| prefetches.iter_mut().find(|p| p.range == *prefetched))
+        else {
+            return;
+        };
+        self.stacks
+            .lock()
+            .entry(prefetch.stack.clone())
+            .or_default()
+            .hits += 1;
+        prefetch.used.push(range);
+    }
+
+    /// starts prefetching the readahead from `offset` through `inner`,
+    /// unless that's been prefetched already
+    fn prefetch(&self, inner: &Arc<dyn ObjectStore>, location: &Path, offset: usize) {
+        if self.readahead == 0 {
+            return;
+        }
+        let ahead = offset..offset + self.readahead;
+        if self
+            .lookup(location, &(ahead.start..ahead.start + 1))
+            .is_some()
+        {
+            return;
+        }
+
+        let stack = stacks::capture();
+
+        let (inner, path) = (inner.clone(), location.clone());
+        let options = GetOptions {
+            range: Some(GetRange::Bounded(ahead.clone())),
+            ..Default::default()
+        };
+        // spawned so it carries on whether or not anything waits for it
+        let handle = tokio::spawn(async move {
+            let result = inner.get_opts(&path, options).await.ok()?;
+            result.bytes().await.ok()
+        });
+        let fetch = async move { handle.await.ok().flatten() }.boxed().shared();
+
+        let evicted = {
+            let mut prefetches = self.prefetches.lock();
+            let prefetches = prefetches.entry(location.clone()).or_default();
+            prefetches.push_back(Prefetch {
+                range: ahead,
+                fetch,
+                stack,
+                used: vec![],
+            });
+            if prefetches.len() > MAX_PREFETCHES {
+                prefetches.pop_front()
+            } else {
+                None
+            }
+        };
+        if let Some(evicted) = evicted {
+            self.settle(evicted);
+        }
+    }
+
+    /// drops the prefetches of `location`, accounting for them
+    fn invalidate(&self, location: &Path) {
+        let dropped = self.prefetches.lock().remove(location);
+        for prefetch in dropped.into_iter().flatten() {
+            self.settle(prefetch);
+        }
+    }
+
+    /// adds what came of `prefetch` to the stats of the stack that made it.
+    /// one that's still in flight is counted as having fetched nothing
+    fn settle(&self, prefetch: Prefetch) {
+        let fetched = prefetch
+            .fetch
+            .peek()
+            .and_then(|bytes| bytes.as_ref())
+            .map_or(0, |bytes| bytes.len());
+        let mut used = prefetch.used;
+        used.sort_by_key(|r| r.start);
+        let (mut used_bytes, mut covered) = (0, prefetch.range.start);
+        for r in used {
+            let start = r.start.max(covered);
+            if r.end > start {
+                used_bytes += r.end - start;
+            }
+            covered = covered.max(r.end);
+        }
+
+        let mut stacks = self.stacks.lock();
+        let stats = stacks.entry(prefetch.stack).or_default();
+        stats.prefetches += 1;
+        stats.prefetched_bytes += fetched as u64;
+        stats.used_bytes += used_bytes.min(fetched) as u64;
+    }
+
+    /// how the prefetches made since the last call did, most wasted bytes
+    /// first. the prefetched ranges are dropped, so each report stands alone
+    pub fn take_report(&self) -> PrefetchReport {
+        let prefetches = std::mem::take(&mut *self.prefetches.lock());
+        for prefetch in prefetches.into_values().flatten() {
+            self.settle(prefetch);
+        }
+        let stacks = std::mem::take(&mut *self.stacks.lock());
+        let mut stacks = stacks
+            .into_iter()
+            .map(|(stack, stats)| (stacks::report(&stack, &["pprof_dev::prefetch::"]), stats))
+            .collect::<Vec<_>>();
+        stacks.sort_by_key(|(_, s)| std::cmp::Reverse(s.prefetched_bytes - s.used_bytes));
+        PrefetchReport { stacks }
+    }
+}
+
+impl fmt::Debug for Prefetcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Prefetcher")
+            .field("readahead", &self.readahead)
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct PrefetchReport {
+    stacks: Vec<(Vec<String>, StackStats)>,
+}
+
+impl Display for PrefetchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self
+            .stacks
+            .iter()
+            .fold(StackStats::default(), |mut total, (_, s)| {
+                total.prefetches += s.prefetches;
+                total.prefetched_bytes += s.prefetched_bytes;
+                total.used_bytes += s.used_bytes;
+                total.hits += s.hits;
+                total
+            });
+        writeln!(f, "{}", summary(&total))?;
+        for (frames, s) in self.stacks.iter().take(REPORT_STACKS) {
+            writeln!(f, "\n{}", summary(s))?;
+            for frame in frames {
+                writeln!(f, "    {frame}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn summary(s: &StackStats) -> String {
+    let wasted = s.prefetched_bytes - s.used_bytes;
+    let wasted_pct = match s.prefetched_bytes {
+        0 => 0.0,
+        bytes => wasted as f64 * 100.0 / bytes as f64,
+    };
+    format!(
+        "{} prefetches of {} bytes answered {} gets, {} bytes used and {wasted} wasted \
+         ({wasted_pct:.1}%)",
+        s.prefetches, s.prefetched_bytes, s.hits, s.used_bytes
+    )
+}
+
+/// Object store answering ranged gets from `prefetcher` when it can, and
+/// prefetching past each of them. anything that writes to a path drops its
+/// prefetches
+#[derive(Debug)]
+pub struct PrefetchingObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    prefetcher: Arc<Prefetcher>,
+}
+
+impl PrefetchingObjectStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, prefetcher: Arc<Prefetcher>) -> Self {
+        Self { inner, prefetcher }
+    }
+
+    /// `range` of the object at `location`, from a prefetch if one covers it.
+    /// a hit prefetches the readahead after that prefetch, a miss the
+    /// readahead after the get
+    async fn get(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        if let Some(bytes) = self.prefetched(location, &range).await {
+            return Ok(bytes);
+        }
+        let bytes = self.inner.get_range(location, range.clone()).await?;
+        self.prefetcher.prefetch(&self.inner, location, range.end);
+        Ok(bytes)
+    }
+
+    /// `range` from a prefetch covering it, if there's one
+    async fn prefetched(&self, location: &Path, range: &Range<usize>) -> Option<Bytes> {
+        let (prefetched, fetch) = self.prefetcher.lookup(location, range)?;
+        // a prefetch comes back short at the end of the object
+        let bytes = fetch
+            .await
+            .filter(|bytes| range.end - prefetched.start <= bytes.len())?;
+        self.prefetcher
+            .mark_used(location, &prefetched, range.clone());
+        if bytes.len() == prefetched.len() {
+            self.prefetcher
+                .prefetch(&self.inner, location, prefetched.end);
+        }
+        let start = prefetched.start;
+        Some(bytes.slice(range.start - start..range.end - start))
+    }
+}
+
+impl Display for PrefetchingObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PrefetchingObjectStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for PrefetchingObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.prefetcher.invalidate(location);
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.prefetcher.invalidate(location);
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.get(location, range).await
+    }
+
+    /// the ranges prefetches cover are answered from them, the rest with a
+    /// single `get_ranges` of the store underneath, followed by the readahead
+    /// after the furthest of those
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        let mut bytes = Vec::with_capacity(ranges.len());
+        let mut missed = vec![];
+        for (i, range) in ranges.iter().enumerate() {
+            let prefetched = self.prefetched(location, range).await;
+            if prefetched.is_none() {
+                missed.push(i);
+            }
+            bytes.push(prefetched);
+        }
+        if !missed.is_empty() {
+            let misses = missed
+                .iter()
+                .map(|&i| ranges[i].clone())
+                .collect::<Vec<_>>();
+            let fetched = self.inner.get_ranges(location, &misses).await?;
+            for (&i, fetched) in missed.iter().zip(fetched) {
+                bytes[i] = Some(fetched);
+            }
+            let end = misses.iter().map(|range| range.end).max().unwrap();
+            self.prefetcher.prefetch(&self.inner, location, end);
+        }
+        Ok(bytes.into_iter().map(Option::unwrap).collect())
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.prefetcher.invalidate(location);
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list_with_offset(prefix, offset)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.prefetcher.invalidate(to);
+        self.inner.copy(from, to).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.prefetcher.invalidate(from);
+        self.prefetcher.invalidate(to);
+        self.inner.rename(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.prefetcher.invalidate(to);
+        self.inner.copy_if_not_exists(from, to).await
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.prefetcher.invalidate(from);
+        self.prefetcher.invalidate(to);
+        self.inner.rename_if_not_exists(from, to).await
+    }
+}
@@ -0,0 +1,1222 @@
+//! The object store wrapper handed to lance. With the `profiling` feature
+//! disabled it hands back the original store untouched, so there is no extra
+//! layer, locking or recording on the IO path and the pprof dependencies
+//! aren't built at all. Any [`StoreHooks`] are layered on top either way.
+//!
+//! Profiles are recorded into a [`ProfileSink`], the first of the hooks, so
+//! its samples carry every label a request got on the way: the stack of each
+//! request captured as it completes, or in count-only mode flat profiles,
+//! where no stacks are captured and requests are only tallied. Everything
+//! else recorded about requests (stats, summaries, traces) comes from hooks.
+//!
+//! Operations that aren't requests can be profiled as well by implementing
+//! [`ProfiledOp`] for them and recording them through the registry, and the
+//! sizes of the requests from each stack by hooks of their own alongside
+//! whichever sink records the rest.
+
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use lance::io::WrappingObjectStore;
+use object_store::limit::LimitStore;
+use object_store::ObjectStore;
+use serde::Serialize;
+
+use crate::cache::{CachedObjectStore, HeadCache};
+use crate::clock::{Clock, MonotonicClock};
+use crate::columns::{ColumnLabels, ColumnLayout};
+use crate::fork::ProcessLocal;
+use crate::hooks::{HookSet, HookedObjectStore, Labels, StoreHooks};
+use crate::layers::StoreLayer;
+use crate::mmap::MmapStore;
+use crate::op::ObjectStoreOp;
+use crate::prefetch::{Prefetcher, PrefetchingObjectStore};
+use crate::run::{Phase, RunDir};
+use crate::scope::ScopedLabels;
+use crate::tenant::{TenantKey, TenantLabels};
+
+#[cfg(feature = "profiling")]
+pub use enabled::{write_profile, CustomProfiles, ProfileSink, RequestSizes};
+
+/// An operation of the workload's own, such as a commit or loading an index,
+/// recorded with its stack into a profile next to the object store ops.
+pub trait ProfiledOp {
+    /// the op's profile file is `{phase}_{name}`, and its count sample type is
+    /// named after it too
+    fn name(&self) -> &'static str;
+
+    /// name and unit of the value each occurrence is recorded with, kept as a
+    /// second sample type
+    fn value_type(&self) -> (&'static str, &'static str) {
+        ("value", "count")
+    }
+}
+
+/// How much of each resolved stack frame ends up in the profiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameResolution {
+    /// every function inlined at the frame, as well as the one it's inlined into
+    Full,
+    /// only the function the frame actually belongs to, dropping inlined
+    /// frames. much smaller string tables for deep async stacks
+    Outermost,
+}
+
+// for the PPROF_DEV_FRAME_RESOLUTION override
+impl FromStr for FrameResolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs::File;
+    use std::io::{self, Write};
+    use std::ops::Range;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use object_store::path::Path as ObjectPath;
+    use parking_lot::Mutex;
+    use pprof::protos::{Function, Label, Line, Location, Message, Profile, Sample, ValueType};
+
+    use super::{FrameResolution, ProfiledOp};
+    use crate::fork::PerProcess;
+    use crate::hooks::{LabelValue, Labels, Outcome, StoreHooks};
+    use crate::op::ObjectStoreOp;
+    use crate::registry::registry;
+    use crate::run::{Phase, RunDir};
+    use crate::scope;
+    use crate::stacks::{self, Symbol};
+
+    /// the modules recording a request, whose frames are dropped from the
+    /// innermost end of its stack
+    const OWN_MODULES: &[&str] = &[
+        "pprof_dev::profiling::",
+        "pprof_dev::registry::",
+        "pprof_dev::hooks::",
+        "pprof_dev::scope::",
+    ];
+
+    /// What the wrapper records its requests into profiles with. It's the
+    /// first of the wrapper's hooks, so it sees every request with all the
+    /// labels the layers above it added. Sinks keep what they record per
+    /// process, see [`crate::fork`].
+    pub trait ProfileSink: StoreHooks {
+        /// discards everything recorded so far
+        fn reset(&self);
+
+        /// labels what's recorded from now on with dataset `version`
+        fn set_dataset_version(&self, version: u64);
+
+        /// the profiles recorded since the last reset, each with the op of its
+        /// file name. `name` and `phase` are only for logging
+        fn take_profiles(&self, name: &str, phase: Phase) -> Vec<(String, Profile)>;
+
+        /// what's been recorded of `op` since the last reset, leaving it
+        /// recorded. `None` if the sink doesn't profile `op`
+        fn report(&self, op: ObjectStoreOp) -> Option<Profile>;
+    }
+
+    /// Sinks recording the same requests side by side, each writing profiles
+    /// of its own.
+    pub struct FanOutSink(pub Vec<Arc<dyn ProfileSink>>);
+
+    impl StoreHooks for FanOutSink {
+        fn on_request(&self, op: ObjectStoreOp, path: &ObjectPath, range: Option<Range<usize>>) {
+            for sink in &self.0 {
+                sink.on_request(op, path, range.clone());
+            }
+        }
+
+        fn on_first_byte(
+            &self,
+            op: ObjectStoreOp,
+            path: &ObjectPath,
+            ttfb: Duration,
+            labels: &Labels,
+        ) {
+            for sink in &self.0 {
+                sink.on_first_byte(op, path, ttfb, labels);
+            }
+        }
+
+        fn on_complete(
+            &self,
+            op: ObjectStoreOp,
+            path: &ObjectPath,
+            range: Option<Range<usize>>,
+            bytes: usize,
+            duration: Duration,
+            outcome: Outcome<'_>,
+            labels: &Labels,
+        ) {
+            for sink in &self.0 {
+                sink.on_complete(op, path, range.clone(), bytes, duration, outcome, labels);
+            }
+        }
+    }
+
+    impl ProfileSink for FanOutSink {
+        fn reset(&self) {
+            for sink in &self.0 {
+                sink.reset();
+            }
+        }
+
+        fn set_dataset_version(&self, version: u64) {
+            for sink in &self.0 {
+                sink.set_dataset_version(version);
+            }
+        }
+
+        /// every sink's profiles, which have to be named apart
+        fn take_profiles(&self, name: &str, phase: Phase) -> Vec<(String, Profile)> {
+            self.0
+                .iter()
+                .flat_map(|sink| sink.take_profiles(name, phase))
+                .collect()
+        }
+
+        /// the first sink's that profiles `op`
+        fn report(&self, op: ObjectStoreOp) -> Option<Profile> {
+            self.0.iter().find_map(|sink| sink.report(op))
+        }
+    }
+
+    /// Hands requests from the hooks layer to the wrapper's sink.
+    pub struct SinkHooks(pub Arc<dyn ProfileSink>);
+
+    impl StoreHooks for SinkHooks {
+        fn on_request(&self, op: ObjectStoreOp, path: &ObjectPath, range: Option<Range<usize>>) {
+            self.0.on_request(op, path, range);
+        }
+
+        fn on_first_byte(
+            &self,
+            op: ObjectStoreOp,
+            path: &ObjectPath,
+            ttfb: Duration,
+            labels: &Labels,
+        ) {
+            self.0.on_first_byte(op, path, ttfb, labels);
+        }
+
+        fn on_complete(
+            &self,
+            op: ObjectStoreOp,
+            path: &ObjectPath,
+            range: Option<Range<usize>>,
+            bytes: usize,
+            duration: Duration,
+            outcome: Outcome<'_>,
+            labels: &Labels,
+        ) {
+            self.0
+                .on_complete(op, path, range, bytes, duration, outcome, labels);
+        }
+    }
+
+    /// Profiles of the stack of every get and put, captured when the request
+    /// completes, with a sample per stack and label set.
+    pub struct StackProfiles {
+        max_depth: Option<usize>,
+        frames: FrameResolution,
+        dataset_version: PerProcess<Mutex<Option<u64>>>,
+        /// count per stack and labels, of each op
+        stacks: PerProcess<Mutex<BTreeMap<ObjectStoreOp, StackCounts>>>,
+        segments: PerProcess<Mutex<Vec<Segment>>>,
+    }
+
+    type StackCounts = BTreeMap<(Vec<usize>, Labels), i64>;
+
+    /// The profiles recorded against an earlier dataset version, cut when a
+    /// phase moved on to a new one.
+    struct Segment {
+        dataset_version: u64,
+        profiles: BTreeMap<ObjectStoreOp, Profile>,
+    }
+
+    impl StackProfiles {
+        pub fn new(max_depth: Option<usize>, frames: FrameResolution) -> Self {
+            Self {
+                max_depth,
+                frames,
+                dataset_version: PerProcess::default(),
+                stacks: PerProcess::default(),
+                segments: PerProcess::default(),
+            }
+        }
+
+        /// a profile of `stacks` for `op`, labelled with the dataset
+        /// `version` they were recorded against
+        fn profile(&self, op: ObjectStoreOp, stacks: StackCounts, version: Option<u64>) -> Profile {
+            let sample_type = op.sample_type();
+            let samples = stacks.into_iter().map(|((stack, labels), count)| {
+                let labels = match version {
+                    Some(version) => labels.num("dataset_version", version as i64),
+                    None => labels,
+                };
+                (stack, vec![count], labels)
+            });
+            stack_profile(
+                &[(sample_type.as_str(), "count")],
+                samples,
+                self.max_depth,
+                self.frames,
+            )
+        }
+    }
+
+    impl StoreHooks for StackProfiles {
+        /// requests of disabled ops aren't recorded
+        fn on_complete(
+            &self,
+            op: ObjectStoreOp,
+            _path: &ObjectPath,
+            _range: Option<Range<usize>>,
+            _bytes: usize,
+            _duration: Duration,
+            _outcome: Outcome<'_>,
+            labels: &Labels,
+        ) {
+            if !ObjectStoreOp::PROFILED.contains(&op) || !registry().toggles().is_enabled(op) {
+                return;
+            }
+            let stack = stacks::capture();
+            let labels = Labels::default().str("node", scope::current()).with(labels);
+            let mut stacks = self.stacks.get().lock();
+            *stacks
+                .entry(op)
+                .or_default()
+                .entry((stack, labels))
+                .or_default() += 1;
+        }
+    }
+
+    impl ProfileSink for StackProfiles {
+        fn reset(&self) {
+            self.stacks.get().lock().clear();
+            self.segments.get().lock().clear();
+        }
+
+        /// if samples were already recorded against another version in this
+        /// phase, those are set aside to be written as a profile of their own
+        fn set_dataset_version(&self, version: u64) {
+            let mut current = self.dataset_version.get().lock();
+            if let Some(previous) = *current {
+                if previous == version {
+                    return;
+                }
+                let profiles = std::mem::take(&mut *self.stacks.get().lock())
+                    .into_iter()
+                    .map(|(op, stacks)| (op, self.profile(op, stacks, Some(previous))))
+                    .collect();
+                self.segments.get().lock().push(Segment {
+                    dataset_version: previous,
+                    profiles,
+                });
+            }
+            *current = Some(version);
+        }
+
+        /// when more than one dataset version was read there's a profile per
+        /// version, suffixed with `_v<version>`
+        fn take_profiles(&self, _name: &str, _phase: Phase) -> Vec<(String, Profile)> {
+            let segments = std::mem::take(&mut *self.segments.get().lock());
+            let mut stacks = std::mem::take(&mut *self.stacks.get().lock());
+            let version = *self.dataset_version.get().lock();
+
+            let mut outputs = vec![];
+            for op in ObjectStoreOp::PROFILED {
+                let current = self.profile(op, stacks.remove(&op).unwrap_or_default(), version);
+                let earlier = segments
+                    .iter()
+                    .filter_map(|segment| {
+                        let profile = segment.profiles.get(&op)?;
+                        (!profile.sample.is_empty())
+                            .then(|| (segment.dataset_version, profile.clone()))
+                    })
+                    .collect::<Vec<_>>();
+
+                if earlier.is_empty() {
+                    outputs.push((op.to_string(), current));
+                } else {
+                    for (version, profile) in earlier {
+                        outputs.push((format!("{op}_v{version}"), profile));
+                    }
+                    let version = version.unwrap_or_default();
+                    outputs.push((format!("{op}_v{version}"), current));
+                }
+            }
+            outputs
+        }
+
+        /// only the samples of the current dataset version, those set aside
+        /// for earlier ones aren't included
+        fn report(&self, op: ObjectStoreOp) -> Option<Profile> {
+            if !ObjectStoreOp::PROFILED.contains(&op) {
+                return None;
+            }
+            let stacks = self
+                .stacks
+                .get()
+                .lock()
+                .get(&op)
+                .cloned()
+                .unwrap_or_default();
+            Some(self.profile(op, stacks, *self.dataset_version.get().lock()))
+        }
+    }
+
+    /// writes `profile` to `path` with `comments` added to it
+    pub fn write_profile(mut profile: Profile, comments: &[String], path: &Path) -> io::Result<()> {
+        for comment in comments {
+            let comment = push_string(&mut profile, comment);
+            profile.comment.push(comment);
+        }
+
+        let mut content = Vec::new();
+        profile
+            .write_to_vec(&mut content)
+            .map_err(io::Error::other)?;
+        File::create(path)?.write_all(&content)
+    }
+
+    /// Per op request counts, bytes and latency for `--count-only` runs, kept
+    /// per operator, dataset version and request labels rather than per stack.
+    #[derive(Default)]
+    pub struct FlatProfiles {
+        dataset_version: PerProcess<Mutex<Option<u64>>>,
+        samples: PerProcess<Mutex<BTreeMap<FlatKey, FlatSample>>>,
+    }
+
+    type FlatKey = (ObjectStoreOp, &'static str, Option<u64>, Labels);
+
+    #[derive(Default)]
+    struct FlatSample {
+        count: i64,
+        bytes: i64,
+        latency_ns: i64,
+        ttfb_ns: i64,
+    }
+
+    impl ProfileSink for FlatProfiles {
+        fn reset(&self) {
+            self.samples.get().lock().clear();
+        }
+
+        /// flat samples carry their version, there's nothing to set aside
+        fn set_dataset_version(&self, version: u64) {
+            *self.dataset_version.get().lock() = Some(version);
+        }
+
+        fn take_profiles(&self, _name: &str, _phase: Phase) -> Vec<(String, Profile)> {
+            let samples = std::mem::take(&mut *self.samples.get().lock());
+            Self::profiles(&samples)
+                .into_iter()
+                .map(|(op, profile)| (op.to_string(), profile))
+                .collect()
+        }
+
+        /// every op is profiled, an op nothing was recorded for has a profile
+        /// without samples
+        fn report(&self, op: ObjectStoreOp) -> Option<Profile> {
+            let samples = self.samples.get().lock();
+            let samples = samples.iter().filter(|((sampled, ..), _)| *sampled == op);
+            let profile = Self::profiles(samples).remove(&op);
+            Some(profile.unwrap_or_else(|| flat_profile(op)))
+        }
+    }
+
+    impl FlatProfiles {
+        /// a profile for each op among `samples`, with a sample per operator,
+        /// dataset version and label set
+        fn profiles<'a>(
+            samples: impl IntoIterator<Item = (&'a FlatKey, &'a FlatSample)>,
+        ) -> BTreeMap<ObjectStoreOp, Profile> {
+            let mut profiles = BTreeMap::new();
+            for ((op, node, version, labels), flat) in samples {
+                let (op, node, version) = (*op, *node, *version);
+                let profile = profiles.entry(op).or_insert_with(|| flat_profile(op));
+                let mut sample = Sample::new();
+                sample.location_id = vec![1];
+                sample.value = vec![flat.count, flat.bytes, flat.latency_ns, flat.ttfb_ns];
+
+                let mut own = Labels::default().str("node", node);
+                if let Some(version) = version {
+                    own = own.num("dataset_version", version as i64);
+                }
+                for (key, value) in own.iter().chain(labels.iter()) {
+                    sample.label.push(label(profile, key, value));
+                }
+                profile.sample.push(sample);
+            }
+            profiles
+        }
+    }
+
+    impl FlatProfiles {
+        fn with_sample(&self, op: ObjectStoreOp, labels: &Labels, f: impl FnOnce(&mut FlatSample)) {
+            if !registry().toggles().is_enabled(op) {
+                return;
+            }
+            let version = *self.dataset_version.get().lock();
+            let key = (op, scope::current(), version, labels.clone());
+            f(self.samples.get().lock().entry(key).or_default());
+        }
+    }
+
+    impl StoreHooks for FlatProfiles {
+        fn on_first_byte(
+            &self,
+            op: ObjectStoreOp,
+            _path: &ObjectPath,
+            ttfb: Duration,
+            labels: &Labels,
+        ) {
+            self.with_sample(op, labels, |flat| flat.ttfb_ns += ttfb.as_nanos() as i64);
+        }
+
+        fn on_complete(
+            &self,
+            op: ObjectStoreOp,
+            _path: &ObjectPath,
+            _range: Option<Range<usize>>,
+            bytes: usize,
+            duration: Duration,
+            _outcome: Outcome<'_>,
+            labels: &Labels,
+        ) {
+            self.with_sample(op, labels, |flat| {
+                flat.count += 1;
+                flat.bytes += bytes as i64;
+                flat.latency_ns += duration.as_nanos() as i64;
+            });
+        }
+    }
+
+    /// How long requests took, as flat profiles named `{op}_latency` with a
+    /// sample per latency bucket next to the operator and labels. The buckets
+    /// are powers of two microseconds, labelled with their upper bound as
+    /// `latency_bucket_us`, so `go tool pprof -tags` shows the distribution of
+    /// each op and `-tagfocus` the requests of one bucket.
+    #[derive(Default)]
+    pub struct StatsSink(FlatProfiles);
+
+    impl StoreHooks for StatsSink {
+        fn on_complete(
+            &self,
+            op: ObjectStoreOp,
+            path: &ObjectPath,
+            range: Option<Range<usize>>,
+            bytes: usize,
+            duration: Duration,
+            outcome: Outcome<'_>,
+            labels: &Labels,
+        ) {
+            let bucket = (duration.as_micros() as u64).max(1).next_power_of_two();
+            let labels = labels.clone().num("latency_bucket_us", bucket as i64);
+            self.0
+                .on_complete(op, path, range, bytes, duration, outcome, &labels);
+        }
+    }
+
+    impl ProfileSink for StatsSink {
+        fn reset(&self) {
+            self.0.reset();
+        }
+
+        fn set_dataset_version(&self, version: u64) {
+            self.0.set_dataset_version(version);
+        }
+
+        fn take_profiles(&self, name: &str, phase: Phase) -> Vec<(String, Profile)> {
+            self.0
+                .take_profiles(name, phase)
+                .into_iter()
+                .map(|(op, profile)| (format!("{op}_latency"), profile))
+                .collect()
+        }
+
+        fn report(&self, op: ObjectStoreOp) -> Option<Profile> {
+            self.0.report(op)
+        }
+    }
+
+    /// Stacks [`ProfiledOp`]s were recorded from, with how many times and the
+    /// total of their values.
+    #[derive(Default)]
+    pub struct CustomProfiles {
+        ops: PerProcess<Mutex<BTreeMap<&'static str, CustomOp>>>,
+    }
+
+    struct CustomOp {
+        value_type: (&'static str, &'static str),
+        /// count and value total per stack
+        stacks: HashMap<Vec<usize>, [i64; 2]>,
+    }
+
+    impl CustomProfiles {
+        pub fn record(&self, op: &dyn ProfiledOp, value: i64) {
+            let stack = stacks::capture();
+            let mut ops = self.ops.get().lock();
+            let custom = ops.entry(op.name()).or_insert_with(|| CustomOp {
+                value_type: op.value_type(),
+                stacks: HashMap::new(),
+            });
+            let sample = custom.stacks.entry(stack).or_default();
+            sample[0] += 1;
+            sample[1] += value;
+        }
+
+        pub fn reset(&self) {
+            self.ops.get().lock().clear();
+        }
+
+        /// writes a profile of each op recorded during `phase`
+        pub fn write(&self, run_dir: &RunDir, phase: Phase) {
+            for (name, op) in std::mem::take(&mut *self.ops.get().lock()) {
+                let comments = run_dir.profile_comments(phase, name);
+                let written = run_dir
+                    .output_path(phase, name, "pb")
+                    .and_then(|path| write_profile(op.profile(name), &comments, &path));
+                if let Err(e) = written {
+                    log::warn!(
+                        "couldn't write the {name} profile of {}: {e}",
+                        phase.as_str()
+                    );
+                }
+            }
+        }
+    }
+
+    impl CustomOp {
+        fn profile(self, name: &str) -> Profile {
+            let (value_name, value_unit) = self.value_type;
+            stack_profile(
+                &[(name, "count"), (value_name, value_unit)],
+                self.stacks
+                    .into_iter()
+                    .map(|(stack, values)| (stack, values.to_vec(), Labels::default())),
+                None,
+                FrameResolution::Full,
+            )
+        }
+    }
+
+    /// Count, total, smallest and largest bytes of the gets and puts made
+    /// from each stack, recorded from hooks on completion so it works with
+    /// either sink. Failed requests are left out, they moved nothing.
+    #[derive(Default)]
+    pub struct RequestSizes {
+        ops: PerProcess<Mutex<BTreeMap<ObjectStoreOp, HashMap<Vec<usize>, [i64; 4]>>>>,
+    }
+
+    impl RequestSizes {
+        pub fn reset(&self) {
+            self.ops.get().lock().clear();
+        }
+
+        /// a `{op}_bytes` profile for each op recorded since the last call
+        pub fn take_profiles(&self) -> Vec<(String, Profile)> {
+            std::mem::take(&mut *self.ops.get().lock())
+                .into_iter()
+                .map(|(op, stacks)| {
+                    let sample_type = op.sample_type();
+                    let profile = stack_profile(
+                        &[
+                            (sample_type.as_str(), "count"),
+                            ("bytes", "bytes"),
+                            ("min_bytes", "bytes"),
+                            ("max_bytes", "bytes"),
+                        ],
+                        stacks
+                            .into_iter()
+                            .map(|(stack, values)| (stack, values.to_vec(), Labels::default())),
+                        None,
+                        FrameResolution::Full,
+                    );
+                    (format!("{op}_bytes"), profile)
+                })
+                .collect()
+        }
+    }
+
+    impl StoreHooks for RequestSizes {
+        fn on_complete(
+            &self,
+            op: ObjectStoreOp,
+            _path: &ObjectPath,
+            _range: Option<Range<usize>>,
+            bytes: usize,
+            _duration: Duration,
+            outcome: Outcome<'_>,
+            _labels: &Labels,
+        ) {
+            if !ObjectStoreOp::PROFILED.contains(&op)
+                || !registry().toggles().is_enabled(op)
+                || matches!(outcome, Outcome::Error(_))
+            {
+                return;
+            }
+            let stack = stacks::capture();
+            let bytes = bytes as i64;
+            let mut ops = self.ops.get().lock();
+            let sample = ops
+                .entry(op)
+                .or_default()
+                .entry(stack)
+                .or_insert([0, 0, i64::MAX, 0]);
+            sample[0] += 1;
+            sample[1] += bytes;
+            sample[2] = sample[2].min(bytes);
+            sample[3] = sample[3].max(bytes);
+        }
+    }
+
+    /// a profile with `sample_types` and a sample of the values and labels
+    /// recorded at each of `stacks`, after dropping the frames that did the
+    /// recording. only the innermost `max_depth` of the frames left are kept,
+    /// resolved as `frames`
+    fn stack_profile(
+        sample_types: &[(&str, &str)],
+        stacks: impl IntoIterator<Item = (Vec<usize>, Vec<i64>, Labels)>,
+        max_depth: Option<usize>,
+        frames: FrameResolution,
+    ) -> Profile {
+        let mut profile = Profile::new();
+        profile.string_table.push(String::new());
+        for (name, unit) in sample_types {
+            let mut sample_type = ValueType::new();
+            sample_type.field_type = push_string(&mut profile, name);
+            sample_type.unit = push_string(&mut profile, unit);
+            profile.sample_type.push(sample_type);
+        }
+
+        let mut functions = HashMap::new();
+        let mut locations = HashMap::new();
+        for (stack, values, labels) in stacks {
+            let resolved = stacks::resolve(&stack);
+            let own = stacks::own_frames(&resolved, OWN_MODULES);
+
+            let mut sample = Sample::new();
+            sample.value = values;
+            for (key, value) in labels.iter() {
+                sample.label.push(label(&mut profile, key, value));
+            }
+            let kept = stack
+                .iter()
+                .zip(resolved)
+                .skip(own)
+                .take(max_depth.unwrap_or(usize::MAX));
+            for (ip, mut symbols) in kept {
+                let id = match locations.get(ip) {
+                    Some(id) => *id,
+                    None => {
+                        if frames == FrameResolution::Outermost {
+                            // inlined functions come before the one they're
+                            // inlined into
+                            symbols.drain(..symbols.len().saturating_sub(1));
+                        }
+                        let id = profile.location.len() as u64 + 1;
+                        let location = location(&mut profile, &mut functions, id, symbols);
+                        profile.location.push(location);
+                        locations.insert(*ip, id);
+                        id
+                    }
+                };
+                sample.location_id.push(id);
+            }
+            profile.sample.push(sample);
+        }
+        profile
+    }
+
+    fn location(
+        profile: &mut Profile,
+        functions: &mut HashMap<String, u64>,
+        id: u64,
+        symbols: Vec<Symbol>,
+    ) -> Location {
+        let mut location = Location::new();
+        location.id = id;
+        for Symbol {
+            name,
+            file,
+            line: line_number,
+        } in symbols
+        {
+            let function_id = match functions.get(&name) {
+                Some(id) => *id,
+                None => {
+                    let mut function = Function::new();
+                    function.id = profile.function.len() as u64 + 1;
+                    function.name = push_string(profile, &name);
+                    function.system_name = function.name;
+                    function.filename = push_string(profile, &file);
+                    let id = function.id;
+                    profile.function.push(function);
+                    functions.insert(name, id);
+                    id
+                }
+            };
+            let mut line = Line::new();
+            line.function_id = function_id;
+            line.line = line_number;
+            location.line.push(line);
+        }
+        location
+    }
+
+    /// an empty profile for `op` with count, bytes, latency and
+    /// time-to-first-byte sample types, and a single frame named after the op
+    /// for the samples to point at
+    fn flat_profile(op: ObjectStoreOp) -> Profile {
+        let mut profile = Profile::new();
+        profile.string_table.push(String::new());
+        for (name, unit) in [
+            (op.sample_type(), "count"),
+            ("bytes".to_string(), "bytes"),
+            ("latency".to_string(), "nanoseconds"),
+            ("ttfb".to_string(), "nanoseconds"),
+        ] {
+            let mut sample_type = ValueType::new();
+            sample_type.field_type = push_string(&mut profile, &name);
+            sample_type.unit = push_string(&mut profile, unit);
+            profile.sample_type.push(sample_type);
+        }
+
+        let mut function = Function::new();
+        function.id = 1;
+        function.name = push_string(&mut profile, &format!("object_store::{op}"));
+        function.system_name = function.name;
+        profile.function.push(function);
+
+        let mut line = Line::new();
+        line.function_id = 1;
+        let mut location = Location::new();
+        location.id = 1;
+        location.line.push(line);
+        profile.location.push(location);
+        profile
+    }
+
+    /// adds `labels` to every sample of `profile`
+    pub fn add_labels(profile: &mut Profile, labels: &Labels) {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| label(profile, key, value))
+            .collect::<Vec<_>>();
+        for sample in profile.sample.iter_mut() {
+            for label in &labels {
+                sample.label.push(label.clone());
+            }
+        }
+    }
+
+    fn label(profile: &mut Profile, key: &str, value: &LabelValue) -> Label {
+        let mut label = Label::new();
+        label.key = push_string(profile, key);
+        match value {
+            LabelValue::Str(value) => label.str = push_string(profile, value),
+            LabelValue::Num(value) => label.num = *value,
+        }
+        label
+    }
+
+    /// the index of `s` appended to the string table, which can already hold
+    /// an earlier copy of it
+    fn push_string(profile: &mut Profile, s: &str) -> i64 {
+        profile.string_table.push(s.to_string());
+        profile.string_table.len() as i64 - 1
+    }
+}
+
+pub struct ProfilingObjectStoreWrapper {
+    /// the stack profiles, or flat profiles in count-only mode
+    #[cfg(feature = "profiling")]
+    sink: Arc<dyn ProfileSink>,
+    hooks: Vec<Arc<dyn StoreHooks>>,
+    head_cache: Option<Arc<HeadCache>>,
+    prefetcher: Option<Arc<Prefetcher>>,
+    /// requests let through to the profiled store at once
+    permits: Option<usize>,
+    mmap: bool,
+    /// the layers from `--store-layers`, replacing the head cache, prefetcher,
+    /// limit and mmap layers above
+    layers: Option<Vec<StoreLayer>>,
+    task_labels: bool,
+    /// labels gets with the column they read before the hooks see them
+    columns: Option<Arc<ColumnLayout>>,
+    /// labels requests with their tenant before the hooks see them
+    tenants: Option<TenantKey>,
+    /// what the hooks are given the requests' latencies by
+    clock: Arc<dyn Clock>,
+    /// the sizes of requests per stack, if they're profiled
+    #[cfg(feature = "profiling")]
+    request_sizes: Option<Arc<RequestSizes>>,
+    /// added to every sample written
+    #[cfg(feature = "profiling")]
+    labels: Labels,
+}
+
+impl std::fmt::Debug for ProfilingObjectStoreWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProfilingObjectStoreWrapper{}")?; // TODO?
+        Ok(())
+    }
+}
+
+impl ProfilingObjectStoreWrapper {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "profiling")]
+            sink: Arc::new(enabled::StackProfiles::new(None, FrameResolution::Full)),
+            hooks: vec![],
+            head_cache: None,
+            prefetcher: None,
+            permits: None,
+            mmap: false,
+            layers: None,
+            task_labels: false,
+            columns: None,
+            tenants: None,
+            clock: Arc::new(MonotonicClock::default()),
+            #[cfg(feature = "profiling")]
+            request_sizes: None,
+            #[cfg(feature = "profiling")]
+            labels: Labels::default(),
+        }
+    }
+
+    /// fire `hooks` around every request made through the stores this wraps,
+    /// after any hooks added before them. these run even when built without
+    /// the profiling feature
+    pub fn with_hooks(mut self, hooks: Arc<dyn StoreHooks>) -> Self {
+        self.hooks.push(hooks);
+        self
+    }
+
+    /// keeps only the innermost `max_depth` frames of each stack, resolved as
+    /// `frames`. symbols are resolved when the profiles are written at the end
+    /// of each phase, so this is what decides how long that takes. replaces
+    /// the sink, so comes before [`Self::with_count_only`]
+    #[cfg(feature = "profiling")]
+    pub fn with_stack_options(mut self, max_depth: Option<usize>, frames: FrameResolution) -> Self {
+        self.sink = Arc::new(enabled::StackProfiles::new(max_depth, frames));
+        self
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub fn with_stack_options(self, _max_depth: Option<usize>, _frames: FrameResolution) -> Self {
+        self
+    }
+
+    /// records only the count, bytes and latency of requests per op, operator
+    /// and dataset version, without capturing stacks, and writes those as flat
+    /// profiles with a single frame
+    #[cfg(feature = "profiling")]
+    pub fn with_count_only(mut self) -> Self {
+        self.sink = Arc::new(enabled::FlatProfiles::default());
+        self
+    }
+
+    /// there are no profiles to write without the profiling feature, and the
+    /// stores aren't wrapped to capture stacks anyway
+    #[cfg(not(feature = "profiling"))]
+    pub fn with_count_only(self) -> Self {
+        self
+    }
+
+    /// also write `{op}_latency` profiles of how long each op's requests
+    /// took, next to those of the sink set so far, so comes after
+    /// [`Self::with_stack_options`] and [`Self::with_count_only`]
+    #[cfg(feature = "profiling")]
+    pub fn with_latency_profiles(mut self) -> Self {
+        let latency = Arc::new(enabled::StatsSink::default());
+        self.sink = Arc::new(enabled::FanOutSink(vec![self.sink, latency]));
+        self
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub fn with_latency_profiles(self) -> Self {
+        self
+    }
+
+    /// also capture the stack of every completed get and put to write
+    /// `{op}_bytes` profiles of how many bytes each stack's requests moved,
+    /// whether or not the sink captures stacks
+    #[cfg(feature = "profiling")]
+    pub fn with_request_sizes(mut self) -> Self {
+        let sizes = Arc::new(RequestSizes::default());
+        self.hooks.push(sizes.clone());
+        self.request_sizes = Some(sizes);
+        self
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub fn with_request_sizes(self) -> Self {
+        self
+    }
+
+    /// label every sample in the profiles written with `labels`, e.g. the
+    /// dataset when several are profiled at once
+    #[cfg(feature = "profiling")]
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub fn with_labels(self, _labels: Labels) -> Self {
+        self
+    }
+
+    /// answer heads from `cache` before they reach any of the other layers
+    pub fn with_head_cache(mut self, cache: Arc<HeadCache>) -> Self {
+        self.head_cache = Some(cache);
+        self
+    }
+
+    /// answer ranged gets from `prefetcher`'s read-ahead, below the head
+    /// cache and above everything else, so the prefetches are requests like
+    /// any other and only the gets answered from them go unrecorded
+    pub fn with_prefetch(mut self, prefetcher: Arc<Prefetcher>) -> Self {
+        self.prefetcher = Some(prefetcher);
+        self
+    }
+
+    /// let at most `permits` requests through to the profiled store at once,
+    /// the rest wait their turn before they reach any of the hooks
+    pub fn with_permits(mut self, permits: usize) -> Self {
+        self.permits = Some(permits);
+        self
+    }
+
+    /// serve gets of the local files underneath from memory maps, see
+    /// [`crate::mmap`]
+    pub fn with_mmap(mut self) -> Self {
+        self.mmap = true;
+        self
+    }
+
+    /// stack `layers` between lance and the original store, outermost first,
+    /// with the sink and hooks at the profiling layer, see [`crate::layers`].
+    /// the head cache, prefetcher, limit and mmap set here are ignored
+    pub fn with_layers(mut self, layers: Vec<StoreLayer>) -> Self {
+        self.layers = Some(layers);
+        self
+    }
+
+    /// label every request with the tokio task that made it, see
+    /// [`crate::scope`]
+    pub fn with_task_labels(mut self) -> Self {
+        self.task_labels = true;
+        self
+    }
+
+    /// label the gets the hooks see with the column of the dataset they read,
+    /// by `layout`, see [`crate::columns`]
+    pub fn with_column_layout(mut self, layout: Arc<ColumnLayout>) -> Self {
+        self.columns = Some(layout);
+        self
+    }
+
+    /// label the requests the hooks see with the tenant `key` takes from
+    /// their paths, see [`crate::tenant`]
+    pub fn with_tenant_labels(mut self, key: TenantKey) -> Self {
+        self.tenants = Some(key);
+        self
+    }
+
+    /// times requests by `clock` instead of the real time, so tests can say
+    /// how long each one took
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn with_mmap_layer(&self, store: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        if self.mmap {
+            Arc::new(MmapStore::new(store))
+        } else {
+            store
+        }
+    }
+
+    /// the sink and the hooks around `store`, below the layers labelling
+    /// requests so the sink's samples get those labels too
+    fn profiled(&self, store: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        let hooks: Option<Arc<dyn StoreHooks>> = match self.hooks.as_slice() {
+            [] => None,
+            [hooks] => Some(hooks.clone()),
+            hooks => Some(Arc::new(HookSet(hooks.to_vec()))),
+        };
+        // what's behind the hooks is the parent's in a forked child, only the
+        // sink keeps each process's requests apart
+        let hooks = hooks.map(|hooks| Arc::new(ProcessLocal::new(hooks)) as Arc<dyn StoreHooks>);
+        #[cfg(feature = "profiling")]
+        let hooks = {
+            let sink: Arc<dyn StoreHooks> = Arc::new(enabled::SinkHooks(self.sink.clone()));
+            Some(match hooks {
+                Some(hooks) => Arc::new(HookSet(vec![sink, hooks])),
+                None => sink,
+            })
+        };
+        let hooks = match (hooks, &self.columns) {
+            (Some(hooks), Some(layout)) => Some(Arc::new(ColumnLabels(hooks, layout.clone())) as _),
+            (hooks, _) => hooks,
+        };
+        let hooks = match (hooks, self.tenants) {
+            (Some(hooks), Some(key)) => Some(Arc::new(TenantLabels(hooks, key)) as _),
+            (hooks, _) => hooks,
+        };
+        match hooks {
+            Some(hooks) => Arc::new(
+                HookedObjectStore::new(store, Arc::new(ScopedLabels(hooks, self.task_labels)))
+                    .with_clock(self.clock.clone()),
+            ),
+            None => store,
+        }
+    }
+
+    /// the limit, then the prefetcher and the head cache on top of that
+    fn with_outer_layers(&self, store: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        let store: Arc<dyn ObjectStore> = match self.permits {
+            Some(permits) => Arc::new(LimitStore::new(store, permits)),
+            None => store,
+        };
+        let store: Arc<dyn ObjectStore> = match &self.prefetcher {
+            Some(prefetcher) => Arc::new(PrefetchingObjectStore::new(store, prefetcher.clone())),
+            None => store,
+        };
+        match &self.head_cache {
+            Some(cache) => Arc::new(CachedObjectStore::new(store, cache.clone())),
+            None => store,
+        }
+    }
+
+    /// discards everything recorded so far
+    pub fn reset(&self) {
+        #[cfg(feature = "profiling")]
+        {
+            self.sink.reset();
+            if let Some(sizes) = &self.request_sizes {
+                sizes.reset();
+            }
+        }
+    }
+
+    /// labels samples recorded from now on with dataset `version`
+    #[cfg(feature = "profiling")]
+    pub fn set_dataset_version(&self, version: u64) {
+        self.sink.set_dataset_version(version);
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub fn set_dataset_version(&self, _version: u64) {}
+
+    /// the profiles collected since the last reset, each with the op it's
+    /// written under and the wrapper's labels added. `name` and `phase` are
+    /// only for logging
+    #[cfg(feature = "profiling")]
+    pub fn take_profiles(&self, phase: Phase, name: &str) -> Vec<(String, pprof::protos::Profile)> {
+        let sizes = self
+            .request_sizes
+            .iter()
+            .flat_map(|sizes| sizes.take_profiles());
+        let mut profiles = self.sink.take_profiles(name, phase);
+        profiles.extend(sizes);
+        for (_, profile) in profiles.iter_mut() {
+            enabled::add_labels(profile, &self.labels);
+        }
+        profiles
+    }
+
+    /// the profile of `op` recorded so far, with the wrapper's labels, and
+    /// without taking it from the profiles written at the end of the phase
+    #[cfg(feature = "profiling")]
+    pub fn report(&self, op: ObjectStoreOp) -> Option<pprof::protos::Profile> {
+        let mut profile = self.sink.report(op)?;
+        enabled::add_labels(&mut profile, &self.labels);
+        Some(profile)
+    }
+
+    /// writes the profile of `op` recorded so far to `path`, see
+    /// [`Self::report`]. nothing is written if the sink doesn't profile `op`.
+    /// for code embedding the wrapper, the binary writes every profile of a
+    /// phase at once with [`Self::write_profiles`]
+    #[cfg(feature = "profiling")]
+    #[allow(dead_code)]
+    pub fn write_pprof(&self, op: ObjectStoreOp, path: &Path) -> io::Result<()> {
+        match self.report(op) {
+            Some(profile) => enabled::write_profile(profile, &[], path),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    #[allow(dead_code)]
+    pub fn write_pprof(&self, op: ObjectStoreOp, path: &Path) -> io::Result<()> {
+        log::debug!(
+            "built without the profiling feature, not writing the {op} profile to {}",
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// writes the profiles collected during `phase` into the run directory,
+    /// with the op in each file name prefixed by `name`
+    #[cfg(feature = "profiling")]
+    pub fn write_profiles(&self, run_dir: &RunDir, phase: Phase, name: &str) {
+        for (op, profile) in self.take_profiles(phase, name) {
+            let file_op = format!("{name}_{op}");
+            let comments = run_dir.profile_comments(phase, &file_op);
+            let written = run_dir
+                .output_path(phase, &file_op, "pb")
+                .and_then(|path| enabled::write_profile(profile, &comments, &path));
+            // this also runs while a panic unwinds
+            if let Err(e) = written {
+                log::warn!(
+                    "couldn't write the {file_op} profile of {}: {e}",
+                    phase.as_str()
+                );
+            }
+        }
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub fn write_profiles(&self, _run_dir: &RunDir, phase: Phase, name: &str) {
+        log::debug!(
+            "built without the profiling feature, not writing {name} profiles for {}",
+            phase.as_str()
+        );
+    }
+}
+
+impl WrappingObjectStore for ProfilingObjectStoreWrapper {
+    fn wrap(&self, original: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        match &self.layers {
+            // innermost first
+            Some(layers) => layers
+                .iter()
+                .rev()
+                .fold(original, |store, layer| match layer {
+                    StoreLayer::Profiling => self.profiled(store),
+                    layer => layer.wrap(store),
+                }),
+            None => self.with_outer_layers(self.profiled(self.with_mmap_layer(original))),
+        }
+    }
+}
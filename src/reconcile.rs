@@ -0,0 +1,306 @@
+//! `pprof-dev reconcile`: the requests the server logged against a run's
+//! dataset, from s3 server access logs or minio audit logs, next to those the
+//! wrapper recorded, phase by phase, to check the wrapper sees every request.
+//!
+//! The logs count what reached the server, so a multipart upload is a single
+//! put (its parts only add bytes) and a rename is a copy and a delete.
+//! Listings are matched by their prefix, other requests by their key.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::cli::ReconcileArgs;
+use crate::op::ObjectStoreOp;
+use crate::run::{Phase, RunDir};
+use crate::summary::SummaryFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// s3 server access logs, one request per line
+    S3,
+    /// minio audit logs, one json object per line
+    Minio,
+}
+
+/// A logged request, or part of one.
+struct Logged {
+    time_ms: i64,
+    op: ObjectStoreOp,
+    bucket: String,
+    /// the object, or the prefix listed
+    key: String,
+    /// 0 for the parts of a multipart upload, which only add bytes to it
+    requests: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct Counts {
+    requests: u64,
+    bytes: u64,
+}
+
+pub fn reconcile(args: &ReconcileArgs) -> io::Result<()> {
+    let recorded = RunDir::read_phases(&args.run_dir)?;
+    let (bucket, prefix) = bucket_and_prefix(&recorded.dataset_uri)?;
+    if recorded.timings.values().any(|t| t.started_ms == 0) {
+        return Err(invalid(
+            "the run didn't record when its phases started, rerun it".to_string(),
+        ));
+    }
+
+    let mut logged = BTreeMap::<(Phase, ObjectStoreOp), Counts>::new();
+    let mut outside = 0;
+    for file in log_files(&args.logs)? {
+        for line in fs::read_to_string(&file)?.lines() {
+            let entry = match args.log_format {
+                LogFormat::S3 => parse_s3(line),
+                LogFormat::Minio => parse_minio(line),
+            };
+            let Some(entry) = entry else {
+                continue;
+            };
+            if entry.bucket != bucket || !entry.key.starts_with(&prefix) {
+                continue;
+            }
+            let phase = recorded.timings.iter().find(|(_, t)| {
+                let end = t.started_ms + (t.wall_us / 1000) as i64;
+                (t.started_ms - args.slack_ms..=end + args.slack_ms).contains(&entry.time_ms)
+            });
+            match phase {
+                Some((phase, _)) => {
+                    let counts = logged.entry((*phase, entry.op)).or_default();
+                    counts.requests += entry.requests;
+                    counts.bytes += entry.bytes;
+                }
+                // between phases, like warmups, which aren't recorded either
+                None => outside += entry.requests,
+            }
+        }
+    }
+    if outside > 0 {
+        log::info!("ignored {outside} logged requests made outside any phase");
+    }
+
+    let mut rows = BTreeMap::<(Phase, ObjectStoreOp), (Counts, Counts)>::new();
+    for (phase, ops) in &recorded.summaries {
+        for (op, summary) in ops {
+            rows.entry((*phase, *op)).or_default().0 = Counts {
+                requests: summary.count,
+                bytes: summary.bytes,
+            };
+        }
+    }
+    for (key, counts) in logged {
+        rows.entry(key).or_default().1 = counts;
+    }
+
+    let missed = rows
+        .values()
+        .map(|(recorded, logged)| logged.requests.saturating_sub(recorded.requests))
+        .sum::<u64>();
+    if missed > 0 {
+        log::warn!("the server logged {missed} requests more than the wrapper recorded");
+    }
+
+    print!("{}", render(SummaryFormat::Text, &rows));
+    let output = args
+        .run_dir
+        .join(format!("reconcile.{}", args.format.ext()));
+    fs::write(&output, render(args.format, &rows))?;
+    println!("wrote {}", output.display());
+    Ok(())
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// the bucket of an s3 uri and the key prefix of everything under it
+fn bucket_and_prefix(uri: &str) -> io::Result<(String, String)> {
+    let url = Url::parse(uri).map_err(|e| invalid(format!("dataset uri {uri:?}: {e}")))?;
+    if url.scheme() != "s3" {
+        return Err(invalid(format!("{uri} isn't in s3, there are no logs")));
+    }
+    let bucket = url.host_str().unwrap_or_default().to_string();
+    let prefix = match url.path().trim_matches('/') {
+        "" => String::new(),
+        prefix => format!("{prefix}/"),
+    };
+    Ok((bucket, prefix))
+}
+
+/// `paths`, with directories replaced by the files in them
+fn log_files(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?.path();
+                if entry.is_file() {
+                    files.push(entry);
+                }
+            }
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// the space separated fields of an access log line, `[...]` and `"..."`
+/// being a field each
+fn s3_fields(line: &str) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let (field, tail) = match rest.as_bytes()[0] {
+            b'[' => rest[1..].split_once(']').unwrap_or((&rest[1..], "")),
+            b'"' => rest[1..].split_once('"').unwrap_or((&rest[1..], "")),
+            _ => rest.split_once(' ').unwrap_or((rest, "")),
+        };
+        fields.push(field);
+        rest = tail.trim_start();
+    }
+    fields
+}
+
+fn parse_s3(line: &str) -> Option<Logged> {
+    let fields = s3_fields(line);
+    let (bucket, time, operation, key, uri) = (
+        fields.get(1)?,
+        fields.get(2)?,
+        fields.get(6)?,
+        fields.get(7)?,
+        fields.get(8)?,
+    );
+    let number = |i: usize| {
+        fields
+            .get(i)
+            .and_then(|f| f.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    let (bytes_sent, object_size) = (number(11), number(12));
+
+    let (op, requests, bytes) = match *operation {
+        "REST.GET.OBJECT" => (ObjectStoreOp::Get, 1, bytes_sent),
+        "REST.HEAD.OBJECT" => (ObjectStoreOp::Head, 1, 0),
+        "REST.PUT.OBJECT" => (ObjectStoreOp::Put, 1, object_size),
+        "REST.PUT.PART" => (ObjectStoreOp::Put, 0, object_size),
+        // completing a multipart upload
+        "REST.POST.UPLOAD" => (ObjectStoreOp::Put, 1, 0),
+        "REST.GET.BUCKET" => (ObjectStoreOp::List, 1, 0),
+        "REST.DELETE.OBJECT" => (ObjectStoreOp::Delete, 1, 0),
+        "REST.COPY.OBJECT" => (ObjectStoreOp::Copy, 1, 0),
+        _ => return None,
+    };
+    let key = match op {
+        // e.g. `GET /bucket?list-type=2&prefix=test.lance%2F HTTP/1.1`
+        ObjectStoreOp::List => list_prefix(uri)?,
+        _ => key.to_string(),
+    };
+    let time = chrono::DateTime::parse_from_str(time, "%d/%b/%Y:%H:%M:%S %z").ok()?;
+    Some(Logged {
+        time_ms: time.timestamp_millis(),
+        op,
+        bucket: bucket.to_string(),
+        key,
+        requests,
+        bytes,
+    })
+}
+
+fn list_prefix(request: &str) -> Option<String> {
+    let target = request.split(' ').nth(1)?;
+    let url = Url::parse(&format!("http://localhost{target}")).ok()?;
+    let prefix = url
+        .query_pairs()
+        .find(|(key, _)| key == "prefix")
+        .map(|(_, prefix)| prefix.into_owned());
+    Some(prefix.unwrap_or_default())
+}
+
+fn parse_minio(line: &str) -> Option<Logged> {
+    let entry: Value = serde_json::from_str(line).ok()?;
+    let api = &entry["api"];
+    let number = |field: &str| api[field].as_u64().unwrap_or(0);
+    let (op, requests, bytes) = match api["name"].as_str()? {
+        "GetObject" => (ObjectStoreOp::Get, 1, number("tx")),
+        "HeadObject" => (ObjectStoreOp::Head, 1, 0),
+        "PutObject" => (ObjectStoreOp::Put, 1, number("rx")),
+        "PutObjectPart" => (ObjectStoreOp::Put, 0, number("rx")),
+        "CompleteMultipartUpload" => (ObjectStoreOp::Put, 1, 0),
+        "ListObjectsV1" | "ListObjectsV2" => (ObjectStoreOp::List, 1, 0),
+        "DeleteObject" => (ObjectStoreOp::Delete, 1, 0),
+        "CopyObject" => (ObjectStoreOp::Copy, 1, 0),
+        _ => return None,
+    };
+    let key = match op {
+        ObjectStoreOp::List => entry["requestQuery"]["prefix"].as_str().unwrap_or_default(),
+        _ => api["object"].as_str().unwrap_or_default(),
+    };
+    let time = chrono::DateTime::parse_from_rfc3339(entry["time"].as_str()?).ok()?;
+    Some(Logged {
+        time_ms: time.timestamp_millis(),
+        op,
+        bucket: api["bucket"].as_str()?.to_string(),
+        key: key.to_string(),
+        requests,
+        bytes,
+    })
+}
+
+fn render(
+    format: SummaryFormat,
+    rows: &BTreeMap<(Phase, ObjectStoreOp), (Counts, Counts)>,
+) -> String {
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => {
+            out.push_str("phase,op,recorded,logged,recorded_bytes,logged_bytes,missed\n")
+        }
+        SummaryFormat::Text => writeln!(
+            out,
+            "{:<14} {:<8} {:>10} {:>10} {:>14} {:>14} {:>8}",
+            "phase", "op", "recorded", "logged", "recorded_bytes", "logged_bytes", "missed"
+        )
+        .unwrap(),
+    }
+    for ((phase, op), (recorded, logged)) in rows {
+        let missed = logged.requests.saturating_sub(recorded.requests);
+        match format {
+            SummaryFormat::Csv => writeln!(
+                out,
+                "{},{op},{},{},{},{},{missed}",
+                phase.as_str(),
+                recorded.requests,
+                logged.requests,
+                recorded.bytes,
+                logged.bytes
+            ),
+            SummaryFormat::Text => writeln!(
+                out,
+                "{:<14} {:<8} {:>10} {:>10} {:>14} {:>14} {:>8}",
+                phase.as_str(),
+                op.as_str(),
+                recorded.requests,
+                logged.requests,
+                recorded.bytes,
+                logged.bytes,
+                missed
+            ),
+        }
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::fork::PerProcess;
+#[cfg(feature = "profiling")]
+use crate::op::ObjectStoreOp;
+#[cfg(feature = "profiling")]
+use crate::profiling::CustomProfiles;
+use crate::profiling::{ProfiledOp, ProfilingObjectStoreWrapper};
+use crate::run::{Phase, RunDir};
+use crate::toggles::OpToggles;
+
+/// Profiling wrappers registered under a name, so code that wraps many stores
+/// (one per dataset, say) can reset and dump all of their profiles at once.
+/// [`ProfiledOp`]s aren't made against a store, so they're kept here instead,
+/// and so are the ops every wrapper profiles, since those are flipped for the
+/// whole process.
+#[derive(Default)]
+pub struct ProfilerRegistry {
+    wrappers: Mutex<BTreeMap<String, Arc<ProfilingObjectStoreWrapper>>>,
+    toggles: OpToggles,
+    #[cfg(feature = "profiling")]
+    custom: CustomProfiles,
+}
+
+/// the process wide registry. a forked child starts over with an empty one,
+/// the wrappers registered before the fork are the parent's to write out
+pub fn registry() -> &'static ProfilerRegistry {
+    static REGISTRY: PerProcess<ProfilerRegistry> = PerProcess::new(ProfilerRegistry::default);
+    REGISTRY.get()
+}
+
+impl ProfilerRegistry {
+    /// registers `wrapper` as `name`, handing it back ready to give to lance
+    pub fn register(
+        &self,
+        name: &str,
+        wrapper: ProfilingObjectStoreWrapper,
+    ) -> Arc<ProfilingObjectStoreWrapper> {
+        let wrapper = Arc::new(wrapper);
+        if self
+            .wrappers
+            .lock()
+            .insert(name.to_string(), wrapper.clone())
+            .is_some()
+        {
+            log::debug!("replaced profiler already registered as {name}");
+        }
+        wrapper
+    }
+
+    /// which ops are profiled right now
+    pub fn toggles(&self) -> &OpToggles {
+        &self.toggles
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.wrappers.lock().keys().cloned().collect()
+    }
+
+    pub fn reset_all(&self) {
+        for wrapper in self.wrappers.lock().values() {
+            wrapper.reset();
+        }
+        #[cfg(feature = "profiling")]
+        self.custom.reset();
+    }
+
+    /// records an occurrence of `op` with `value`, with the current stack
+    #[cfg(feature = "profiling")]
+    pub fn record(&self, op: &dyn ProfiledOp, value: i64) {
+        self.custom.record(op, value);
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub fn record(&self, _op: &dyn ProfiledOp, _value: i64) {}
+
+    /// see [`ProfilingObjectStoreWrapper::set_dataset_version`]
+    pub fn set_dataset_version(&self, version: u64) {
+        for wrapper in self.wrappers.lock().values() {
+            wrapper.set_dataset_version(version);
+        }
+    }
+
+    /// what the wrapper registered as `name` recorded of `op` so far, see
+    /// [`ProfilingObjectStoreWrapper::report`]
+    #[cfg(feature = "profiling")]
+    pub fn report(&self, name: &str, op: ObjectStoreOp) -> Option<pprof::protos::Profile> {
+        self.wrappers.lock().get(name)?.report(op)
+    }
+
+    /// writes the profiles of every registered wrapper for `phase`. each file is
+    /// named after the op prefixed with the name it was registered under,
+    /// except those of [`ProfiledOp`]s which are only named after the op
+    pub fn flush_all(&self, run_dir: &RunDir, phase: Phase) {
+        for (name, wrapper) in self.wrappers.lock().iter() {
+            wrapper.write_profiles(run_dir, phase, name);
+        }
+        #[cfg(feature = "profiling")]
+        self.custom.write(run_dir, phase);
+    }
+}
@@ -0,0 +1,162 @@
+//! `pprof-dev report`: a single html file with the summary tables, the config
+//! the run used and a flamegraph of every profile, so results can be shared
+//! without the run directory. `pprof-dev diff` renders a differential
+//! flamegraph of two profiles.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{DiffArgs, ReportArgs};
+use crate::run::{RunDir, CONFIG_FILE};
+use crate::summary::PhaseSummary;
+
+pub fn write_report(args: &ReportArgs) -> io::Result<()> {
+    let summaries = RunDir::read_summaries(&args.run_dir)?;
+    let config = fs::read_to_string(args.run_dir.join(CONFIG_FILE)).unwrap_or_default();
+
+    let title = args
+        .run_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>pprof-dev {}</title>",
+        escape(&title)
+    )
+    .unwrap();
+    html.push_str(STYLE);
+    writeln!(
+        html,
+        "</head><body><h1>pprof-dev run {}</h1>",
+        escape(&title)
+    )
+    .unwrap();
+
+    html.push_str("<h2>Summary</h2>\n");
+    for (phase, ops) in &summaries {
+        writeln!(html, "<h3>{}</h3>", phase.as_str()).unwrap();
+        html.push_str(&summary_table(ops));
+    }
+
+    html.push_str("<h2>Profiles</h2>\n");
+    for profile in find_profiles(&args.run_dir)? {
+        let name = profile
+            .strip_prefix(&args.run_dir)
+            .unwrap_or(&profile)
+            .display()
+            .to_string();
+        writeln!(html, "<h3>{}</h3>", escape(&name)).unwrap();
+        html.push_str(&flamegraph(&profile, &name));
+    }
+
+    html.push_str("<h2>Config</h2>\n");
+    writeln!(html, "<pre>{}</pre>", escape(&config)).unwrap();
+    html.push_str("</body></html>\n");
+
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.run_dir.join("report.html"));
+    fs::write(&output, html)?;
+    println!("wrote {}", output.display());
+    Ok(())
+}
+
+const STYLE: &str = "<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+object { width: 100%; }
+</style>
+";
+
+fn summary_table(ops: &PhaseSummary) -> String {
+    let mut table = String::from(
+        "<table><tr><th>op</th><th>count</th><th>bytes</th>\
+         <th>p50 (us)</th><th>p99 (us)</th><th>errors</th></tr>\n",
+    );
+    for (op, s) in ops {
+        writeln!(
+            table,
+            "<tr><td>{op}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            s.count, s.bytes, s.p50_us, s.p99_us, s.errors
+        )
+        .unwrap();
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+/// every profile under `dir`, in order of their paths
+pub fn find_profiles(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut profiles = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            profiles.extend(find_profiles(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "pb") {
+            profiles.push(path);
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
+#[cfg(feature = "profiling")]
+fn flamegraph(path: &Path, name: &str) -> String {
+    use base64::Engine;
+
+    let svg = crate::flamegraph::read_profile(path)
+        .and_then(|profile| crate::flamegraph::render_svg(&profile, name));
+    match svg {
+        // embedded as an object rather than inline so each keeps its own
+        // search/zoom script without their element ids clashing
+        Ok(svg) => format!(
+            "<object type=\"image/svg+xml\" data=\"data:image/svg+xml;base64,{}\"></object>\n",
+            base64::engine::general_purpose::STANDARD.encode(svg)
+        ),
+        Err(e) => format!("<p>no flamegraph: {}</p>\n", escape(&e.to_string())),
+    }
+}
+
+#[cfg(not(feature = "profiling"))]
+fn flamegraph(_path: &Path, _name: &str) -> String {
+    "<p>built without the profiling feature, no flamegraph</p>\n".to_string()
+}
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(feature = "profiling")]
+pub fn write_diff(args: &DiffArgs) -> io::Result<()> {
+    let before = crate::flamegraph::read_profile(&args.before)?;
+    let after = crate::flamegraph::read_profile(&args.after)?;
+    let title = format!("{} vs {}", args.after.display(), args.before.display());
+    let svg = crate::flamegraph::render_diff_svg(&before, &after, &title)?;
+
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.after.with_extension("diff.svg"));
+    fs::write(&output, svg)?;
+    println!("wrote {}", output.display());
+    Ok(())
+}
+
+#[cfg(not(feature = "profiling"))]
+pub fn write_diff(_args: &DiffArgs) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "built without the profiling feature, can't read profiles",
+    ))
+}
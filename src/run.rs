@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Args;
+use crate::lance_stats::LanceCounts;
+use crate::metadata;
+use crate::shared::{DirLock, PROCESSES_DIR};
+use crate::summary::{PhaseSummary, PhaseTiming};
+
+const STATE_FILE: &str = "state.json";
+pub const CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum Phase {
+    Write,
+    Index,
+    QueryStartup,
+    Query,
+    QueryCold,
+    QueryWarm,
+    IndexPrime,
+    QueryPrimed,
+    Prefilter,
+    Take,
+    Scan,
+    TimeRange,
+    Sql,
+    OptimizeIndex,
+    Evolve,
+    Cleanup,
+    Rewrite,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Write => "write",
+            Phase::Index => "index",
+            Phase::QueryStartup => "query_startup",
+            Phase::Query => "query",
+            Phase::QueryCold => "query_cold",
+            Phase::QueryWarm => "query_warm",
+            Phase::IndexPrime => "index_prime",
+            Phase::QueryPrimed => "query_primed",
+            Phase::Prefilter => "prefilter",
+            Phase::Take => "take",
+            Phase::Scan => "scan",
+            Phase::TimeRange => "time_range",
+            Phase::Sql => "sql",
+            Phase::OptimizeIndex => "optimize_index",
+            Phase::Evolve => "evolve",
+            Phase::Cleanup => "cleanup",
+            Phase::Rewrite => "rewrite",
+        }
+    }
+
+    /// the tracing span the phase's workload runs in, for exporters like
+    /// `--otlp-endpoint` to group its requests by
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!("phase", phase = self.as_str())
+    }
+}
+
+// for `--phase-timeout`, and reading the phases back out of a trace
+impl FromStr for Phase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+/// What a run does once one of its phases times out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnTimeout {
+    /// go on to the next phase, unless there's no dataset to run it against
+    Continue,
+    /// skip the rest of the phases
+    Abort,
+}
+
+// for the PPROF_DEV_ON_TIMEOUT override
+impl FromStr for OnTimeout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RunState {
+    dataset_uri: String,
+    // kept in the state so a resumed run keeps naming its outputs consistently
+    timestamp: String,
+    completed: Vec<Phase>,
+    #[serde(default)]
+    summaries: BTreeMap<Phase, PhaseSummary>,
+    #[serde(default)]
+    timings: BTreeMap<Phase, PhaseTiming>,
+    #[serde(default)]
+    lance_counts: BTreeMap<Phase, LanceCounts>,
+}
+
+/// The directory a single run writes its profiles into, along with the state
+/// needed to resume the run if it dies part way through.
+#[derive(Clone)]
+pub struct RunDir {
+    path: PathBuf,
+    output_dir: PathBuf,
+    output_template: String,
+    run_id: String,
+    /// where the run directory is relative to the output directory, what
+    /// `{run_id}` in the output template stands for
+    relative_path: String,
+    /// the redacted config on one line, for embedding in profiles
+    config: String,
+    state: RunState,
+    /// for a process of a shared run, held until the last clone is dropped
+    _lock: Option<Arc<DirLock>>,
+}
+
+impl RunDir {
+    pub fn open(args: &Args) -> io::Result<Self> {
+        let timestamp = timestamp();
+        let run_id = args
+            .run_id
+            .clone()
+            .unwrap_or_else(|| default_run_id(&timestamp));
+        if args.process.is_some() && args.run_id.is_none() {
+            return Err(io::Error::other(
+                "--process needs a --run-id, the one all the run's processes share",
+            ));
+        }
+        let relative_path = relative_path(&run_id, args);
+        let path = args.output_dir.join(&relative_path);
+        fs::create_dir_all(&path)?;
+        let lock = match &args.process {
+            Some(_) => Some(Arc::new(DirLock::acquire(&path)?)),
+            None => None,
+        };
+
+        let dataset_uri = args.uri.as_str();
+        let state_path = path.join(STATE_FILE);
+        let state = if args.resume && state_path.exists() {
+            let state: RunState = serde_json::from_slice(&fs::read(&state_path)?)?;
+            if state.dataset_uri != dataset_uri {
+                log::warn!(
+                    "resuming run against {} (from {}) instead of {}",
+                    state.dataset_uri,
+                    state_path.display(),
+                    dataset_uri
+                );
+            }
+            state
+        } else {
+            if args.resume {
+                log::warn!(
+                    "no state found at {}, starting a new run",
+                    state_path.display()
+                );
+            }
+            RunState {
+                dataset_uri: dataset_uri.to_string(),
+                timestamp,
+                completed: vec![],
+                summaries: BTreeMap::new(),
+                timings: BTreeMap::new(),
+                lance_counts: BTreeMap::new(),
+            }
+        };
+
+        let config = args.redacted();
+        fs::write(path.join(CONFIG_FILE), serde_json::to_vec_pretty(&config)?)?;
+
+        let run_dir = Self {
+            path,
+            output_dir: args.output_dir.clone(),
+            output_template: args.output_template.clone(),
+            run_id,
+            relative_path,
+            config: serde_json::to_string(&config)?,
+            state,
+            _lock: lock,
+        };
+        run_dir.save()?;
+        Ok(run_dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// `key=value` lines describing the run, written into the comments of
+    /// each profile so a profile file found on its own is still self
+    /// describing
+    pub fn profile_comments(&self, phase: Phase, op: &str) -> Vec<String> {
+        let mut comments = vec![
+            format!("run_id={}", self.run_id),
+            format!("timestamp={}", self.state.timestamp),
+            format!("phase={}", phase.as_str()),
+            format!("op={op}"),
+        ];
+        comments.extend(
+            metadata::build_info()
+                .iter()
+                .map(|(key, value)| format!("{key}={value}")),
+        );
+        comments.push(format!("config={}", self.config));
+        comments
+    }
+
+    pub fn dataset_uri(&self) -> &str {
+        &self.state.dataset_uri
+    }
+
+    pub fn is_complete(&self, phase: Phase) -> bool {
+        self.state.completed.contains(&phase)
+    }
+
+    pub fn mark_complete(
+        &mut self,
+        phase: Phase,
+        summary: PhaseSummary,
+        timing: PhaseTiming,
+    ) -> io::Result<()> {
+        if !self.is_complete(phase) {
+            self.state.completed.push(phase);
+        }
+        self.record_partial(phase, summary, timing)
+    }
+
+    /// keeps what an interrupted phase recorded, without marking it complete
+    pub fn record_partial(
+        &mut self,
+        phase: Phase,
+        summary: PhaseSummary,
+        timing: PhaseTiming,
+    ) -> io::Result<()> {
+        self.state.summaries.insert(phase, summary);
+        self.state.timings.insert(phase, timing);
+        self.save()
+    }
+
+    pub fn record_lance_counts(&mut self, phase: Phase, counts: LanceCounts) -> io::Result<()> {
+        self.state.lance_counts.insert(phase, counts);
+        self.save()
+    }
+
+    /// request statistics of every phase completed so far, including those
+    /// from before the run was resumed
+    pub fn summaries(&self) -> &BTreeMap<Phase, PhaseSummary> {
+        &self.state.summaries
+    }
+
+    /// wall time of every phase completed so far, including those from before
+    /// the run was resumed
+    pub fn timings(&self) -> &BTreeMap<Phase, PhaseTiming> {
+        &self.state.timings
+    }
+
+    /// the IO lance counted in each phase that ran any scanners
+    pub fn lance_counts(&self) -> &BTreeMap<Phase, LanceCounts> {
+        &self.state.lance_counts
+    }
+
+    /// path of a file that covers the whole run rather than a single phase
+    pub fn run_file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// resolves the output template for a file produced by `phase`, creating
+    /// any parent directories it needs
+    pub fn output_path(&self, phase: Phase, op: &str, ext: &str) -> io::Result<PathBuf> {
+        let relative = self
+            .output_template
+            .replace("{run_id}", &self.relative_path)
+            .replace("{timestamp}", &self.state.timestamp)
+            .replace("{phase}", phase.as_str())
+            .replace("{op}", op)
+            .replace("{ext}", ext);
+        let path = self.output_dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+
+    /// the directory of the run `args` is the config of, which has a run id
+    pub fn path_for(args: &Args) -> PathBuf {
+        let run_id = args.run_id.as_deref().expect("the run has no --run-id");
+        args.output_dir.join(relative_path(run_id, args))
+    }
+
+    /// the summaries recorded in an existing run directory
+    pub fn read_summaries(path: &Path) -> io::Result<BTreeMap<Phase, PhaseSummary>> {
+        Ok(read_state(path)?.summaries)
+    }
+
+    /// the dataset, summaries and timings recorded in an existing run directory
+    pub fn read_phases(path: &Path) -> io::Result<RecordedPhases> {
+        let state = read_state(path)?;
+        Ok(RecordedPhases {
+            dataset_uri: state.dataset_uri,
+            summaries: state.summaries,
+            timings: state.timings,
+        })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        // write then rename so a crash mid-write can't leave a truncated state file
+        let tmp = self.path.join(format!("{STATE_FILE}.tmp"));
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.state)?)?;
+        fs::rename(tmp, self.path.join(STATE_FILE))
+    }
+}
+
+/// the local time to the second, e.g. `20250327-101500`
+pub fn timestamp() -> String {
+    chrono::Local::now().format("%Y%m%d-%H%M%S").to_string()
+}
+
+/// `timestamp` and the pid, so runs started in the same second don't share
+/// a run directory
+pub fn default_run_id(timestamp: &str) -> String {
+    format!("{timestamp}-{}", std::process::id())
+}
+
+/// where the directory of a run is relative to the output directory. each
+/// process of a shared run gets a directory of its own in it
+fn relative_path(run_id: &str, args: &Args) -> String {
+    match &args.process {
+        Some(process) => format!("{run_id}/{PROCESSES_DIR}/{process}"),
+        None => run_id.to_string(),
+    }
+}
+
+fn read_state(path: &Path) -> io::Result<RunState> {
+    Ok(serde_json::from_slice(&fs::read(path.join(STATE_FILE))?)?)
+}
+
+/// What an existing run directory recorded of its phases.
+pub struct RecordedPhases {
+    pub dataset_uri: String,
+    pub summaries: BTreeMap<Phase, PhaseSummary>,
+    pub timings: BTreeMap<Phase, PhaseTiming>,
+}
@@ -0,0 +1,230 @@
+//! Which operator of the workload (knn search, filtered scan, take, ...) a
+//! request was made on behalf of. Lance's exec nodes can't be instrumented
+//! from outside, so the workload wraps each of its lance calls in the operator
+//! that call plans, and requests are attributed to that.
+//!
+//! The operator is a tokio task-local, which doesn't survive lance spawning
+//! IO onto tasks of its own. Requests from those are attributed to the one
+//! operator running at the time, if there is exactly one.
+//!
+//! Labels of the caller's own, such as a tenant or request id, can be put on
+//! every request made within a scope the same way with [`with_labels`].
+//!
+//! With `--task-labels` requests are also labelled with the id of the tokio
+//! task that made them, and where that task was spawned when tracing knows,
+//! to tell the IO of lance's `spawn_blocking` and background tasks apart from
+//! the query's own.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use object_store::path::Path;
+
+use crate::fork::PerProcess;
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+use crate::summary::{PhaseSummary, SummaryCollector, SummaryFormat};
+
+tokio::task_local! {
+    static NODE: &'static str;
+    static LABELS: Labels;
+}
+
+/// operators being run right now, with how many times each
+static ACTIVE: PerProcess<Mutex<BTreeMap<&'static str, usize>>> = PerProcess::new(Mutex::default);
+
+/// node requests get when it's not known which operator made them
+const UNATTRIBUTED: &str = "unattributed";
+
+/// runs `fut` with its requests attributed to `node`
+pub async fn in_node<F: Future>(node: &'static str, fut: F) -> F::Output {
+    let _active = Active::enter(node);
+    NODE.scope(node, fut).await
+}
+
+/// the operator the current request is being made for
+pub fn current() -> &'static str {
+    NODE.try_with(|node| *node).unwrap_or_else(|_| {
+        let active = ACTIVE.get().lock().unwrap();
+        match active.len() {
+            1 => active.keys().next().unwrap(),
+            _ => UNATTRIBUTED,
+        }
+    })
+}
+
+/// runs `fut` with `labels` added to those of every request it makes, after
+/// the labels of any enclosing call. like the operator, they don't reach the
+/// requests of tasks lance spawns
+pub async fn with_labels<F: Future>(labels: Labels, fut: F) -> F::Output {
+    let labels = LABELS
+        .try_with(|outer| outer.clone().with(&labels))
+        .unwrap_or(labels);
+    LABELS.scope(labels, fut).await
+}
+
+/// calls `f` with `labels` preceded by those of the enclosing [`with_labels`],
+/// and followed by the task's if `tasks` is set
+fn with_scoped(labels: &Labels, tasks: bool, f: impl FnOnce(&Labels)) {
+    let scoped = LABELS.try_with(|scoped| scoped.clone().with(labels)).ok();
+    match (scoped, tasks) {
+        (scoped, true) => f(&with_task(scoped.unwrap_or_else(|| labels.clone()))),
+        (Some(scoped), false) => f(&scoped),
+        (None, false) => f(labels),
+    }
+}
+
+/// `labels` followed by the id of the current task, and the kind of task it
+/// is and where it was spawned if tracing knows, see
+/// [`crate::otel::spawn_location`]
+fn with_task(mut labels: Labels) -> Labels {
+    if let Some(id) = tokio::task::try_id() {
+        labels = labels.str("task", id.to_string());
+    }
+    if let Some((kind, location)) = crate::otel::spawn_location() {
+        labels = labels.str("spawn_kind", kind).str("spawned_at", location);
+    }
+    labels
+}
+
+struct Active(&'static str);
+
+impl Active {
+    fn enter(node: &'static str) -> Self {
+        *ACTIVE.get().lock().unwrap().entry(node).or_default() += 1;
+        Self(node)
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        let mut active = ACTIVE.get().lock().unwrap();
+        // entered before a fork, in the parent's copy
+        let Some(count) = active.get_mut(self.0) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            active.remove(self.0);
+        }
+    }
+}
+
+/// Hooks that summarize requests per operator, like [`SummaryCollector`]
+/// does per phase.
+#[derive(Default)]
+pub struct NodeCollector {
+    nodes: parking_lot::Mutex<BTreeMap<&'static str, SummaryCollector>>,
+}
+
+impl NodeCollector {
+    /// returns the summary of each operator since the last call
+    pub fn take(&self) -> BTreeMap<&'static str, PhaseSummary> {
+        std::mem::take(&mut *self.nodes.lock())
+            .into_iter()
+            .map(|(node, collector)| (node, collector.take()))
+            .collect()
+    }
+}
+
+impl StoreHooks for NodeCollector {
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        self.nodes
+            .lock()
+            .entry(current())
+            .or_default()
+            .on_complete(op, path, range, bytes, duration, outcome, labels);
+    }
+}
+
+/// Hooks firing `inner` with each request's labels preceded by the ones it was
+/// made within, see [`with_labels`], and followed by its task's when the
+/// second field is set.
+pub struct ScopedLabels(pub Arc<dyn StoreHooks>, pub bool);
+
+impl StoreHooks for ScopedLabels {
+    fn on_request(&self, op: ObjectStoreOp, path: &Path, range: Option<Range<usize>>) {
+        self.0.on_request(op, path, range);
+    }
+
+    fn on_first_byte(&self, op: ObjectStoreOp, path: &Path, ttfb: Duration, labels: &Labels) {
+        with_scoped(labels, self.1, |labels| {
+            self.0.on_first_byte(op, path, ttfb, labels)
+        });
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        with_scoped(labels, self.1, |labels| {
+            self.0
+                .on_complete(op, path, range, bytes, duration, outcome, labels)
+        });
+    }
+}
+
+pub type NodeSummaries = BTreeMap<Phase, BTreeMap<&'static str, PhaseSummary>>;
+
+pub fn render(format: SummaryFormat, phases: &NodeSummaries) -> String {
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => out.push_str("phase,node,op,count,bytes,p50_us,p99_us\n"),
+        SummaryFormat::Text => writeln!(
+            out,
+            "{:<14} {:<12} {:<8} {:>8} {:>14} {:>10} {:>10}",
+            "phase", "node", "op", "count", "bytes", "p50_us", "p99_us"
+        )
+        .unwrap(),
+    }
+    for (phase, nodes) in phases {
+        for (node, ops) in nodes {
+            for (op, s) in ops {
+                match format {
+                    SummaryFormat::Csv => writeln!(
+                        out,
+                        "{},{node},{op},{},{},{},{}",
+                        phase.as_str(),
+                        s.count,
+                        s.bytes,
+                        s.p50_us,
+                        s.p99_us
+                    ),
+                    SummaryFormat::Text => writeln!(
+                        out,
+                        "{:<14} {:<12} {:<8} {:>8} {:>14} {:>10} {:>10}",
+                        phase.as_str(),
+                        node,
+                        op.as_str(),
+                        s.count,
+                        s.bytes,
+                        s.p50_us,
+                        s.p99_us
+                    ),
+                }
+                .unwrap();
+            }
+        }
+    }
+    out
+}
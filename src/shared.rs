@@ -0,0 +1,113 @@
+//! `--process` and `pprof-dev finalize`: several processes on one host, e.g.
+//! forked benchmark drivers, writing into the same run directory. Each
+//! process of the run writes everything into `processes/{process}/` of it,
+//! where nothing of another process's is, and holds a lock on that directory
+//! for as long as it runs. Once they're all done, `finalize` merges their
+//! profiles into the run directory itself.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+use crate::cli::FinalizeArgs;
+use crate::merge;
+use crate::report;
+
+/// where in the run directory the processes' directories are
+pub const PROCESSES_DIR: &str = "processes";
+
+/// the lock file in a process's directory, and in the run directory while
+/// it's being finalized
+const LOCK_FILE: &str = ".lock";
+
+/// A lock file, there until it's dropped. Taking it fails while another
+/// holds it, or if one that held it died without removing it.
+pub struct DirLock {
+    path: PathBuf,
+    /// the process holding it, a forked child's copy isn't removed
+    pid: u32,
+}
+
+impl DirLock {
+    pub fn acquire(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(LOCK_FILE);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let holder = fs::read_to_string(&path).unwrap_or_default();
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!(
+                        "{} is locked by pid {}, remove {} if that process is gone",
+                        dir.display(),
+                        holder.trim(),
+                        path.display()
+                    ),
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        let pid = std::process::id();
+        writeln!(file, "{pid}")?;
+        Ok(Self { path, pid })
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        if self.pid != std::process::id() {
+            return;
+        }
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::warn!("couldn't remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// merges the profiles of every process of the run in `args.run_dir` into
+/// the run directory, each with the same path relative to it as it has in the
+/// processes' directories
+pub fn finalize(args: &FinalizeArgs) -> io::Result<()> {
+    let _lock = DirLock::acquire(&args.run_dir)?;
+    let processes_dir = args.run_dir.join(PROCESSES_DIR);
+    let mut processes: Vec<PathBuf> = fs::read_dir(&processes_dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<_>>()?;
+    processes.retain(|path| path.is_dir());
+    processes.sort();
+
+    // the profiles of the same phase and op, by their path in each process's
+    // directory
+    let mut profiles: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for process in &processes {
+        if process.join(LOCK_FILE).exists() {
+            let message = format!("{} is still running, or died", process.display());
+            if !args.force {
+                return Err(io::Error::other(format!(
+                    "{message}, pass --force to merge what it wrote anyway"
+                )));
+            }
+            log::warn!("{message}, merging what it wrote so far");
+        }
+        for profile in report::find_profiles(process)? {
+            let relative = profile.strip_prefix(process).unwrap().to_path_buf();
+            profiles.entry(relative).or_default().push(profile);
+        }
+    }
+
+    for (relative, paths) in &profiles {
+        let output = args.run_dir.join(relative);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        merge::merge_files(paths, &output)?;
+    }
+    println!(
+        "merged {} profiles of {} processes into {}",
+        profiles.len(),
+        processes.len(),
+        args.run_dir.display()
+    );
+    Ok(())
+}
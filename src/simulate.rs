@@ -0,0 +1,242 @@
+//! `pprof-dev simulate`: replays the gets in a run's `trace.json` as if they
+//! had been read in aligned blocks of other sizes, with readahead past each
+//! read, to pick an object store block size without rerunning the workload.
+//!
+//! Blocks read stay cached for the rest of the phase, so a get only reads the
+//! blocks it's missing, a run of them per request. Wasted bytes are those read
+//! that no get asked for, including the ones read more than once.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+use serde_json::Value;
+
+use crate::cli::SimulateArgs;
+use crate::run::Phase;
+use crate::summary::SummaryFormat;
+
+struct Get {
+    ts: f64,
+    path: String,
+    range: Range<u64>,
+}
+
+#[derive(Default)]
+struct Counts {
+    requests: u64,
+    read: u64,
+    wasted: u64,
+}
+
+pub fn simulate(args: &SimulateArgs) -> io::Result<()> {
+    let path = args.run_dir.join("trace.json");
+    let trace = match fs::read_to_string(&path) {
+        Ok(trace) => trace,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} doesn't exist, rerun with --trace", path.display()),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+    let trace: Value = serde_json::from_str(&trace)?;
+    let phases = gets_by_phase(trace["traceEvents"].as_array().unwrap_or(&vec![]));
+
+    // an object's size is taken to be the furthest any get read into it, so
+    // readahead doesn't run past its end
+    let mut sizes = HashMap::<&str, u64>::new();
+    for get in phases.values().flatten() {
+        let size = sizes.entry(get.path.as_str()).or_default();
+        *size = (*size).max(get.range.end);
+    }
+
+    let mut rows = vec![];
+    for (phase, gets) in &phases {
+        let distinct = distinct_bytes(gets);
+        let read = gets
+            .iter()
+            .map(|g| g.range.end - g.range.start)
+            .sum::<u64>();
+        let actual = Counts {
+            requests: gets.len() as u64,
+            read,
+            wasted: read - distinct,
+        };
+        rows.push((*phase, None, actual));
+        for &block_size in &args.block_sizes {
+            for &readahead in &args.readahead {
+                let mut counts = replay(gets, &sizes, block_size, readahead);
+                counts.wasted = counts.read - distinct;
+                rows.push((*phase, Some((block_size, readahead)), counts));
+            }
+        }
+    }
+
+    print!("{}", render(SummaryFormat::Text, &rows));
+    let output = args
+        .run_dir
+        .join(format!("block_sim.{}", args.format.ext()));
+    fs::write(&output, render(args.format, &rows))?;
+    println!("wrote {}", output.display());
+    Ok(())
+}
+
+/// the gets in the trace, in the order they were made, under the phase that
+/// was running when they were
+fn gets_by_phase(events: &[Value]) -> BTreeMap<Phase, Vec<Get>> {
+    let mut windows = vec![];
+    for event in events.iter().filter(|e| e["cat"] == "phase") {
+        let (Some(phase), Some(ts)) = (
+            event["name"].as_str().and_then(|n| n.parse::<Phase>().ok()),
+            event["ts"].as_f64(),
+        ) else {
+            continue;
+        };
+        match event["ph"].as_str() {
+            Some("B") => windows.push((phase, ts, f64::INFINITY)),
+            Some("E") => {
+                if let Some((_, _, end)) = windows
+                    .iter_mut()
+                    .rev()
+                    .find(|(p, _, end)| *p == phase && end.is_infinite())
+                {
+                    *end = ts;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut phases = BTreeMap::<Phase, Vec<Get>>::new();
+    for event in events {
+        if event["cat"] != "object_store" || event["ph"] != "b" || event["name"] != "get" {
+            continue;
+        }
+        let args = &event["args"];
+        let (Some(ts), Some(path)) = (event["ts"].as_f64(), args["path"].as_str()) else {
+            continue;
+        };
+        // gets of whole objects have no range
+        let range = match args["range"].as_array().map(|r| r.as_slice()) {
+            Some([start, end]) => start.as_u64().unwrap_or(0)..end.as_u64().unwrap_or(0),
+            _ => 0..args["bytes"].as_u64().unwrap_or(0),
+        };
+        let phase = windows
+            .iter()
+            .find(|(_, start, end)| (*start..=*end).contains(&ts));
+        if let Some((phase, _, _)) = phase {
+            phases.entry(*phase).or_default().push(Get {
+                ts,
+                path: path.to_string(),
+                range,
+            });
+        }
+    }
+    for gets in phases.values_mut() {
+        gets.sort_by(|a, b| a.ts.total_cmp(&b.ts));
+    }
+    phases
+}
+
+/// the bytes at least one get asked for
+fn distinct_bytes(gets: &[Get]) -> u64 {
+    let mut by_path = HashMap::<&str, Vec<Range<u64>>>::new();
+    for get in gets {
+        by_path
+            .entry(get.path.as_str())
+            .or_default()
+            .push(get.range.clone());
+    }
+    let mut distinct = 0;
+    for ranges in by_path.values_mut() {
+        ranges.sort_by_key(|r| r.start);
+        let mut covered = 0;
+        for r in ranges.iter() {
+            let start = r.start.max(covered);
+            if r.end > start {
+                distinct += r.end - start;
+            }
+            covered = covered.max(r.end);
+        }
+    }
+    distinct
+}
+
+/// the requests and bytes `gets` would have taken reading aligned blocks of
+/// `block_size`, plus `readahead` bytes past any get that missed the cache
+fn replay(gets: &[Get], sizes: &HashMap<&str, u64>, block_size: u64, readahead: u64) -> Counts {
+    let mut cached = HashMap::<&str, BTreeSet<u64>>::new();
+    let mut counts = Counts::default();
+    for get in gets {
+        if get.range.is_empty() {
+            continue;
+        }
+        let size = sizes[get.path.as_str()];
+        let blocks = cached.entry(get.path.as_str()).or_default();
+        let first = get.range.start / block_size;
+        let last = get.range.end.div_ceil(block_size);
+        if (first..last).all(|b| blocks.contains(&b)) {
+            continue;
+        }
+        let last = last.max((get.range.end + readahead).min(size).div_ceil(block_size));
+        let mut in_run = false;
+        for block in first..last {
+            if blocks.insert(block) {
+                if !in_run {
+                    counts.requests += 1;
+                }
+                in_run = true;
+                counts.read += ((block + 1) * block_size).min(size) - block * block_size;
+            } else {
+                in_run = false;
+            }
+        }
+    }
+    counts
+}
+
+fn render(format: SummaryFormat, rows: &[(Phase, Option<(u64, u64)>, Counts)]) -> String {
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => out.push_str("phase,block_size,readahead,requests,read,wasted\n"),
+        SummaryFormat::Text => writeln!(
+            out,
+            "{:<14} {:>10} {:>10} {:>10} {:>14} {:>14}",
+            "phase", "block_size", "readahead", "requests", "read", "wasted"
+        )
+        .unwrap(),
+    }
+    for (phase, config, counts) in rows {
+        // the gets as they were made
+        let (block_size, readahead) = match config {
+            Some((block_size, readahead)) => (block_size.to_string(), readahead.to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        match format {
+            SummaryFormat::Csv => writeln!(
+                out,
+                "{},{block_size},{readahead},{},{},{}",
+                phase.as_str(),
+                counts.requests,
+                counts.read,
+                counts.wasted
+            ),
+            SummaryFormat::Text => writeln!(
+                out,
+                "{:<14} {:>10} {:>10} {:>10} {:>14} {:>14}",
+                phase.as_str(),
+                block_size,
+                readahead,
+                counts.requests,
+                counts.read,
+                counts.wasted
+            ),
+        }
+        .unwrap();
+    }
+    out
+}
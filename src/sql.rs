@@ -0,0 +1,104 @@
+//! The sql phase: each `--sql` statement run through lance's datafusion table
+//! provider with the dataset registered as `t`, so the IO of analytic queries
+//! (aggregations, filters, the dataset joined with itself) can be profiled
+//! next to the knn searches. The requests of each statement are labelled
+//! with its index among the statements.
+
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use datafusion::prelude::SessionContext;
+use lance::datafusion::LanceTableProvider;
+use lance::Dataset;
+
+use crate::cli::Args;
+use crate::hooks::Labels;
+use crate::profiling::ProfiledOp;
+use crate::registry::registry;
+use crate::scope;
+
+/// the name the dataset is queried by
+pub const TABLE: &str = "t";
+
+/// one run of a whole statement, from planning to its last batch
+struct Statement;
+
+impl ProfiledOp for Statement {
+    fn name(&self) -> &'static str {
+        "sql"
+    }
+
+    fn value_type(&self) -> (&'static str, &'static str) {
+        ("latency", "nanoseconds")
+    }
+}
+
+/// How the runs of one statement went.
+pub struct StatementRuns {
+    pub sql: String,
+    pub rows: usize,
+    pub elapsed: Vec<Duration>,
+    /// why it failed, it isn't run again once it has
+    pub error: Option<String>,
+}
+
+/// runs every statement `--sql-repeats` times, in order. a statement that
+/// fails is reported and skipped, the rest still run
+pub async fn sql_phase(args: &Args, ds: &Dataset) -> Vec<StatementRuns> {
+    let ctx = SessionContext::new();
+    let table = LanceTableProvider::new(Arc::new(ds.clone()), false, false);
+    ctx.register_table(TABLE, Arc::new(table)).unwrap();
+
+    let mut runs = vec![];
+    for (i, sql) in args.sql.iter().enumerate() {
+        let mut statement = StatementRuns {
+            sql: sql.clone(),
+            rows: 0,
+            elapsed: vec![],
+            error: None,
+        };
+        for _ in 0..args.sql_repeats {
+            let labels = Labels::default().num("statement", i as i64);
+            let started = Instant::now();
+            let batches = match scope::with_labels(
+                labels,
+                scope::in_node("sql", async { ctx.sql(sql).await?.collect().await }),
+            )
+            .await
+            {
+                Ok(batches) => batches,
+                Err(e) => {
+                    log::warn!("sql statement {i} failed, skipping it: {e}");
+                    statement.error = Some(e.to_string());
+                    break;
+                }
+            };
+            let elapsed = started.elapsed();
+            registry().record(&Statement, elapsed.as_nanos() as i64);
+            statement.rows = batches.iter().map(|batch| batch.num_rows()).sum();
+            statement.elapsed.push(elapsed);
+        }
+        runs.push(statement);
+    }
+    runs
+}
+
+/// a line per statement with the rows it returned and how long its runs took
+pub fn render(runs: &[StatementRuns]) -> String {
+    let mut out = String::new();
+    for (i, run) in runs.iter().enumerate() {
+        let ms = run
+            .elapsed
+            .iter()
+            .map(|elapsed| format!("{:.1}", elapsed.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(out, "statement {i}: {}", run.sql).unwrap();
+        match &run.error {
+            Some(e) => writeln!(out, "  failed: {e}").unwrap(),
+            None => writeln!(out, "  rows: {}, ms per run: {ms}", run.rows).unwrap(),
+        }
+    }
+    out
+}
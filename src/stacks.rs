@@ -0,0 +1,91 @@
+//! The call stacks requests are made from, for everything that breaks what
+//! it records down by stack: captured as return addresses while the request
+//! is made, and resolved to symbols only once they're reported.
+
+use std::ffi::c_void;
+
+/// frames kept of each stack
+pub const MAX_FRAMES: usize = 128;
+
+/// frames shown of each stack in a report, after the recording's own
+pub const REPORT_FRAMES: usize = 12;
+
+/// A function at a frame of a stack.
+#[derive(Clone)]
+pub struct Symbol {
+    pub name: String,
+    // only the profiles show where a function is
+    #[cfg_attr(not(feature = "profiling"), allow(dead_code))]
+    pub file: String,
+    #[cfg_attr(not(feature = "profiling"), allow(dead_code))]
+    pub line: i64,
+}
+
+/// the return addresses of the calling thread's stack, innermost first
+pub fn capture() -> Vec<usize> {
+    let mut stack = Vec::with_capacity(MAX_FRAMES);
+    backtrace::trace(|frame| {
+        stack.push(frame.ip() as usize);
+        stack.len() < MAX_FRAMES
+    });
+    stack
+}
+
+/// the functions at `ip`, inlined ones first
+pub fn symbols(ip: usize) -> Vec<Symbol> {
+    let mut symbols = vec![];
+    backtrace::resolve(ip as *mut c_void, |symbol| {
+        symbols.push(Symbol {
+            name: symbol
+                .name()
+                .map(|name| format!("{name:#}"))
+                .unwrap_or_else(|| format!("{ip:#x}")),
+            file: symbol
+                .filename()
+                .map(|file| file.display().to_string())
+                .unwrap_or_default(),
+            line: symbol.lineno().unwrap_or(0) as i64,
+        });
+    });
+    symbols
+}
+
+/// the functions at each frame of `stack`
+pub fn resolve(stack: &[usize]) -> Vec<Vec<Symbol>> {
+    stack.iter().map(|&ip| symbols(ip)).collect()
+}
+
+/// how many of the innermost `frames` are the recording's own, up to the
+/// first frame of whoever made the request. those are backtrace's, the
+/// standard library's, such as the `poll` of a boxed future between a store
+/// and its caller, and those of the modules `own` names, e.g.
+/// `pprof_dev::hooks::`. the same modules further out, like the scope the
+/// workload runs its requests in, stay
+pub fn own_frames(frames: &[Vec<Symbol>], own: &[&str]) -> usize {
+    let is_own = |name: &str| {
+        let krate = name.trim_start_matches('<');
+        ["backtrace::", "core::", "std::", "alloc::"]
+            .iter()
+            .any(|prefix| krate.starts_with(prefix))
+            || name.contains("pprof_dev::stacks::")
+            || own.iter().any(|module| name.contains(module))
+    };
+    frames
+        .iter()
+        .position(|symbols| !symbols.iter().all(|symbol| is_own(&symbol.name)))
+        .unwrap_or(0)
+}
+
+/// the names of the innermost [`REPORT_FRAMES`] functions of `stack` past
+/// the recording's own, see [`own_frames`]
+pub fn report(stack: &[usize], own: &[&str]) -> Vec<String> {
+    let frames = resolve(stack);
+    let skip = own_frames(&frames, own);
+    frames
+        .into_iter()
+        .skip(skip)
+        .flatten()
+        .map(|symbol| symbol.name)
+        .take(REPORT_FRAMES)
+        .collect()
+}
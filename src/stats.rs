@@ -0,0 +1,204 @@
+//! Request counters and latency histograms per op and operator, kept apart
+//! from pprof so there's IO accounting for every phase even when the profiler
+//! couldn't be created or the tool was built without it.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+use object_store::path::Path;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+use crate::scope;
+
+/// significant figures kept by the latency histograms
+const SIGFIG: u8 = 3;
+
+struct OpCounters {
+    count: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    aborted: AtomicU64,
+    latency_us: Mutex<Histogram<u64>>,
+    /// only recorded for streamed gets
+    ttfb_us: Mutex<Histogram<u64>>,
+}
+
+impl OpCounters {
+    fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            aborted: AtomicU64::new(0),
+            latency_us: Mutex::new(Histogram::new(SIGFIG).unwrap()),
+            ttfb_us: Mutex::new(Histogram::new(SIGFIG).unwrap()),
+        }
+    }
+
+    fn snapshot(&self, op: ObjectStoreOp, node: &'static str) -> OpStats {
+        let latency = self.latency_us.lock();
+        let ttfb = self.ttfb_us.lock();
+        OpStats {
+            op,
+            node,
+            count: self.count.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            aborted: self.aborted.load(Ordering::Relaxed),
+            min_us: latency.min(),
+            mean_us: latency.mean().round() as u64,
+            p50_us: latency.value_at_quantile(0.50),
+            p90_us: latency.value_at_quantile(0.90),
+            p99_us: latency.value_at_quantile(0.99),
+            p999_us: latency.value_at_quantile(0.999),
+            max_us: latency.max(),
+            ttfb_p50_us: ttfb.value_at_quantile(0.50),
+            ttfb_p99_us: ttfb.value_at_quantile(0.99),
+        }
+    }
+}
+
+/// Statistics for one op made by one operator.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpStats {
+    pub op: ObjectStoreOp,
+    pub node: &'static str,
+    pub count: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    /// streams dropped before they were read to the end, and aborted uploads
+    pub aborted: u64,
+    pub min_us: u64,
+    pub mean_us: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub max_us: u64,
+    pub ttfb_p50_us: u64,
+    pub ttfb_p99_us: u64,
+}
+
+/// Hooks counting every completed request until [`Self::take`] is called.
+/// Recording only takes a lock for the histogram of the request's own op.
+#[derive(Default)]
+pub struct StatsCollector {
+    ops: RwLock<BTreeMap<(ObjectStoreOp, &'static str), Arc<OpCounters>>>,
+}
+
+impl StatsCollector {
+    /// the statistics of everything recorded since the last call
+    pub fn take(&self) -> Vec<OpStats> {
+        std::mem::take(&mut *self.ops.write())
+            .into_iter()
+            .map(|((op, node), counters)| counters.snapshot(op, node))
+            .collect()
+    }
+
+    fn counters(&self, op: ObjectStoreOp, node: &'static str) -> Arc<OpCounters> {
+        if let Some(counters) = self.ops.read().get(&(op, node)) {
+            return counters.clone();
+        }
+        self.ops
+            .write()
+            .entry((op, node))
+            .or_insert_with(|| Arc::new(OpCounters::new()))
+            .clone()
+    }
+}
+
+impl StoreHooks for StatsCollector {
+    fn on_first_byte(&self, op: ObjectStoreOp, _path: &Path, ttfb: Duration, _labels: &Labels) {
+        self.counters(op, scope::current())
+            .ttfb_us
+            .lock()
+            .saturating_record(ttfb.as_micros() as u64);
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        _path: &Path,
+        _range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        _labels: &Labels,
+    ) {
+        let counters = self.counters(op, scope::current());
+        counters.count.fetch_add(1, Ordering::Relaxed);
+        counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        match outcome {
+            Outcome::Success => {}
+            Outcome::Error(_) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            Outcome::Aborted => {
+                counters.aborted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        counters
+            .latency_us
+            .lock()
+            .saturating_record(duration.as_micros() as u64);
+    }
+}
+
+pub fn render_text(stats: &[OpStats]) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{:<8} {:<12} {:>8} {:>14} {:>7} {:>8} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>11} {:>11}",
+        "op",
+        "node",
+        "count",
+        "bytes",
+        "errors",
+        "aborted",
+        "min_us",
+        "mean_us",
+        "p50_us",
+        "p90_us",
+        "p99_us",
+        "p999_us",
+        "max_us",
+        "ttfb_p50_us",
+        "ttfb_p99_us"
+    )
+    .unwrap();
+    for s in stats {
+        writeln!(
+            out,
+            "{:<8} {:<12} {:>8} {:>14} {:>7} {:>8} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>11} {:>11}",
+            s.op.as_str(),
+            s.node,
+            s.count,
+            s.bytes,
+            s.errors,
+            s.aborted,
+            s.min_us,
+            s.mean_us,
+            s.p50_us,
+            s.p90_us,
+            s.p99_us,
+            s.p999_us,
+            s.max_us,
+            s.ttfb_p50_us,
+            s.ttfb_p99_us
+        )
+        .unwrap();
+    }
+    out
+}
+
+pub fn render_json(stats: &[OpStats]) -> String {
+    serde_json::to_string_pretty(stats).unwrap()
+}
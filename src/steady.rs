@@ -0,0 +1,127 @@
+//! Splitting the query phase where its IO settles down, with `--steady-after`.
+//! The queries before that point, which open the index and read metadata for
+//! the first time, are recorded as the query_startup phase. The rest are the
+//! query phase, so the startup IO doesn't drown out the steady state in its
+//! profiles.
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use lance::Dataset;
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::query_phase;
+use crate::summary::SummaryCollector;
+
+/// how close the requests per query of a window have to be to the window
+/// before's for the queries to count as steady
+const TOLERANCE: f64 = 0.1;
+
+/// Where the startup of the query phase ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SteadyAfter {
+    /// after this many queries
+    Queries(usize),
+    /// once the requests per query of `--steady-window` queries are within
+    /// 10% of those of the window before
+    Auto,
+}
+
+impl FromStr for SteadyAfter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(SteadyAfter::Auto),
+            queries => queries
+                .parse()
+                .ok()
+                .filter(|queries| *queries > 0)
+                .map(SteadyAfter::Queries)
+                .ok_or_else(|| format!("expected `auto` or a number of queries, got {s:?}")),
+        }
+    }
+}
+
+/// The queries run before the steady state.
+pub struct Startup {
+    queries: usize,
+    elapsed: Duration,
+}
+
+/// runs queries until they reach a steady state. `summary` is the run's,
+/// counting the requests made so far in the phase
+pub async fn startup_phase(
+    args: &Args,
+    ds: &Dataset,
+    steady_after: SteadyAfter,
+    summary: &SummaryCollector,
+) -> Startup {
+    let started = Instant::now();
+    let window = match steady_after {
+        SteadyAfter::Queries(queries) => queries,
+        SteadyAfter::Auto => args.steady_window.max(1),
+    };
+    let mut window_args = args.clone();
+    window_args.queries = window;
+    window_args.duration_secs = None;
+
+    let mut queries = 0;
+    let mut previous: Option<f64> = None;
+    loop {
+        let before = summary.requests();
+        let ran = query_phase(&window_args, ds).await;
+        queries += ran;
+        if steady_after != SteadyAfter::Auto {
+            break;
+        }
+
+        let per_query = (summary.requests() - before) as f64 / ran.max(1) as f64;
+        if let Some(previous) = previous {
+            if (per_query - previous).abs() <= TOLERANCE * previous {
+                log::info!(
+                    "steady state after {queries} queries, at {per_query:.1} requests per query"
+                );
+                break;
+            }
+        }
+        previous = Some(per_query);
+
+        // the steady state gets at least half the load
+        let spent = match args.duration_secs {
+            Some(secs) => started.elapsed() >= Duration::from_secs(secs) / 2,
+            None => queries >= args.queries / 2,
+        };
+        if spent {
+            log::warn!(
+                "no steady state within the first {queries} queries, recording the rest as \
+                 steady anyway"
+            );
+            break;
+        }
+    }
+    Startup {
+        queries,
+        elapsed: started.elapsed(),
+    }
+}
+
+/// the query load left over for the steady state after `startup`, at least a
+/// query or a second of it
+pub fn steady_args(args: &Args, startup: &Startup) -> Args {
+    let mut steady = args.clone();
+    steady.queries = args.queries.saturating_sub(startup.queries);
+    if steady.queries == 0 && args.duration_secs.is_none() {
+        log::warn!(
+            "the startup took all {} of --queries, running the steady state for 1 query",
+            args.queries
+        );
+    }
+    steady.queries = steady.queries.max(1);
+    steady.duration_secs = args
+        .duration_secs
+        .map(|secs| secs.saturating_sub(startup.elapsed.as_secs()).max(1));
+    steady
+}
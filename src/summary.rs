@@ -0,0 +1,390 @@
+//! Aggregated per-op request statistics for each phase, and the summary
+//! written at the end of the run.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use object_store::path::Path;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::metadata;
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryFormat {
+    Text,
+    Csv,
+}
+
+// for the PPROF_DEV_FORMAT override
+impl FromStr for SummaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+impl SummaryFormat {
+    pub fn ext(&self) -> &'static str {
+        match self {
+            SummaryFormat::Text => "txt",
+            SummaryFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Statistics for one op over one phase.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpSummary {
+    pub count: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    pub p50_us: u64,
+    pub p99_us: u64,
+}
+
+pub type PhaseSummary = BTreeMap<ObjectStoreOp, OpSummary>;
+
+/// How long one phase took.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    /// when the phase started, in milliseconds since the epoch
+    #[serde(default)]
+    pub started_ms: i64,
+    pub wall_us: u64,
+    /// part of `wall_us` spent generating the data the phase wrote
+    pub data_gen_us: u64,
+    /// rows generated and written by the phase
+    pub rows: u64,
+    /// cut short by its `--phase-timeout`, so the rest are partial
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+impl PhaseTiming {
+    /// rows written per second, not counting the time generating them
+    pub fn rows_per_sec(&self) -> f64 {
+        per_sec(self.rows, self.wall_us.saturating_sub(self.data_gen_us))
+    }
+
+    /// megabytes moved by all of the phase's requests per second
+    pub fn mb_per_sec(&self, summary: &PhaseSummary) -> f64 {
+        let bytes = summary.values().map(|s| s.bytes).sum::<u64>();
+        per_sec(bytes, self.wall_us) / 1e6
+    }
+}
+
+pub fn per_sec(n: u64, us: u64) -> f64 {
+    match us {
+        0 => 0.0,
+        us => n as f64 * 1e6 / us as f64,
+    }
+}
+
+#[derive(Default)]
+struct OpAccumulator {
+    count: u64,
+    bytes: u64,
+    errors: u64,
+    latencies_us: Vec<u64>,
+}
+
+impl OpAccumulator {
+    fn summarize(mut self) -> OpSummary {
+        self.latencies_us.sort_unstable();
+        OpSummary {
+            count: self.count,
+            bytes: self.bytes,
+            errors: self.errors,
+            p50_us: percentile(&self.latencies_us, 0.50),
+            p99_us: percentile(&self.latencies_us, 0.99),
+        }
+    }
+}
+
+fn percentile(sorted: &[u64], q: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let idx = ((sorted.len() - 1) as f64 * q).round() as usize;
+    sorted[idx]
+}
+
+/// Hooks that tally up every completed request until [`Self::take`] is called.
+#[derive(Default)]
+pub struct SummaryCollector {
+    ops: Mutex<BTreeMap<ObjectStoreOp, OpAccumulator>>,
+}
+
+impl SummaryCollector {
+    /// returns the summary of everything recorded since the last call
+    pub fn take(&self) -> PhaseSummary {
+        std::mem::take(&mut *self.ops.lock())
+            .into_iter()
+            .map(|(op, acc)| (op, acc.summarize()))
+            .collect()
+    }
+
+    /// how many requests completed since the last [`Self::take`], leaving
+    /// them to be taken
+    pub fn requests(&self) -> u64 {
+        self.ops.lock().values().map(|acc| acc.count).sum()
+    }
+}
+
+impl StoreHooks for SummaryCollector {
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        _path: &Path,
+        _range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        _labels: &Labels,
+    ) {
+        let mut ops = self.ops.lock();
+        let acc = ops.entry(op).or_default();
+        acc.count += 1;
+        acc.bytes += bytes as u64;
+        acc.latencies_us.push(duration.as_micros() as u64);
+        if let Outcome::Error(_) = outcome {
+            acc.errors += 1;
+        }
+    }
+}
+
+/// The summaries of each phase for each of several datasets, e.g. the tenants
+/// of a shared service or the tiers of a comparison, in the order they were
+/// added.
+#[derive(Default)]
+pub struct DatasetSummaries {
+    collectors: Vec<(String, Arc<SummaryCollector>)>,
+    phases: BTreeMap<Phase, Vec<(String, PhaseSummary)>>,
+}
+
+impl DatasetSummaries {
+    /// the hooks collecting the requests of the dataset `name`
+    pub fn add(&mut self, name: &str) -> Arc<SummaryCollector> {
+        let collector = Arc::new(SummaryCollector::default());
+        self.collectors.push((name.to_string(), collector.clone()));
+        collector
+    }
+
+    /// forgets what was collected since the last phase, as the next starts
+    pub fn start_phase(&self) {
+        for (_, collector) in &self.collectors {
+            collector.take();
+        }
+    }
+
+    pub fn finish_phase(&mut self, phase: Phase) {
+        let summaries = self
+            .collectors
+            .iter()
+            .map(|(name, collector)| (name.clone(), collector.take()))
+            .collect();
+        self.phases.insert(phase, summaries);
+    }
+
+    pub fn phases(&self) -> &BTreeMap<Phase, Vec<(String, PhaseSummary)>> {
+        &self.phases
+    }
+}
+
+/// every row of the csv carries what produced it, so summaries from many
+/// machines can be concatenated and still be told apart
+pub fn render(format: SummaryFormat, phases: &BTreeMap<Phase, PhaseSummary>) -> String {
+    let build_info = metadata::build_info();
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => {
+            out.push_str("op,phase,count,bytes,p50_us,p99_us,errors");
+            for (key, _) in &build_info {
+                write!(out, ",{key}").unwrap();
+            }
+            out.push('\n');
+            let build_values = build_info
+                .iter()
+                .map(|(_, value)| *value)
+                .collect::<Vec<_>>()
+                .join(",");
+            for (phase, ops) in phases {
+                for (op, s) in ops {
+                    writeln!(
+                        out,
+                        "{op},{},{},{},{},{},{},{build_values}",
+                        phase.as_str(),
+                        s.count,
+                        s.bytes,
+                        s.p50_us,
+                        s.p99_us,
+                        s.errors
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        SummaryFormat::Text => {
+            writeln!(
+                out,
+                "{:<8} {:<14} {:>8} {:>14} {:>10} {:>10} {:>7}",
+                "op", "phase", "count", "bytes", "p50_us", "p99_us", "errors"
+            )
+            .unwrap();
+            for (phase, ops) in phases {
+                for (op, s) in ops {
+                    writeln!(
+                        out,
+                        "{:<8} {:<14} {:>8} {:>14} {:>10} {:>10} {:>7}",
+                        op.as_str(),
+                        phase.as_str(),
+                        s.count,
+                        s.bytes,
+                        s.p50_us,
+                        s.p99_us,
+                        s.errors
+                    )
+                    .unwrap();
+                }
+            }
+            writeln!(out, "\n{}", metadata::describe()).unwrap();
+        }
+    }
+    out
+}
+
+/// wall time and throughput of each phase, alongside its io
+pub fn render_phases(
+    format: SummaryFormat,
+    phases: &BTreeMap<Phase, PhaseSummary>,
+    timings: &BTreeMap<Phase, PhaseTiming>,
+) -> String {
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => out.push_str(
+            "phase,wall_ms,data_gen_ms,rows,rows_per_s,requests,bytes,mb_per_s,timed_out\n",
+        ),
+        SummaryFormat::Text => writeln!(
+            out,
+            "{:<14} {:>10} {:>11} {:>10} {:>10} {:>8} {:>14} {:>9} {}",
+            "phase",
+            "wall_ms",
+            "data_gen_ms",
+            "rows",
+            "rows_per_s",
+            "requests",
+            "bytes",
+            "mb_per_s",
+            "timed_out"
+        )
+        .unwrap(),
+    }
+    let empty = PhaseSummary::new();
+    for (phase, t) in timings {
+        let summary = phases.get(phase).unwrap_or(&empty);
+        let requests = summary.values().map(|s| s.count).sum::<u64>();
+        let bytes = summary.values().map(|s| s.bytes).sum::<u64>();
+        match format {
+            SummaryFormat::Csv => writeln!(
+                out,
+                "{},{},{},{},{:.0},{requests},{bytes},{:.2},{}",
+                phase.as_str(),
+                t.wall_us / 1000,
+                t.data_gen_us / 1000,
+                t.rows,
+                t.rows_per_sec(),
+                t.mb_per_sec(summary),
+                t.timed_out
+            ),
+            SummaryFormat::Text => writeln!(
+                out,
+                "{:<14} {:>10} {:>11} {:>10} {:>10.0} {:>8} {:>14} {:>9.2} {}",
+                phase.as_str(),
+                t.wall_us / 1000,
+                t.data_gen_us / 1000,
+                t.rows,
+                t.rows_per_sec(),
+                requests,
+                bytes,
+                t.mb_per_sec(summary),
+                if t.timed_out { "yes" } else { "" }
+            ),
+        }
+        .unwrap();
+    }
+    out
+}
+
+/// per-op comparison of two phases that ran the same workload, e.g. cold and
+/// warm queries
+pub fn render_delta(
+    format: SummaryFormat,
+    (base_phase, base): (Phase, &PhaseSummary),
+    (phase, summary): (Phase, &PhaseSummary),
+) -> String {
+    let (b, p) = (base_phase.as_str(), phase.as_str());
+    let empty = OpSummary::default();
+    let ops = base.keys().chain(summary.keys()).collect::<BTreeSet<_>>();
+
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => {
+            writeln!(
+                out,
+                "op,{b}_count,{p}_count,count_delta,{b}_bytes,{p}_bytes,bytes_delta"
+            )
+            .unwrap();
+        }
+        SummaryFormat::Text => {
+            writeln!(
+                out,
+                "{:<8} {:>12} {:>12} {:>12} {:>14} {:>14} {:>14}",
+                "op",
+                format!("{b}_count"),
+                format!("{p}_count"),
+                "count_delta",
+                format!("{b}_bytes"),
+                format!("{p}_bytes"),
+                "bytes_delta"
+            )
+            .unwrap();
+        }
+    }
+    for op in ops {
+        let before = base.get(op).unwrap_or(&empty);
+        let after = summary.get(op).unwrap_or(&empty);
+        let count_delta = after.count as i64 - before.count as i64;
+        let bytes_delta = after.bytes as i64 - before.bytes as i64;
+        match format {
+            SummaryFormat::Csv => writeln!(
+                out,
+                "{op},{},{},{count_delta},{},{},{bytes_delta}",
+                before.count, after.count, before.bytes, after.bytes
+            ),
+            SummaryFormat::Text => writeln!(
+                out,
+                "{:<8} {:>12} {:>12} {:>12} {:>14} {:>14} {:>14}",
+                op.as_str(),
+                before.count,
+                after.count,
+                count_delta,
+                before.bytes,
+                after.bytes,
+                bytes_delta
+            ),
+        }
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,1134 @@
+//! Reruns the index and query phases across a list of ivf partition counts,
+//! dataset sizes, index types, tokio runtimes or local readers, the queries
+//! across result sizes and concurrency, the queries and scans across lance's
+//! io parallelism or deleted rows, or the scans across projections of
+//! more and more columns, scanner settings or filter selectivities, one run
+//! directory per configuration, then compares them.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int64Type;
+use arrow_array::Array;
+use clap::ValueEnum;
+use lance::Dataset;
+use object_store::path::Path;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tokio::runtime::{Builder, Runtime};
+use tracing::Instrument;
+
+use crate::cli::{
+    Args, CompareArgs, DeletesArgs, DiffArgs, KnnArgs, MmapArgs, ParallelismArgs, ProjectionsArgs,
+    RuntimesArgs, ScaleArgs, ScanTuningArgs, SelectivityArgs, SweepArgs, ENV_PREFIX,
+};
+use crate::data;
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+use crate::otel;
+use crate::registry::registry;
+use crate::report;
+use crate::run::{self, Phase, RecordedPhases, RunDir};
+use crate::summary::{OpSummary, PhaseSummary, PhaseTiming, SummaryFormat};
+use crate::table::{Cell, Table};
+use crate::{
+    append_range, index_phase, knn_scanner, open_dataset, profiling_wrapper, query_phase,
+    scan_phase, warmup_args, write_phase, Run,
+};
+
+/// seeds the queries recall is measured over, so every index type gets the
+/// same ones
+const RECALL_SEED: u64 = 0x5eed;
+
+/// the delete sweep deletes rows by their id modulo this, so its ratios are
+/// rounded to 0.01%
+const DELETE_MODULUS: u64 = 10_000;
+
+/// What a setting of a sweep runs, each but `Warmup` as the phase of the same
+/// name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// writes the dataset before any other step. a setting without it opens
+    /// the dataset an earlier one wrote
+    Write,
+    /// appends the rows from this one up to the setting's `--rows`
+    Append(u64),
+    Index,
+    /// the queries of `--warmup`, which aren't measured
+    Warmup,
+    Query,
+    Scan,
+}
+
+/// Runs the settings of a sweep, each a run of the workload in a directory of
+/// its own, `{sweep id}-{label}`. Every setting searches for the same vectors,
+/// from `--query-seed` if it's set.
+pub struct Runner {
+    /// what the sweep's table is named after
+    kind: &'static str,
+    args: Args,
+    id: String,
+    /// how many settings have been started
+    started: usize,
+    _otel: otel::Exporter,
+}
+
+impl Runner {
+    pub fn new(kind: &'static str, args: &Args) -> Self {
+        let mut args = args.clone();
+        args.query_seed = Some(args.query_seed.unwrap_or_else(rand::random));
+        Self {
+            kind,
+            _otel: otel::Exporter::start(&args),
+            id: sweep_id(&args),
+            args,
+            started: 0,
+        }
+    }
+
+    /// the workload of the setting `label`, the sweep's with `configure`
+    /// applied
+    pub fn args(&self, label: &str, configure: impl FnOnce(&mut Args)) -> Args {
+        let mut args = self.args.clone();
+        configure(&mut args);
+        args.run_id = Some(format!("{}-{label}", self.id));
+        args
+    }
+
+    /// the workload of the next setting and what it runs: `setup` if it's the
+    /// first, then `steps`
+    pub fn next(
+        &mut self,
+        label: &str,
+        configure: impl FnOnce(&mut Args),
+        setup: &[Step],
+        steps: &[Step],
+    ) -> (Args, Vec<Step>) {
+        let setup = if self.started == 0 { setup } else { &[] };
+        self.started += 1;
+        let steps = setup.iter().chain(steps).copied().collect();
+        (self.args(label, configure), steps)
+    }
+
+    /// starts the next setting, see [`Runner::next`]
+    pub async fn start(
+        &mut self,
+        label: &str,
+        configure: impl FnOnce(&mut Args),
+        setup: &[Step],
+        steps: &[Step],
+    ) -> Setting {
+        let (args, steps) = self.next(label, configure, setup, steps);
+        Setting::start(args, label.to_string(), vec![], &steps).await
+    }
+
+    /// runs the next setting, see [`Runner::next`]
+    pub async fn run(
+        &mut self,
+        label: &str,
+        configure: impl FnOnce(&mut Args),
+        setup: &[Step],
+        steps: &[Step],
+    ) -> Measured {
+        self.start(label, configure, setup, steps).await.finish()
+    }
+
+    /// prints `table` and writes it to `{kind}-{sweep id}`
+    pub fn write_table(&self, table: &Table) {
+        print!("{}", table.render(SummaryFormat::Text));
+        let path = self.args.output_dir.join(format!(
+            "{}-{}.{}",
+            self.kind,
+            self.id,
+            self.args.format.ext()
+        ));
+        std::fs::write(&path, table.render(self.args.format)).unwrap();
+        println!("wrote {}", path.display());
+    }
+}
+
+/// A setting of a sweep that's running.
+pub struct Setting {
+    name: String,
+    args: Args,
+    run: Run,
+    ds: Dataset,
+    /// the searches its query phase made
+    queries: usize,
+}
+
+impl Setting {
+    /// runs `steps` of the workload `args` as the setting `name`, with
+    /// `hooks` on top of the run's
+    pub async fn start(
+        args: Args,
+        name: String,
+        hooks: Vec<Arc<dyn StoreHooks>>,
+        steps: &[Step],
+    ) -> Self {
+        let mut run = Run::new(&args);
+        let uri = run.dir.dataset_uri().to_string();
+        let wrapper = hooks
+            .into_iter()
+            .fold(profiling_wrapper(&args, &run), |wrapper, hooks| {
+                wrapper.with_hooks(hooks)
+            });
+        let wrapper = registry().register("dataset", wrapper);
+
+        let ds = if steps.contains(&Step::Write) {
+            run.start_phase(Phase::Write);
+            let ds = write_phase(&args, &uri, wrapper)
+                .instrument(Phase::Write.span())
+                .await;
+            run.finish_phase(Phase::Write).await;
+            ds
+        } else {
+            open_dataset(&args, &uri, wrapper).await
+        };
+        let mut setting = Self {
+            name,
+            args,
+            run,
+            ds,
+            queries: 0,
+        };
+        for &step in steps {
+            if step != Step::Write {
+                setting.step(step).await;
+            }
+        }
+        setting
+    }
+
+    pub async fn step(&mut self, step: Step) {
+        let (args, ds, run, queries) = (&self.args, &mut self.ds, &mut self.run, &mut self.queries);
+        let phase = match step {
+            Step::Write => unreachable!("the dataset is only written as it's opened"),
+            Step::Warmup => {
+                if let Some(warmup) = warmup_args(args) {
+                    query_phase(&warmup, ds).await;
+                }
+                return;
+            }
+            Step::Append(_) => Phase::Write,
+            Step::Index => Phase::Index,
+            Step::Query => Phase::Query,
+            Step::Scan => Phase::Scan,
+        };
+        run.start_phase(phase);
+        async {
+            match step {
+                Step::Append(from) => append_range(args, ds, from..args.rows as u64).await,
+                Step::Index => index_phase(args, ds).await,
+                Step::Query => *queries = query_phase(args, ds).await,
+                Step::Scan => scan_phase(args, ds).await,
+                Step::Write | Step::Warmup => unreachable!(),
+            }
+        }
+        .instrument(phase.span())
+        .await;
+        run.finish_phase(phase).await;
+    }
+
+    /// what the setting measured, once it's done
+    pub fn finish(self) -> Measured {
+        self.run.finish();
+        let dir = &self.run.dir;
+        let mut profiles = BTreeMap::new();
+        for &phase in dir.summaries().keys() {
+            for op in ObjectStoreOp::PROFILED {
+                let path = dir
+                    .output_path(phase, &format!("dataset_{op}"), "pb")
+                    .unwrap();
+                if path.exists() {
+                    profiles.insert((phase, op), path);
+                }
+            }
+        }
+        Measured {
+            name: self.name,
+            summaries: dir.summaries().clone(),
+            timings: dir.timings().clone(),
+            queries: self.queries,
+            profiles,
+            args: self.args,
+        }
+    }
+}
+
+/// What a setting of a sweep measured.
+pub struct Measured {
+    pub name: String,
+    pub args: Args,
+    pub summaries: BTreeMap<Phase, PhaseSummary>,
+    pub timings: BTreeMap<Phase, PhaseTiming>,
+    /// the searches its query phase made
+    pub queries: usize,
+    /// the profiles each phase wrote
+    pub profiles: BTreeMap<(Phase, ObjectStoreOp), PathBuf>,
+}
+
+impl Measured {
+    /// what the setting `name` of `args`, run by another process, recorded in
+    /// its run directory. its profiles aren't looked for
+    fn read(name: String, args: Args) -> Self {
+        let RecordedPhases {
+            summaries, timings, ..
+        } = RunDir::read_phases(&RunDir::path_for(&args)).unwrap();
+        Self {
+            name,
+            args,
+            summaries,
+            timings,
+            queries: 0,
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    /// `stat` of the phase's requests of `op`, 0 if it made none
+    pub fn stat(&self, phase: Phase, op: ObjectStoreOp, stat: impl Fn(&OpSummary) -> u64) -> u64 {
+        self.summaries
+            .get(&phase)
+            .and_then(|summary| summary.get(&op))
+            .map_or(0, stat)
+    }
+
+    pub fn wall_us(&self, phase: Phase) -> u64 {
+        self.timings.get(&phase).map_or(0, |timing| timing.wall_us)
+    }
+
+    pub fn wall_ms(&self, phase: Phase) -> u64 {
+        self.wall_us(phase) / 1000
+    }
+
+    pub fn mb_per_sec(&self, phase: Phase) -> f64 {
+        match (self.timings.get(&phase), self.summaries.get(&phase)) {
+            (Some(timing), Some(summary)) => timing.mb_per_sec(summary),
+            _ => 0.0,
+        }
+    }
+
+    /// the phase's gets, their bytes and p99 latency, and its wall time
+    fn get_cells(&self, phase: Phase) -> [Cell; 4] {
+        [
+            self.stat(phase, ObjectStoreOp::Get, |s| s.count).into(),
+            self.stat(phase, ObjectStoreOp::Get, |s| s.bytes).into(),
+            self.stat(phase, ObjectStoreOp::Get, |s| s.p99_us).into(),
+            self.wall_ms(phase).into(),
+        ]
+    }
+}
+
+/// Hooks counting the gets of deletion files, which the summaries don't tell
+/// apart from the rest.
+#[derive(Default)]
+struct DeletionFileGets {
+    gets: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl DeletionFileGets {
+    /// the gets and bytes counted since the last take
+    fn take(&self) -> (u64, u64) {
+        (
+            self.gets.swap(0, Ordering::Relaxed),
+            self.bytes.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+impl StoreHooks for DeletionFileGets {
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        _range: Option<Range<usize>>,
+        bytes: usize,
+        _duration: Duration,
+        _outcome: Outcome<'_>,
+        _labels: &Labels,
+    ) {
+        if op == ObjectStoreOp::Get && path.parts().any(|part| part.as_ref() == "_deletions") {
+            self.gets.fetch_add(1, Ordering::Relaxed);
+            self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A tokio runtime to run the workload on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeFlavor {
+    CurrentThread,
+    /// with this many worker threads
+    MultiThread(usize),
+}
+
+impl RuntimeFlavor {
+    fn build(&self) -> Runtime {
+        let mut builder = match self {
+            RuntimeFlavor::CurrentThread => Builder::new_current_thread(),
+            RuntimeFlavor::MultiThread(workers) => {
+                let mut builder = Builder::new_multi_thread();
+                builder.worker_threads(*workers);
+                builder
+            }
+        };
+        builder.enable_all().build().unwrap()
+    }
+}
+
+impl FromStr for RuntimeFlavor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "current" => Ok(RuntimeFlavor::CurrentThread),
+            workers => workers
+                .parse()
+                .ok()
+                .filter(|workers| *workers > 0)
+                .map(RuntimeFlavor::MultiThread)
+                .ok_or_else(|| format!("expected `current` or a worker count, got {s:?}")),
+        }
+    }
+}
+
+impl Display for RuntimeFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeFlavor::CurrentThread => f.write_str("current"),
+            RuntimeFlavor::MultiThread(workers) => write!(f, "multi-{workers}"),
+        }
+    }
+}
+
+pub fn sweep_id(args: &Args) -> String {
+    args.run_id
+        .clone()
+        .unwrap_or_else(|| run::default_run_id(&run::timestamp()))
+}
+
+pub async fn run_sweep(sweep: SweepArgs) {
+    let mut runner = Runner::new("sweep", &sweep.run);
+    let mut table = Table::new(&[
+        "partitions",
+        "index_puts",
+        "index_put_bytes",
+        "query_gets",
+        "query_get_bytes",
+        "query_get_p99_us",
+    ]);
+    for &partitions in &sweep.partitions {
+        log::info!("sweep: building index with {partitions} partitions");
+        let m = runner
+            .run(
+                &format!("p{partitions}"),
+                |args| args.num_partitions = partitions,
+                &[Step::Write],
+                &[Step::Index, Step::Query],
+            )
+            .await;
+        table.row(vec![
+            partitions.into(),
+            m.stat(Phase::Index, ObjectStoreOp::Put, |s| s.count).into(),
+            m.stat(Phase::Index, ObjectStoreOp::Put, |s| s.bytes).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.count).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.bytes).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.p99_us)
+                .into(),
+        ]);
+    }
+    runner.write_table(&table);
+}
+
+/// grows the dataset through each of `scale.steps` rows. the first step
+/// writes it and every later one appends to it, as that step's write phase,
+/// with the growth of the query gets and bytes relative to the first step next
+/// to the rows' growth
+pub async fn run_scale(scale: ScaleArgs) {
+    assert!(
+        scale.steps.windows(2).all(|w| w[0] < w[1]),
+        "--steps must be increasing"
+    );
+    let mut runner = Runner::new("scale", &scale.run);
+    let mut results = Vec::new();
+    let mut previous = None;
+    for &rows in &scale.steps {
+        log::info!("scale: growing the dataset to {rows} rows");
+        let append = previous.map(|previous: i32| Step::Append(previous as u64));
+        let steps: Vec<_> = append
+            .into_iter()
+            .chain([Step::Index, Step::Query])
+            .collect();
+        let m = runner
+            .run(
+                &format!("r{rows}"),
+                |args| args.rows = rows,
+                &[Step::Write],
+                &steps,
+            )
+            .await;
+        previous = Some(rows);
+        results.push(m);
+    }
+
+    let mut table = Table::new(&[
+        "rows",
+        "write_puts",
+        "write_put_bytes",
+        "index_puts",
+        "index_put_bytes",
+        "query_gets",
+        "query_get_bytes",
+        "query_get_p99_us",
+        "rows_growth",
+        "query_gets_growth",
+        "query_get_bytes_growth",
+    ]);
+    let growth = |value: u64, first: u64| match first {
+        0 => Cell::Float(0.0, 2),
+        first => Cell::Float(value as f64 / first as f64, 2),
+    };
+    if let Some(first) = results.first() {
+        for m in &results {
+            let query =
+                |stat: fn(&OpSummary) -> u64| m.stat(Phase::Query, ObjectStoreOp::Get, stat);
+            let first_query =
+                |stat: fn(&OpSummary) -> u64| first.stat(Phase::Query, ObjectStoreOp::Get, stat);
+            table.row(vec![
+                Cell::from(m.args.rows as u64),
+                m.stat(Phase::Write, ObjectStoreOp::Put, |s| s.count).into(),
+                m.stat(Phase::Write, ObjectStoreOp::Put, |s| s.bytes).into(),
+                m.stat(Phase::Index, ObjectStoreOp::Put, |s| s.count).into(),
+                m.stat(Phase::Index, ObjectStoreOp::Put, |s| s.bytes).into(),
+                query(|s| s.count).into(),
+                query(|s| s.bytes).into(),
+                query(|s| s.p99_us).into(),
+                growth(m.args.rows as u64, first.args.rows as u64),
+                growth(query(|s| s.count), first_query(|s| s.count)),
+                growth(query(|s| s.bytes), first_query(|s| s.bytes)),
+            ]);
+        }
+    }
+    runner.write_table(&table);
+}
+
+/// builds each of `compare.index_types` on the same data and runs the same
+/// queries against it
+pub async fn run_compare(compare: CompareArgs) {
+    let mut runner = Runner::new("compare", &compare.run);
+    let mut table = Table::new(&[
+        "index_type",
+        "index_puts",
+        "index_put_bytes",
+        "query_gets",
+        "query_get_bytes",
+        "query_get_p50_us",
+        "query_get_p99_us",
+        "recall",
+    ]);
+    for &index_type in &compare.index_types {
+        let name = index_type
+            .to_possible_value()
+            .unwrap()
+            .get_name()
+            .to_string();
+        log::info!("compare: building a {name} index");
+        let setting = runner
+            .start(
+                &name,
+                |args| args.index_type = index_type,
+                &[Step::Write],
+                &[Step::Index, Step::Query],
+            )
+            .await;
+        let recall = recall(&setting.args, &setting.ds, compare.recall_queries).await;
+        let m = setting.finish();
+        table.row(vec![
+            name.into(),
+            m.stat(Phase::Index, ObjectStoreOp::Put, |s| s.count).into(),
+            m.stat(Phase::Index, ObjectStoreOp::Put, |s| s.bytes).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.count).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.bytes).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.p50_us)
+                .into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.p99_us)
+                .into(),
+            Cell::Float(recall, 3),
+        ]);
+    }
+    runner.write_table(&table);
+}
+
+/// runs the workload on each of `runtimes.runtimes`, then diffs each one's
+/// profiles against the first's
+pub async fn run_runtimes(runtimes: RuntimesArgs) {
+    let mut runner = Runner::new("runtimes", &runtimes.run);
+    let mut results = Vec::new();
+    for &runtime in &runtimes.runtimes {
+        log::info!("runtimes: running the workload on a {runtime} runtime");
+        let name = runtime.to_string();
+        let (args, steps) = runner.next(&name, |_| {}, &[Step::Write], &[Step::Index, Step::Query]);
+
+        // lance spawns its IO onto whichever runtime is current, so the
+        // workload gets a thread of its own with nothing but its runtime on it.
+        // this one keeps running the exporter in the meantime
+        let thread = std::thread::spawn(move || {
+            runtime
+                .build()
+                .block_on(async move { Setting::start(args, name, vec![], &steps).await.finish() })
+        });
+        let m = tokio::task::spawn_blocking(move || thread.join().unwrap())
+            .await
+            .unwrap();
+        results.push(m);
+    }
+    write_compared(&runner, "runtime", &results);
+}
+
+/// runs the workload reading the dataset through `LocalFileSystem`, then
+/// through memory mapped files, and diffs the mapped run's profiles against
+/// the other's
+pub async fn run_mmap(mmap: MmapArgs) {
+    let mut runner = Runner::new("readers", &mmap.run);
+    let uri = object_store_uri(&mmap.run.uri);
+    let mut results = Vec::new();
+    for (name, mapped) in [("object_store", false), ("mmap", true)] {
+        log::info!("mmap: reading the dataset through {name}");
+        let configure = |args: &mut Args| {
+            args.uri = uri.clone();
+            args.mmap = mapped;
+        };
+        let steps = [Step::Index, Step::Query];
+        results.push(runner.run(name, configure, &[Step::Write], &steps).await);
+    }
+    write_compared(&runner, "reader", &results);
+}
+
+/// the local dataset at `uri` as a `file-object-store://` uri, which lance
+/// reads through an object store like any other instead of its own reader
+fn object_store_uri(uri: &str) -> String {
+    if uri.starts_with("file-object-store://") {
+        return uri.to_string();
+    }
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    assert!(!path.contains("://"), "{uri} isn't a local dataset");
+    let path = match path.strip_prefix("~/") {
+        Some(path) => PathBuf::from(std::env::var("HOME").unwrap()).join(path),
+        None => PathBuf::from(path),
+    };
+    format!(
+        "file-object-store://{}",
+        std::path::absolute(path).unwrap().display()
+    )
+}
+
+/// writes the table of `results`, with the settings in the column `kind`,
+/// then diffs the profiles of each setting after the first against the
+/// first's
+fn write_compared(runner: &Runner, kind: &str, results: &[Measured]) {
+    let mut table = Table::new(&[
+        kind,
+        "index_puts",
+        "index_wall_ms",
+        "query_gets",
+        "query_get_bytes",
+        "query_get_p50_us",
+        "query_get_p99_us",
+        "query_wall_ms",
+    ]);
+    for m in results {
+        table.row(vec![
+            m.name.as_str().into(),
+            m.stat(Phase::Index, ObjectStoreOp::Put, |s| s.count).into(),
+            m.wall_ms(Phase::Index).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.count).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.bytes).into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.p50_us)
+                .into(),
+            m.stat(Phase::Query, ObjectStoreOp::Get, |s| s.p99_us)
+                .into(),
+            m.wall_ms(Phase::Query).into(),
+        ]);
+    }
+    runner.write_table(&table);
+    diff_profiles(results);
+}
+
+/// diffs the profiles of each of `results` after the first against the
+/// first's of the same phase and op
+fn diff_profiles(results: &[Measured]) {
+    let Some((first, rest)) = results.split_first() else {
+        return;
+    };
+    for result in rest {
+        for (key, after) in &result.profiles {
+            let Some(before) = first.profiles.get(key) else {
+                continue;
+            };
+            let diff = DiffArgs {
+                before: before.clone(),
+                after: after.clone(),
+                output: None,
+            };
+            if let Err(e) = report::write_diff(&diff) {
+                log::warn!("not diffing the profiles: {e}");
+                return;
+            }
+        }
+    }
+}
+
+/// runs the query and scan phases with each of `parallelism.io_threads`, each
+/// setting in a process of its own. the first setting also writes the data
+/// and builds the index
+pub async fn run_parallelism(parallelism: ParallelismArgs) {
+    if let Some(i) = parallelism.setting {
+        run_parallelism_setting(&parallelism, i).await;
+        return;
+    }
+    let runner = Runner::new("parallelism", &parallelism.run);
+    let mut table = Table::new(&[
+        "io_threads",
+        "query_gets",
+        "query_get_p99_us",
+        "query_wall_ms",
+        "query_mb_per_sec",
+        "scan_gets",
+        "scan_get_p99_us",
+        "scan_wall_ms",
+        "scan_mb_per_sec",
+    ]);
+    for (i, &io_threads) in parallelism.io_threads.iter().enumerate() {
+        log::info!("parallelism: running the queries with {io_threads} io threads");
+        let name = format!("t{io_threads}");
+        let args = runner.args(&name, |_| {});
+        // lance has no parameter for it, only reading it from the environment
+        // whenever it schedules reads on a store, which can't be changed
+        // safely in a process already running threads. the rest of the
+        // setting's config goes through the environment too since it wins
+        // over the flags it's rerun with
+        let status = tokio::process::Command::new(std::env::current_exe().unwrap())
+            .args(std::env::args_os().skip(1))
+            .args(["--setting", &i.to_string()])
+            .env("LANCE_IO_THREADS", io_threads.to_string())
+            .env(format!("{ENV_PREFIX}RUN_ID"), args.run_id.as_ref().unwrap())
+            .env(
+                format!("{ENV_PREFIX}QUERY_SEED"),
+                args.query_seed.unwrap().to_string(),
+            )
+            .status()
+            .await
+            .unwrap();
+        assert!(
+            status.success(),
+            "parallelism: the run with {io_threads} io threads exited with {status}"
+        );
+
+        let m = Measured::read(name, args);
+        let get = |phase, stat: fn(&OpSummary) -> u64| m.stat(phase, ObjectStoreOp::Get, stat);
+        table.row(vec![
+            io_threads.into(),
+            get(Phase::Query, |s| s.count).into(),
+            get(Phase::Query, |s| s.p99_us).into(),
+            m.wall_ms(Phase::Query).into(),
+            Cell::Float(m.mb_per_sec(Phase::Query), 2),
+            get(Phase::Scan, |s| s.count).into(),
+            get(Phase::Scan, |s| s.p99_us).into(),
+            m.wall_ms(Phase::Scan).into(),
+            Cell::Float(m.mb_per_sec(Phase::Scan), 2),
+        ]);
+    }
+    runner.write_table(&table);
+}
+
+/// the `i`th setting of a parallelism sweep, in the process `run_parallelism`
+/// started for it
+async fn run_parallelism_setting(parallelism: &ParallelismArgs, i: usize) {
+    let args = parallelism.run.clone();
+    let _otel = otel::Exporter::start(&args);
+    let name = format!("t{}", parallelism.io_threads[i]);
+    let steps: &[Step] = match i {
+        0 => &[Step::Write, Step::Index, Step::Query, Step::Scan],
+        _ => &[Step::Query, Step::Scan],
+    };
+    Setting::start(args, name, vec![], steps).await.finish();
+}
+
+/// runs the query phase with each of `knn.ks` for each of
+/// `knn.concurrencies`, with the gets and bytes per search next to the
+/// totals. the first setting also writes the data and builds the index
+pub async fn run_knn(knn: KnnArgs) {
+    let mut runner = Runner::new("knn", &knn.run);
+    let concurrencies = match knn.concurrencies.as_slice() {
+        [] => vec![knn.run.concurrency],
+        concurrencies => concurrencies.to_vec(),
+    };
+    let mut table = Table::new(&[
+        "k",
+        "concurrency",
+        "queries",
+        "query_gets",
+        "query_get_bytes",
+        "query_get_p50_us",
+        "query_get_p99_us",
+        "query_wall_ms",
+        "gets_per_query",
+        "bytes_per_query",
+    ]);
+    for &concurrency in &concurrencies {
+        for &k in &knn.ks {
+            log::info!("knn: searching for {k} results, {concurrency} searches at a time");
+            let configure = |args: &mut Args| {
+                args.k = k;
+                args.concurrency = concurrency;
+            };
+            let m = runner
+                .run(
+                    &format!("k{k}-c{concurrency}"),
+                    configure,
+                    &[Step::Write, Step::Index],
+                    &[Step::Query],
+                )
+                .await;
+            let query =
+                |stat: fn(&OpSummary) -> u64| m.stat(Phase::Query, ObjectStoreOp::Get, stat);
+            let per_query = |value: u64| value as f64 / m.queries.max(1) as f64;
+            table.row(vec![
+                k.into(),
+                concurrency.into(),
+                m.queries.into(),
+                query(|s| s.count).into(),
+                query(|s| s.bytes).into(),
+                query(|s| s.p50_us).into(),
+                query(|s| s.p99_us).into(),
+                m.wall_ms(Phase::Query).into(),
+                Cell::Float(per_query(query(|s| s.count)), 2),
+                Cell::Float(per_query(query(|s| s.bytes)), 0),
+            ]);
+        }
+    }
+    runner.write_table(&table);
+}
+
+/// deletes each of `deletes.delete_ratios` of the rows in turn, then runs the
+/// query and scan phases, a row of the table per phase of each setting. the
+/// first setting also writes the data and builds the index before deleting
+/// anything
+pub async fn run_deletes(deletes: DeletesArgs) {
+    assert!(
+        deletes
+            .delete_ratios
+            .iter()
+            .all(|&r| (0.0..1.0).contains(&r)),
+        "--delete-ratios must be fractions of the rows below 1"
+    );
+    let mut runner = Runner::new("deletes", &deletes.run);
+    // deletes only add up, so each setting has to delete more than the last
+    let mut deleted: Vec<u64> = deletes
+        .delete_ratios
+        .iter()
+        .map(|&r| (r * DELETE_MODULUS as f64).round() as u64)
+        .collect();
+    deleted.sort_unstable();
+    deleted.dedup();
+
+    let mut table = Table::new(&[
+        "deleted",
+        "rows_left",
+        "phase",
+        "gets",
+        "get_bytes",
+        "get_p99_us",
+        "wall_ms",
+        "deletion_gets",
+        "deletion_bytes",
+    ]);
+    for &deleted in &deleted {
+        let ratio = deleted as f64 / DELETE_MODULUS as f64;
+        log::info!(
+            "deletes: querying with {:.2}% of the rows deleted",
+            ratio * 100.0
+        );
+        let name = format!("d{deleted}");
+        let (args, setup) = runner.next(&name, |_| {}, &[Step::Write, Step::Index], &[]);
+        let deletion_gets = Arc::new(DeletionFileGets::default());
+        let mut setting = Setting::start(args, name, vec![deletion_gets.clone()], &setup).await;
+        // like the appends before index maintenance, the deletes themselves
+        // aren't measured
+        setting
+            .ds
+            .delete(&format!("id % {DELETE_MODULUS} < {deleted}"))
+            .await
+            .unwrap();
+        let rows_left = setting.ds.count_rows(None).await.unwrap();
+        deletion_gets.take();
+
+        let mut deletions = vec![];
+        for (step, phase) in [(Step::Query, Phase::Query), (Step::Scan, Phase::Scan)] {
+            setting.step(step).await;
+            deletions.push((phase, deletion_gets.take()));
+        }
+        let m = setting.finish();
+        for (phase, (gets, bytes)) in deletions {
+            let [phase_gets, get_bytes, get_p99_us, wall_ms] = m.get_cells(phase);
+            table.row(vec![
+                Cell::Percent(ratio, 2),
+                rows_left.into(),
+                phase.as_str().into(),
+                phase_gets,
+                get_bytes,
+                get_p99_us,
+                wall_ms,
+                gets.into(),
+                bytes.into(),
+            ]);
+        }
+    }
+    runner.write_table(&table);
+}
+
+/// runs the scan phase projecting the first of each of `projections.widths`
+/// columns of the schema, with each width's get bytes as a share of the
+/// widest projection's, the curve pruning should make. the first width also
+/// writes the data
+pub async fn run_projections(projections: ProjectionsArgs) {
+    let mut runner = Runner::new("projections", &projections.run);
+    let columns: Vec<String> = data::create_schema(&projections.run)
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect();
+    let mut widths = match projections.widths.as_slice() {
+        [] => (1..=columns.len()).collect(),
+        widths => widths.to_vec(),
+    };
+    widths.sort_unstable();
+    widths.dedup();
+    assert!(
+        widths
+            .iter()
+            .all(|width| (1..=columns.len()).contains(width)),
+        "--widths must be between 1 and the dataset's {} columns",
+        columns.len()
+    );
+
+    let mut results = Vec::new();
+    for &width in &widths {
+        let projected = columns[..width].join(",");
+        log::info!("projections: scanning {projected}");
+        let configure = |args: &mut Args| args.scan_columns = Some(projected.clone());
+        let m = runner
+            .run(
+                &format!("c{width}"),
+                configure,
+                &[Step::Write],
+                &[Step::Scan],
+            )
+            .await;
+        results.push((width, projected, m));
+    }
+
+    let scan_bytes = |m: &Measured| m.stat(Phase::Scan, ObjectStoreOp::Get, |s| s.bytes);
+    // every column added to the projection should be more to read
+    for pair in results.windows(2) {
+        let [(narrow, _, before), (wide, _, after)] = pair else {
+            unreachable!()
+        };
+        if scan_bytes(after) <= scan_bytes(before) {
+            log::warn!(
+                "projecting {wide} columns read no more than projecting {narrow}: {} bytes against {}",
+                scan_bytes(after),
+                scan_bytes(before)
+            );
+        }
+    }
+
+    let mut table = Table::new(&[
+        "width",
+        "scan_gets",
+        "scan_get_bytes",
+        "scan_get_p99_us",
+        "scan_wall_ms",
+        "bytes_share",
+        "columns",
+    ]);
+    let widest = results.last().map_or(0, |(_, _, m)| scan_bytes(m));
+    for (width, projected, m) in results {
+        let share = match widest {
+            0 => 0.0,
+            widest => scan_bytes(&m) as f64 / widest as f64,
+        };
+        let mut row = vec![width.into()];
+        row.extend(m.get_cells(Phase::Scan));
+        row.extend([Cell::Percent(share, 1), projected.into()]);
+        table.row(row);
+    }
+    runner.write_table(&table);
+}
+
+/// runs the scan phase with each of `tuning.batch_sizes` for each of
+/// `tuning.fragment_readaheads`. the first setting also writes the data
+pub async fn run_scan_tuning(tuning: ScanTuningArgs) {
+    let mut runner = Runner::new("scan-tuning", &tuning.run);
+    let settings = |values: &[usize], default: Option<usize>| match values {
+        [] => vec![default],
+        values => values.iter().copied().map(Some).collect(),
+    };
+    let batch_sizes = settings(&tuning.batch_sizes, tuning.run.scan_batch_size);
+    let readaheads = settings(&tuning.fragment_readaheads, tuning.run.fragment_readahead);
+
+    let mut table = Table::new(&[
+        "batch_size",
+        "fragment_readahead",
+        "scan_gets",
+        "scan_get_bytes",
+        "scan_get_p50_us",
+        "scan_get_p99_us",
+        "scan_wall_ms",
+        "scan_mb_per_sec",
+    ]);
+    for &fragment_readahead in &readaheads {
+        for &batch_size in &batch_sizes {
+            let (batch, readahead) = (setting_name(batch_size), setting_name(fragment_readahead));
+            let name = format!("b{batch}-r{readahead}");
+            log::info!("scan tuning: scanning with {name}");
+            let configure = |args: &mut Args| {
+                args.scan_batch_size = batch_size;
+                args.fragment_readahead = fragment_readahead;
+            };
+            let m = runner
+                .run(&name, configure, &[Step::Write], &[Step::Scan])
+                .await;
+            let scan = |stat: fn(&OpSummary) -> u64| m.stat(Phase::Scan, ObjectStoreOp::Get, stat);
+            table.row(vec![
+                batch.into(),
+                readahead.into(),
+                scan(|s| s.count).into(),
+                scan(|s| s.bytes).into(),
+                scan(|s| s.p50_us).into(),
+                scan(|s| s.p99_us).into(),
+                m.wall_ms(Phase::Scan).into(),
+                Cell::Float(m.mb_per_sec(Phase::Scan), 2),
+            ]);
+        }
+    }
+    runner.write_table(&table);
+}
+
+/// runs the scan phase unfiltered, then filtered to each of
+/// `selectivity.selectivities` of the rows, with each filtered scan's gets
+/// and bytes relative to the unfiltered scan's, below 1 where the filter saved
+/// IO, and diffs the filtered scans' profiles against the unfiltered one's.
+/// the unfiltered scan also writes the data
+pub async fn run_selectivity(selectivity: SelectivityArgs) {
+    assert!(
+        selectivity
+            .selectivities
+            .iter()
+            .all(|&s| s > 0.0 && s <= 1.0),
+        "--selectivities must be fractions of the rows"
+    );
+    let mut runner = Runner::new("selectivity", &selectivity.run);
+    // ids are sequential, so `id % m = 0` picks one in every m rows from
+    // every fragment, rather than a few whole fragments
+    let moduli = selectivity
+        .selectivities
+        .iter()
+        .map(|&s| Some((1.0 / s).round().max(1.0) as u64));
+
+    let mut results = Vec::new();
+    let mut filters = Vec::new();
+    for modulus in std::iter::once(None).chain(moduli) {
+        let filter = modulus.map(|m| format!("id % {m} = 0"));
+        let name = match modulus {
+            Some(m) => format!("m{m}"),
+            None => "full".to_string(),
+        };
+        log::info!(
+            "selectivity: scanning {}",
+            filter.as_deref().unwrap_or("every row")
+        );
+        let configure = |args: &mut Args| args.scan_filter = filter.clone();
+        results.push(
+            runner
+                .run(&name, configure, &[Step::Write], &[Step::Scan])
+                .await,
+        );
+        filters.push((modulus, filter));
+    }
+
+    let mut table = Table::new(&[
+        "selectivity",
+        "filter",
+        "scan_gets",
+        "scan_get_bytes",
+        "scan_get_p99_us",
+        "scan_wall_ms",
+        "gets_vs_full",
+        "bytes_vs_full",
+    ]);
+    let full = &results[0];
+    let relative = |m: &Measured, stat: fn(&OpSummary) -> u64| {
+        let value = m.stat(Phase::Scan, ObjectStoreOp::Get, stat);
+        match full.stat(Phase::Scan, ObjectStoreOp::Get, stat) {
+            0 => Cell::Float(0.0, 3),
+            full => Cell::Float(value as f64 / full as f64, 3),
+        }
+    };
+    for (m, (modulus, filter)) in results.iter().zip(filters) {
+        let mut row = vec![
+            Cell::Percent(modulus.map_or(1.0, |m| 1.0 / m as f64), 4),
+            filter.unwrap_or_else(|| "none".to_string()).into(),
+        ];
+        row.extend(m.get_cells(Phase::Scan));
+        row.extend([relative(m, |s| s.count), relative(m, |s| s.bytes)]);
+        table.row(row);
+    }
+    runner.write_table(&table);
+    diff_profiles(&results);
+}
+
+/// a scanner setting, `default` when it's left to lance
+fn setting_name(setting: Option<usize>) -> String {
+    setting.map_or_else(|| "default".to_string(), |value| value.to_string())
+}
+
+/// recall@k of the index over `queries` seeded searches, against the same
+/// searches made without the index. runs after the phases, so isn't recorded
+async fn recall(args: &Args, ds: &Dataset, queries: usize) -> f64 {
+    let mut rng = StdRng::seed_from_u64(RECALL_SEED);
+    let (mut found, mut expected) = (0, 0);
+    for _ in 0..queries {
+        let query = data::query_vector(args, &mut rng);
+        let approximate = knn_ids(args, ds, query.as_ref(), true).await;
+        let exact = knn_ids(args, ds, query.as_ref(), false).await;
+        expected += exact.len();
+        found += exact.intersection(&approximate).count();
+    }
+    match expected {
+        0 => 0.0,
+        expected => found as f64 / expected as f64,
+    }
+}
+
+async fn knn_ids(args: &Args, ds: &Dataset, query: &dyn Array, use_index: bool) -> HashSet<i64> {
+    let mut scanner = knn_scanner(args, ds, "vector", query);
+    scanner.use_index(use_index).project(&["id"]).unwrap();
+    let batch = scanner.try_into_batch().await.unwrap();
+    batch
+        .column_by_name("id")
+        .unwrap()
+        .as_primitive::<Int64Type>()
+        .values()
+        .iter()
+        .copied()
+        .collect()
+}
@@ -0,0 +1,145 @@
+//! The tables the sweeps and the runs over several datasets write, as text
+//! with each column as wide as its widest cell, or as csv.
+
+use std::fmt::Write as _;
+
+use crate::summary::SummaryFormat;
+
+/// A cell of a [`Table`]. Text is left aligned in the text table, numbers
+/// right aligned.
+pub enum Cell {
+    Int(u64),
+    /// with this many decimals
+    Float(f64, usize),
+    /// a fraction, shown as a percentage with this many decimals in the text
+    /// table and as the fraction in the csv
+    Percent(f64, usize),
+    Text(String),
+}
+
+impl Cell {
+    fn text(&self) -> String {
+        match self {
+            Cell::Int(value) => value.to_string(),
+            Cell::Float(value, decimals) => format!("{value:.decimals$}"),
+            Cell::Percent(value, decimals) => format!("{:.decimals$}%", value * 100.0),
+            Cell::Text(text) => text.clone(),
+        }
+    }
+
+    fn csv(&self) -> String {
+        match self {
+            Cell::Percent(value, decimals) => format!("{value:.0$}", decimals + 2),
+            Cell::Text(text) if text.contains([',', '"']) => {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            }
+            cell => cell.text(),
+        }
+    }
+}
+
+impl From<u64> for Cell {
+    fn from(value: u64) -> Self {
+        Cell::Int(value)
+    }
+}
+
+impl From<usize> for Cell {
+    fn from(value: usize) -> Self {
+        Cell::Int(value as u64)
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::Text(text)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::Text(text.to_string())
+    }
+}
+
+/// Rows under a header, rendered in either [`SummaryFormat`].
+pub struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(columns: &[&str]) -> Self {
+        Self {
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            rows: vec![],
+        }
+    }
+
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        assert_eq!(cells.len(), self.columns.len(), "a row of the wrong width");
+        self.rows.push(cells);
+    }
+
+    pub fn render(&self, format: SummaryFormat) -> String {
+        match format {
+            SummaryFormat::Csv => self.render_csv(),
+            SummaryFormat::Text => self.render_text(),
+        }
+    }
+
+    fn render_csv(&self) -> String {
+        let mut out = self.columns.join(",");
+        out.push('\n');
+        for row in &self.rows {
+            let cells: Vec<_> = row.iter().map(Cell::csv).collect();
+            writeln!(out, "{}", cells.join(",")).unwrap();
+        }
+        out
+    }
+
+    fn render_text(&self) -> String {
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(Cell::text).collect())
+            .collect();
+        let widths: Vec<usize> = (0..self.columns.len())
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].len())
+                    .chain([self.columns[i].len()])
+                    .max()
+                    .unwrap()
+            })
+            .collect();
+        // a column is left aligned if all of it is text
+        let left: Vec<bool> = (0..self.columns.len())
+            .map(|i| {
+                !self.rows.is_empty() && self.rows.iter().all(|row| matches!(row[i], Cell::Text(_)))
+            })
+            .collect();
+
+        let mut out = String::new();
+        let mut line = |cells: &[String]| {
+            let mut text = String::new();
+            for (i, cell) in cells.iter().enumerate() {
+                if i > 0 {
+                    text.push(' ');
+                }
+                let width = widths[i];
+                if left[i] {
+                    write!(text, "{cell:<width$}").unwrap();
+                } else {
+                    write!(text, "{cell:>width$}").unwrap();
+                }
+            }
+            writeln!(out, "{}", text.trim_end()).unwrap();
+        };
+        line(&self.columns);
+        for row in &rows {
+            line(row);
+        }
+        out
+    }
+}
@@ -0,0 +1,187 @@
+//! `--tenant-labels`: which tenant each request was for, worked out from its
+//! key, for when several datasets share a bucket. Requests are labelled with
+//! their `tenant` and summarized per tenant in `tenants.{txt,csv}`, next to
+//! the profiles and summaries of everything together.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use object_store::path::Path;
+use parking_lot::Mutex;
+
+use crate::cli::Args;
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+use crate::summary::{PhaseSummary, SummaryCollector, SummaryFormat};
+
+/// what lance keeps at the top of a dataset
+const DATASET_ENTRIES: [&str; 6] = [
+    "data",
+    "_versions",
+    "_indices",
+    "_deletions",
+    "_transactions",
+    "_latest.manifest",
+];
+
+/// the tenant of keys none of the rules match
+const UNKNOWN: &str = "unknown";
+
+/// Which part of a key names its tenant.
+#[derive(Debug, Clone, Copy)]
+pub enum TenantKey {
+    /// the directory of the dataset the key is in, the segment before the
+    /// last of lance's own `data`, `_versions`, ...
+    Dataset,
+    /// this segment of the key, counting from 0
+    Segment(usize),
+}
+
+impl TenantKey {
+    /// the rule `--tenant-labels` and `--tenant-segment` ask for, if any
+    pub fn from_args(args: &Args) -> Option<Self> {
+        if !args.tenant_labels {
+            return None;
+        }
+        Some(
+            args.tenant_segment
+                .map_or(TenantKey::Dataset, TenantKey::Segment),
+        )
+    }
+
+    pub fn tenant(&self, path: &Path) -> String {
+        let parts: Vec<_> = path.parts().collect();
+        let tenant = match self {
+            TenantKey::Dataset => parts
+                .iter()
+                .rposition(|part| DATASET_ENTRIES.contains(&part.as_ref()))
+                .and_then(|i| i.checked_sub(1))
+                .map(|i| &parts[i]),
+            TenantKey::Segment(i) => parts.get(*i),
+        };
+        tenant.map_or_else(|| UNKNOWN.to_string(), |part| part.as_ref().to_string())
+    }
+}
+
+/// Hooks firing `inner` with each request's labels followed by its tenant.
+pub struct TenantLabels(pub Arc<dyn StoreHooks>, pub TenantKey);
+
+impl StoreHooks for TenantLabels {
+    fn on_request(&self, op: ObjectStoreOp, path: &Path, range: Option<Range<usize>>) {
+        self.0.on_request(op, path, range);
+    }
+
+    fn on_first_byte(&self, op: ObjectStoreOp, path: &Path, ttfb: Duration, labels: &Labels) {
+        let labels = labels.clone().str("tenant", self.1.tenant(path));
+        self.0.on_first_byte(op, path, ttfb, &labels);
+    }
+
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        let labels = labels.clone().str("tenant", self.1.tenant(path));
+        self.0
+            .on_complete(op, path, range, bytes, duration, outcome, &labels);
+    }
+}
+
+/// Hooks that summarize requests per tenant, like [`SummaryCollector`] does
+/// per phase.
+pub struct TenantCollector {
+    key: TenantKey,
+    tenants: Mutex<BTreeMap<String, SummaryCollector>>,
+}
+
+impl TenantCollector {
+    pub fn new(key: TenantKey) -> Self {
+        Self {
+            key,
+            tenants: Mutex::default(),
+        }
+    }
+
+    /// returns the summary of each tenant since the last call
+    pub fn take(&self) -> BTreeMap<String, PhaseSummary> {
+        std::mem::take(&mut *self.tenants.lock())
+            .into_iter()
+            .map(|(tenant, collector)| (tenant, collector.take()))
+            .collect()
+    }
+}
+
+impl StoreHooks for TenantCollector {
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        self.tenants
+            .lock()
+            .entry(self.key.tenant(path))
+            .or_default()
+            .on_complete(op, path, range, bytes, duration, outcome, labels);
+    }
+}
+
+pub type TenantSummaries = BTreeMap<Phase, BTreeMap<String, PhaseSummary>>;
+
+pub fn render(format: SummaryFormat, phases: &TenantSummaries) -> String {
+    let mut out = String::new();
+    match format {
+        SummaryFormat::Csv => out.push_str("phase,tenant,op,count,bytes,p50_us,p99_us,errors\n"),
+        SummaryFormat::Text => writeln!(
+            out,
+            "{:<14} {:<20} {:<8} {:>8} {:>14} {:>10} {:>10} {:>7}",
+            "phase", "tenant", "op", "count", "bytes", "p50_us", "p99_us", "errors"
+        )
+        .unwrap(),
+    }
+    for (phase, tenants) in phases {
+        for (tenant, ops) in tenants {
+            for (op, s) in ops {
+                match format {
+                    SummaryFormat::Csv => writeln!(
+                        out,
+                        "{},{tenant},{op},{},{},{},{},{}",
+                        phase.as_str(),
+                        s.count,
+                        s.bytes,
+                        s.p50_us,
+                        s.p99_us,
+                        s.errors
+                    ),
+                    SummaryFormat::Text => writeln!(
+                        out,
+                        "{:<14} {:<20} {:<8} {:>8} {:>14} {:>10} {:>10} {:>7}",
+                        phase.as_str(),
+                        tenant,
+                        op.as_str(),
+                        s.count,
+                        s.bytes,
+                        s.p50_us,
+                        s.p99_us,
+                        s.errors
+                    ),
+                }
+                .unwrap();
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,575 @@
+//! Test helpers for the wrapper's recording: a scripted store for tests to
+//! make requests against, and assertions over the profiles the wrapper takes
+//! from them. The tests live here too, not under [`crate::profiling`], since
+//! frames in the profiling module count as the recording's own and are cut
+//! from every stack.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use object_store::{
+    Error, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult, Result,
+};
+use parking_lot::Mutex;
+
+use crate::clock::ManualClock;
+use crate::op::ObjectStoreOp;
+
+/// How [`MockStore`] answers a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    /// from the objects in memory
+    Pass,
+    Fail,
+    /// passes, after moving the store's clock on by this long
+    Slow(Duration),
+}
+
+/// An in-memory store answering each op's requests with the replies scripted
+/// for it, in order, and from memory once they run out. Every request it gets
+/// is logged.
+#[derive(Debug, Default)]
+pub struct MockStore {
+    inner: InMemory,
+    script: Mutex<HashMap<ObjectStoreOp, VecDeque<Reply>>>,
+    requests: Mutex<Vec<(ObjectStoreOp, Path)>>,
+    clock: Arc<ManualClock>,
+}
+
+impl MockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// answers the next requests of `op` with `replies`
+    pub fn script(self, op: ObjectStoreOp, replies: impl IntoIterator<Item = Reply>) -> Self {
+        self.script.lock().entry(op).or_default().extend(replies);
+        self
+    }
+
+    /// the clock [`Reply::Slow`] moves on, to time the wrapper by
+    pub fn clock(&self) -> Arc<ManualClock> {
+        self.clock.clone()
+    }
+
+    /// puts `len` bytes at `path`, without going through the script
+    pub async fn seed(&self, path: &str, len: usize) -> Path {
+        let path = Path::from(path);
+        self.inner
+            .put(&path, PutPayload::from(vec![0u8; len]))
+            .await
+            .unwrap();
+        path
+    }
+
+    /// the requests made so far, in order
+    pub fn requests(&self) -> Vec<(ObjectStoreOp, Path)> {
+        self.requests.lock().clone()
+    }
+
+    fn reply(&self, op: ObjectStoreOp, location: &Path) -> Result<()> {
+        self.requests.lock().push((op, location.clone()));
+        let reply = self
+            .script
+            .lock()
+            .get_mut(&op)
+            .and_then(VecDeque::pop_front)
+            .unwrap_or(Reply::Pass);
+        match reply {
+            Reply::Pass => Ok(()),
+            Reply::Slow(latency) => {
+                self.clock.advance(latency);
+                Ok(())
+            }
+            Reply::Fail => Err(Error::Generic {
+                store: "mock",
+                source: format!("scripted {op} failure for {location}").into(),
+            }),
+        }
+    }
+}
+
+impl Display for MockStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MockStore")
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MockStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.reply(ObjectStoreOp::Put, location)?;
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.reply(ObjectStoreOp::Put, location)?;
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let op = if options.head {
+            ObjectStoreOp::Head
+        } else {
+            ObjectStoreOp::Get
+        };
+        self.reply(op, location)?;
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.reply(ObjectStoreOp::Get, location)?;
+        self.inner.get_range(location, range).await
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.reply(ObjectStoreOp::Get, location)?;
+        self.inner.get_ranges(location, ranges).await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.reply(ObjectStoreOp::Head, location)?;
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.reply(ObjectStoreOp::Delete, location)?;
+        self.inner.delete(location).await
+    }
+
+    /// never fails, a failed listing is its stream's first item
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.requests
+            .lock()
+            .push((ObjectStoreOp::List, prefix.cloned().unwrap_or_default()));
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.reply(ObjectStoreOp::List, prefix.unwrap_or(&Path::default()))?;
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.reply(ObjectStoreOp::Copy, from)?;
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.reply(ObjectStoreOp::Copy, from)?;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(feature = "profiling")]
+pub use assertions::*;
+
+#[cfg(feature = "profiling")]
+mod assertions {
+    use std::collections::BTreeMap;
+
+    use pprof::protos::Profile;
+
+    use crate::flamegraph;
+
+    /// the profile named `name` among `profiles`, as taken from a wrapper
+    pub fn profile<'a>(profiles: &'a [(String, Profile)], name: &str) -> &'a Profile {
+        profiles
+            .iter()
+            .find_map(|(op, profile)| (op == name).then_some(profile))
+            .unwrap_or_else(|| {
+                let names = profiles.iter().map(|(op, _)| op).collect::<Vec<_>>();
+                panic!("no {name} profile, only {names:?}")
+            })
+    }
+
+    /// each sample type's values added up over every sample
+    pub fn totals(profile: &Profile) -> Vec<i64> {
+        let mut totals = vec![0; profile.sample_type.len()];
+        for sample in &profile.sample {
+            for (total, value) in totals.iter_mut().zip(&sample.value) {
+                *total += value;
+            }
+        }
+        totals
+    }
+
+    /// the labels of each sample, numbers formatted like strings
+    pub fn labels(profile: &Profile) -> Vec<BTreeMap<String, String>> {
+        let strings = &profile.string_table;
+        profile
+            .sample
+            .iter()
+            .map(|sample| {
+                sample
+                    .label
+                    .iter()
+                    .map(|label| {
+                        let value = if label.str != 0 {
+                            strings[label.str as usize].clone()
+                        } else {
+                            label.num.to_string()
+                        };
+                        (strings[label.key as usize].clone(), value)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// asserts every sample of `profile` is labelled `key=value`
+    pub fn assert_labelled(profile: &Profile, key: &str, value: &str) {
+        assert!(!profile.sample.is_empty(), "no samples to be labelled");
+        for labels in labels(profile) {
+            assert_eq!(
+                labels.get(key).map(String::as_str),
+                Some(value),
+                "sample labelled {labels:?}"
+            );
+        }
+    }
+
+    /// asserts a stack of `profile` went through a function whose name
+    /// contains `function`
+    pub fn assert_stack(profile: &Profile, function: &str) {
+        let stacks = flamegraph::collapse(profile);
+        assert!(
+            stacks.iter().any(|stack| stack.contains(function)),
+            "no stack through {function} in {stacks:#?}"
+        );
+    }
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod tests {
+    use std::sync::Arc;
+
+    use lance::io::WrappingObjectStore;
+
+    use super::*;
+    use crate::hooks::Labels;
+    use crate::profiling::ProfilingObjectStoreWrapper;
+    use crate::run::Phase;
+    use crate::scope;
+    use crate::stats::StatsCollector;
+
+    const PHASE: Phase = Phase::Query;
+
+    fn wrap(wrapper: &ProfilingObjectStoreWrapper, mock: &Arc<MockStore>) -> Arc<dyn ObjectStore> {
+        wrapper.wrap(mock.clone())
+    }
+
+    #[tokio::test]
+    async fn count_only_counts_requests_and_bytes() {
+        let mock = Arc::new(MockStore::new());
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new().with_count_only();
+        wrapper.set_dataset_version(3);
+        let store = wrap(&wrapper, &mock);
+
+        scope::in_node("take", async {
+            store.get_range(&path, 0..10).await.unwrap();
+            store.get_range(&path, 10..40).await.unwrap();
+            store.head(&path).await.unwrap();
+        })
+        .await;
+
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        let get = profile(&profiles, "get");
+        assert_eq!(totals(get)[..2], [2, 40]);
+        assert_labelled(get, "node", "take");
+        assert_labelled(get, "dataset_version", "3");
+        assert_eq!(totals(profile(&profiles, "head"))[0], 1);
+        // seeding doesn't go through the script
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    /// gets from a call site of their own, for the stack profiles to show
+    async fn labelled_get(store: &dyn ObjectStore, path: &Path) {
+        store.get_range(path, 0..10).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stack_samples_carry_the_request_labels() {
+        let mock = Arc::new(MockStore::new());
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new();
+        wrapper.set_dataset_version(3);
+        let store = wrap(&wrapper, &mock);
+
+        let labels = Labels::default().str("tenant", "a");
+        scope::in_node(
+            "take",
+            scope::with_labels(labels, labelled_get(store.as_ref(), &path)),
+        )
+        .await;
+
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        let get = profile(&profiles, "get");
+        assert_eq!(totals(get), [1]);
+        assert_labelled(get, "node", "take");
+        assert_labelled(get, "tenant", "a");
+        assert_labelled(get, "dataset_version", "3");
+        assert_stack(get, "labelled_get");
+        assert!(profile(&profiles, "put").sample.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_requests_are_counted_without_bytes() {
+        let mock = Arc::new(MockStore::new().script(ObjectStoreOp::Get, [Reply::Fail]));
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new().with_count_only();
+        let store = wrap(&wrapper, &mock);
+
+        assert!(store.get_range(&path, 0..10).await.is_err());
+        store.get_range(&path, 0..10).await.unwrap();
+
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        assert_eq!(totals(profile(&profiles, "get"))[..2], [2, 10]);
+    }
+
+    #[tokio::test]
+    async fn reset_discards_what_was_recorded() {
+        let mock = Arc::new(MockStore::new());
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_count_only()
+            .with_request_sizes();
+        let store = wrap(&wrapper, &mock);
+
+        store.get_range(&path, 0..10).await.unwrap();
+        wrapper.reset();
+
+        assert!(wrapper.take_profiles(PHASE, "test").is_empty());
+    }
+
+    #[tokio::test]
+    async fn wrapper_labels_every_sample() {
+        let mock = Arc::new(MockStore::new());
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_count_only()
+            .with_labels(Labels::default().str("dataset", "mock"));
+        let store = wrap(&wrapper, &mock);
+
+        store.head(&path).await.unwrap();
+
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        assert_labelled(profile(&profiles, "head"), "dataset", "mock");
+    }
+
+    /// gets from one call site, so their sizes land in one sample
+    async fn read_sizes(store: &dyn ObjectStore, path: &Path, sizes: &[usize]) {
+        for &len in sizes {
+            let _ = store.get_range(path, 0..len).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn request_sizes_are_kept_per_stack() {
+        let mock = Arc::new(MockStore::new().script(ObjectStoreOp::Get, [Reply::Fail]));
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_count_only()
+            .with_request_sizes();
+        let store = wrap(&wrapper, &mock);
+
+        // the failed first get isn't a size
+        read_sizes(store.as_ref(), &path, &[50, 10, 30]).await;
+
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        let sizes = profile(&profiles, "get_bytes");
+        assert_eq!(sizes.sample.len(), 1);
+        assert_eq!(totals(sizes), [2, 40, 10, 30]);
+        assert_stack(sizes, "read_sizes");
+    }
+
+    #[tokio::test]
+    async fn latency_comes_from_the_clock() {
+        let mock = Arc::new(MockStore::new().script(
+            ObjectStoreOp::Get,
+            [
+                Reply::Slow(Duration::from_millis(10)),
+                Reply::Slow(Duration::from_millis(30)),
+            ],
+        ));
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_count_only()
+            .with_clock(mock.clock());
+        let store = wrap(&wrapper, &mock);
+
+        store.get_range(&path, 0..10).await.unwrap();
+        store.get_range(&path, 0..10).await.unwrap();
+
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        // count, bytes, latency
+        assert_eq!(totals(profile(&profiles, "get"))[..3], [2, 20, 40_000_000]);
+    }
+
+    #[tokio::test]
+    async fn latency_profiles_sit_next_to_the_stacks() {
+        let mock = Arc::new(MockStore::new().script(
+            ObjectStoreOp::Get,
+            [
+                Reply::Slow(Duration::from_micros(100)),
+                Reply::Slow(Duration::from_micros(3000)),
+            ],
+        ));
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_latency_profiles()
+            .with_clock(mock.clock());
+        let store = wrap(&wrapper, &mock);
+
+        store.get_range(&path, 0..10).await.unwrap();
+        store.get_range(&path, 0..10).await.unwrap();
+
+        // reporting leaves the samples to be taken
+        assert_eq!(totals(&wrapper.report(ObjectStoreOp::Get).unwrap()), [2]);
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        assert_eq!(totals(profile(&profiles, "get")), [2]);
+        let latency = profile(&profiles, "get_latency");
+        let mut buckets = labels(latency)
+            .into_iter()
+            .map(|labels| labels["latency_bucket_us"].clone())
+            .collect::<Vec<_>>();
+        buckets.sort();
+        assert_eq!(buckets, ["128", "4096"]);
+    }
+
+    #[tokio::test]
+    async fn write_pprof_writes_the_op_so_far() {
+        use pprof::protos::{Message, Profile};
+
+        let mock = Arc::new(MockStore::new());
+        let path = mock.seed("data/0.lance", 100).await;
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_count_only()
+            .with_labels(Labels::default().str("dataset", "mock"));
+        let store = wrap(&wrapper, &mock);
+        store.get_range(&path, 0..10).await.unwrap();
+
+        let output = std::env::temp_dir().join(format!("pprof-dev-{}-get.pb", std::process::id()));
+        wrapper.write_pprof(ObjectStoreOp::Get, &output).unwrap();
+        let written = Profile::parse_from_bytes(&std::fs::read(&output).unwrap()).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(totals(&written)[..2], [1, 10]);
+        assert_labelled(&written, "dataset", "mock");
+        // and it's still there to be written at the end of the phase
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        assert_eq!(totals(profile(&profiles, "get"))[0], 1);
+    }
+
+    /// forks, runs `child` in the child and asserts it didn't panic
+    #[cfg(unix)]
+    fn in_forked_child(child: impl FnOnce()) {
+        // SAFETY: the child only runs `child` and exits without unwinding
+        // into the test harness
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "couldn't fork");
+        if pid == 0 {
+            let passed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(child)).is_ok();
+            unsafe { libc::_exit(if passed { 0 } else { 1 }) };
+        }
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(
+            libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0,
+            "the child failed: {status}"
+        );
+    }
+
+    // no tokio runtime, its threads and drivers don't survive the fork
+    #[cfg(unix)]
+    #[test]
+    fn forked_child_profiles_only_its_own_requests() {
+        use futures::executor::block_on;
+
+        let mock = Arc::new(MockStore::new());
+        let path = block_on(mock.seed("data/0.lance", 100));
+        let stats = Arc::new(StatsCollector::default());
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_count_only()
+            .with_hooks(stats.clone());
+        let store = wrap(&wrapper, &mock);
+        block_on(read_sizes(store.as_ref(), &path, &[10, 20]));
+
+        in_forked_child(|| {
+            block_on(read_sizes(store.as_ref(), &path, &[30]));
+            let profiles = wrapper.take_profiles(PHASE, "test");
+            assert_eq!(totals(profile(&profiles, "get"))[..2], [1, 30]);
+            // the parent's copy isn't added to
+            assert_eq!(stats.take()[0].count, 2);
+        });
+
+        let profiles = wrapper.take_profiles(PHASE, "test");
+        assert_eq!(totals(profile(&profiles, "get"))[..2], [2, 30]);
+    }
+
+    #[tokio::test]
+    async fn stats_percentiles_come_from_the_clock() {
+        let fast = Reply::Slow(Duration::from_millis(1));
+        let slow = Reply::Slow(Duration::from_millis(2));
+        let replies = std::iter::repeat(fast).take(99).chain([slow]);
+        let mock = Arc::new(MockStore::new().script(ObjectStoreOp::Head, replies));
+        let path = mock.seed("data/0.lance", 100).await;
+        let stats = Arc::new(StatsCollector::default());
+        let wrapper = ProfilingObjectStoreWrapper::new()
+            .with_count_only()
+            .with_hooks(stats.clone())
+            .with_clock(mock.clock());
+        let store = wrap(&wrapper, &mock);
+
+        for _ in 0..100 {
+            store.head(&path).await.unwrap();
+        }
+
+        let stats = stats.take();
+        assert_eq!(stats.len(), 1);
+        let head = &stats[0];
+        assert_eq!(head.op, ObjectStoreOp::Head);
+        assert_eq!(head.count, 100);
+        assert_eq!(
+            (head.min_us, head.p50_us, head.p99_us, head.max_us),
+            (1000, 1000, 1000, 2000)
+        );
+        assert_eq!(head.mean_us, 1010);
+    }
+
+    #[test]
+    fn unscripted_ops_pass() {
+        let mock = MockStore::new().script(ObjectStoreOp::Put, [Reply::Fail]);
+        let path = Path::from("a");
+        assert!(mock.reply(ObjectStoreOp::Get, &path).is_ok());
+        assert!(mock.reply(ObjectStoreOp::Put, &path).is_err());
+        assert!(mock.reply(ObjectStoreOp::Put, &path).is_ok());
+        let ops = mock
+            .requests()
+            .into_iter()
+            .map(|(op, _)| op)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            [ObjectStoreOp::Get, ObjectStoreOp::Put, ObjectStoreOp::Put]
+        );
+    }
+}
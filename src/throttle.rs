@@ -0,0 +1,147 @@
+//! A layer slowing every request down like a slower store would, for the
+//! `throttle` layer of `--store-layers`: a fixed latency before each request
+//! reaches the store underneath, and for gets and puts a delay for their bytes
+//! at a given bandwidth. Streamed listings aren't slowed down.
+
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOpts,
+    PutOptions, PutPayload, PutResult, Result,
+};
+
+#[derive(Debug)]
+pub struct ThrottledStore {
+    inner: Arc<dyn ObjectStore>,
+    latency: Duration,
+    bytes_per_sec: Option<u64>,
+}
+
+impl ThrottledStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, latency: Duration, bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            inner,
+            latency,
+            bytes_per_sec,
+        }
+    }
+
+    async fn wait(&self) {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+    }
+
+    /// waits as long as moving `bytes` takes at the bandwidth
+    async fn transfer(&self, bytes: usize) {
+        if let Some(bytes_per_sec) = self.bytes_per_sec {
+            let secs = bytes as f64 / bytes_per_sec.max(1) as f64;
+            tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+        }
+    }
+}
+
+impl Display for ThrottledStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ThrottledStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ThrottledStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.wait().await;
+        self.transfer(payload.content_length()).await;
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    /// only the upload's creation is slowed down, not its parts
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.wait().await;
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        self.wait().await;
+        let result = self.inner.get_opts(location, options).await?;
+        self.transfer(result.range.end - result.range.start).await;
+        Ok(result)
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.wait().await;
+        let bytes = self.inner.get_range(location, range).await?;
+        self.transfer(bytes.len()).await;
+        Ok(bytes)
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.wait().await;
+        let bytes = self.inner.get_ranges(location, ranges).await?;
+        self.transfer(bytes.iter().map(Bytes::len).sum()).await;
+        Ok(bytes)
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.wait().await;
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.wait().await;
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list_with_offset(prefix, offset)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.wait().await;
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.wait().await;
+        self.inner.copy(from, to).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.wait().await;
+        self.inner.rename(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.wait().await;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.wait().await;
+        self.inner.rename_if_not_exists(from, to).await
+    }
+}
@@ -0,0 +1,240 @@
+//! `pprof-dev tiers`: the same generated rows written to each of several
+//! stores, e.g. a local disk and s3, then the same phases run against each of
+//! them in turn, in one run. Every tier gets the same data and queries in the
+//! same process, so what's left of the difference between them is the
+//! store's rather than another run's.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::Instrument;
+
+use crate::cli::{Args, DiffArgs, TiersArgs};
+use crate::data;
+use crate::hooks::Labels;
+use crate::op::ObjectStoreOp;
+use crate::otel;
+use crate::profiling::ProfilingObjectStoreWrapper;
+use crate::registry::registry;
+use crate::report;
+use crate::run::Phase;
+use crate::summary::{DatasetSummaries, SummaryFormat};
+use crate::table::{Cell, Table};
+use crate::{index_phase, profiling_wrapper, query_phase, scan_phase, write_batch, Run};
+
+/// how long each tier took in each phase, in the order the tiers were given
+type TierWalls = BTreeMap<Phase, Vec<Duration>>;
+
+struct Tier {
+    name: String,
+    /// the workload with the tier's uri and storage options
+    args: Args,
+    wrapper: Arc<ProfilingObjectStoreWrapper>,
+}
+
+pub async fn run_tiers(tiers: TiersArgs) {
+    assert!(
+        tiers.tiers.len() >= 2,
+        "--tier has to be given at least twice to compare anything"
+    );
+    for (name, _) in &tiers.tier_options {
+        assert!(
+            tiers.tiers.iter().any(|(tier, _)| tier == name),
+            "--tier-option for {name}, which isn't a --tier"
+        );
+    }
+    let mut args = tiers.run;
+    args.query_seed = Some(args.query_seed.unwrap_or_else(rand::random));
+    let _otel = otel::Exporter::start(&args);
+    let mut run = Run::new(&args);
+    log::info!(
+        "starting run {} against {} tiers",
+        run.dir.run_id(),
+        tiers.tiers.len()
+    );
+
+    let mut summaries = DatasetSummaries::default();
+    let tiers: Vec<Tier> = tiers
+        .tiers
+        .iter()
+        .map(|(name, uri)| {
+            let mut tier_args = args.clone();
+            tier_args.uri = uri.clone();
+            tier_args.storage_options.extend(
+                tiers
+                    .tier_options
+                    .iter()
+                    .filter(|(tier, _)| tier == name)
+                    .map(|(_, option)| option.clone()),
+            );
+            let wrapper = profiling_wrapper(&tier_args, &run)
+                .with_hooks(summaries.add(name))
+                .with_labels(Labels::default().str("tier", name.clone()));
+            Tier {
+                name: name.clone(),
+                args: tier_args,
+                wrapper: registry().register(name, wrapper),
+            }
+        })
+        .collect();
+    let mut walls = TierWalls::new();
+
+    // generated once up front, so every tier gets the very same rows
+    let schema = Arc::new(data::create_schema(&args));
+    let batch = data::generate_data(&args, schema, 0..args.rows as u64).unwrap();
+
+    let mut datasets = vec![];
+    let mut phase_walls = vec![];
+    run.start_phase(Phase::Write);
+    summaries.start_phase();
+    for tier in &tiers {
+        let started = Instant::now();
+        let ds = write_batch(
+            &tier.args,
+            &tier.args.uri,
+            tier.wrapper.clone(),
+            batch.clone(),
+        )
+        .instrument(Phase::Write.span())
+        .await;
+        phase_walls.push(started.elapsed());
+        datasets.push(ds);
+    }
+    run.finish_phase(Phase::Write).await;
+    summaries.finish_phase(Phase::Write);
+    walls.insert(Phase::Write, phase_walls);
+
+    let mut phase_walls = vec![];
+    run.start_phase(Phase::Index);
+    summaries.start_phase();
+    for (tier, ds) in tiers.iter().zip(&mut datasets) {
+        let started = Instant::now();
+        index_phase(&tier.args, ds)
+            .instrument(Phase::Index.span())
+            .await;
+        phase_walls.push(started.elapsed());
+    }
+    run.finish_phase(Phase::Index).await;
+    summaries.finish_phase(Phase::Index);
+    walls.insert(Phase::Index, phase_walls);
+
+    let mut phase_walls = vec![];
+    run.start_phase(Phase::Query);
+    summaries.start_phase();
+    for (tier, ds) in tiers.iter().zip(&datasets) {
+        let started = Instant::now();
+        query_phase(&tier.args, ds)
+            .instrument(Phase::Query.span())
+            .await;
+        phase_walls.push(started.elapsed());
+    }
+    run.finish_phase(Phase::Query).await;
+    summaries.finish_phase(Phase::Query);
+    walls.insert(Phase::Query, phase_walls);
+
+    let mut phase_walls = vec![];
+    run.start_phase(Phase::Scan);
+    summaries.start_phase();
+    for (tier, ds) in tiers.iter().zip(&datasets) {
+        let started = Instant::now();
+        scan_phase(&tier.args, ds)
+            .instrument(Phase::Scan.span())
+            .await;
+        phase_walls.push(started.elapsed());
+    }
+    run.finish_phase(Phase::Scan).await;
+    summaries.finish_phase(Phase::Scan);
+    walls.insert(Phase::Scan, phase_walls);
+
+    run.finish();
+    let table = table(&summaries, &walls);
+    let path = run.dir.run_file(&format!("tiers.{}", args.format.ext()));
+    std::fs::write(path, table.render(args.format)).unwrap();
+    print!("{}", table.render(SummaryFormat::Text));
+    diff_profiles(&run, &tiers);
+}
+
+/// diffs each tier's profiles against the first tier's
+fn diff_profiles(run: &Run, tiers: &[Tier]) {
+    let Some((first, rest)) = tiers.split_first() else {
+        return;
+    };
+    for phase in [Phase::Write, Phase::Index, Phase::Query, Phase::Scan] {
+        for op in ObjectStoreOp::PROFILED {
+            let profile = |tier: &Tier| {
+                run.dir
+                    .output_path(phase, &format!("{}_{op}", tier.name), "pb")
+                    .unwrap()
+            };
+            let before = profile(first);
+            if !before.exists() {
+                continue;
+            }
+            for tier in rest {
+                let after = profile(tier);
+                if !after.exists() {
+                    continue;
+                }
+                let diff = DiffArgs {
+                    before: before.clone(),
+                    after,
+                    output: None,
+                };
+                if let Err(e) = report::write_diff(&diff) {
+                    log::warn!("not diffing the profiles: {e}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// a line per phase, op and tier, with the tier's p50 latency and wall time
+/// relative to the first tier's
+fn table(summaries: &DatasetSummaries, walls: &TierWalls) -> Table {
+    let relative =
+        |value: f64, first: f64| Cell::Float(if first > 0.0 { value / first } else { 0.0 }, 3);
+
+    let mut table = Table::new(&[
+        "phase",
+        "op",
+        "tier",
+        "count",
+        "bytes",
+        "p50_us",
+        "p99_us",
+        "errors",
+        "wall_ms",
+        "p50_vs_first",
+        "wall_vs_first",
+    ]);
+    for (phase, tiers) in summaries.phases() {
+        let walls = &walls[phase];
+        let Some((_, first)) = tiers.first() else {
+            continue;
+        };
+        for op in ObjectStoreOp::ALL {
+            for ((tier, summary), wall) in tiers.iter().zip(walls) {
+                let Some(s) = summary.get(&op) else {
+                    continue;
+                };
+                let first_p50 = first.get(&op).map_or(0, |s| s.p50_us);
+                table.row(vec![
+                    phase.as_str().into(),
+                    op.as_str().into(),
+                    tier.as_str().into(),
+                    s.count.into(),
+                    s.bytes.into(),
+                    s.p50_us.into(),
+                    s.p99_us.into(),
+                    s.errors.into(),
+                    (wall.as_millis() as u64).into(),
+                    relative(s.p50_us as f64, first_p50 as f64),
+                    relative(wall.as_secs_f64(), walls[0].as_secs_f64()),
+                ]);
+            }
+        }
+    }
+    table
+}
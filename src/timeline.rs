@@ -0,0 +1,212 @@
+//! Request rate and throughput over the course of the run, drawn as an svg
+//! chart with the phases shaded behind it, to spot bursts (e.g. during index
+//! training) without loading the trace into anything.
+//!
+//! With `--queue-depth` it also charts how many requests were in flight. Lance
+//! doesn't expose the depth of its io scheduler's queues, so these are the
+//! requests the wrapper has sent and not yet finished, which back up the same
+//! way when the store can't keep up.
+
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use object_store::path::Path;
+use parking_lot::Mutex;
+
+use crate::hooks::{Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+
+/// requests are counted in buckets this wide, by when they completed
+const BUCKET: Duration = Duration::from_millis(100);
+
+const WIDTH: f64 = 1000.0;
+const CHART_HEIGHT: f64 = 200.0;
+const MARGIN: f64 = 50.0;
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    requests: u64,
+    bytes: u64,
+    /// the most requests in flight at once, `None` if none started or
+    /// finished, in which case it's what it was at the end of the last bucket
+    depth: Option<u64>,
+}
+
+/// Hooks counting requests and bytes per [`BUCKET`] since the timeline was
+/// created.
+pub struct Timeline {
+    started: Instant,
+    queue_depth: bool,
+    in_flight: AtomicU64,
+    buckets: Mutex<Vec<Bucket>>,
+    /// each phase with when it started and finished
+    phases: Mutex<Vec<(Phase, Duration, Option<Duration>)>>,
+}
+
+impl Timeline {
+    pub fn new(queue_depth: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            queue_depth,
+            in_flight: AtomicU64::new(0),
+            buckets: Mutex::new(vec![]),
+            phases: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn begin_phase(&self, phase: Phase) {
+        self.phases
+            .lock()
+            .push((phase, self.started.elapsed(), None));
+    }
+
+    pub fn end_phase(&self, phase: Phase) {
+        let now = self.started.elapsed();
+        if let Some((_, _, end)) = self
+            .phases
+            .lock()
+            .iter_mut()
+            .rev()
+            .find(|(p, _, end)| *p == phase && end.is_none())
+        {
+            *end = Some(now);
+        }
+    }
+
+    /// calls `f` with the bucket for now
+    fn update(&self, f: impl FnOnce(&mut Bucket)) {
+        let i = (self.started.elapsed().as_nanos() / BUCKET.as_nanos()) as usize;
+        let mut buckets = self.buckets.lock();
+        if buckets.len() <= i {
+            buckets.resize(i + 1, Bucket::default());
+        }
+        f(&mut buckets[i]);
+    }
+
+    /// the requests in flight has changed to `depth`
+    fn record_depth(&self, depth: u64) {
+        self.update(|bucket| bucket.depth = Some(bucket.depth.unwrap_or(0).max(depth)));
+    }
+
+    /// requests per second and megabytes per second, one above the other, and
+    /// the requests in flight below them with `--queue-depth`
+    pub fn render_svg(&self) -> String {
+        let buckets = self.buckets.lock().clone();
+        let phases = self.phases.lock().clone();
+        let per_sec = 1.0 / BUCKET.as_secs_f64();
+        let requests = buckets
+            .iter()
+            .map(|b| b.requests as f64 * per_sec)
+            .collect::<Vec<_>>();
+        let mb = buckets
+            .iter()
+            .map(|b| b.bytes as f64 * per_sec / 1e6)
+            .collect::<Vec<_>>();
+        let mut depth = 0;
+        let in_flight = buckets
+            .iter()
+            .map(|b| {
+                depth = b.depth.unwrap_or(depth);
+                depth as f64
+            })
+            .collect::<Vec<_>>();
+        let mut charts = vec![("requests/s", &requests), ("MB/s", &mb)];
+        if self.queue_depth {
+            charts.push(("requests in flight", &in_flight));
+        }
+
+        let end = self
+            .started
+            .elapsed()
+            .as_secs_f64()
+            .max(BUCKET.as_secs_f64());
+        let x = |secs: f64| MARGIN + secs / end * (WIDTH - 2.0 * MARGIN);
+        let height = charts.len() as f64 * (CHART_HEIGHT + MARGIN) + MARGIN;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{height}\" \
+             font-family=\"sans-serif\" font-size=\"11\">"
+        )
+        .unwrap();
+        for (i, (name, values)) in charts.into_iter().enumerate() {
+            let top = MARGIN + i as f64 * (CHART_HEIGHT + MARGIN);
+            let bottom = top + CHART_HEIGHT;
+            let max = values.iter().copied().fold(0.0, f64::max).max(1e-9);
+
+            for (j, (phase, start, finish)) in phases.iter().enumerate() {
+                let (x0, x1) = (
+                    x(start.as_secs_f64()),
+                    x(finish.map_or(end, |f| f.as_secs_f64())),
+                );
+                let fill = if j % 2 == 0 { "#eef" } else { "#efe" };
+                writeln!(
+                    svg,
+                    "<rect x=\"{x0:.1}\" y=\"{top:.1}\" width=\"{:.1}\" height=\"{CHART_HEIGHT}\" \
+                     fill=\"{fill}\"/><text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                    (x1 - x0).max(0.0),
+                    x0 + 2.0,
+                    top + 12.0,
+                    phase.as_str()
+                )
+                .unwrap();
+            }
+
+            let points = values
+                .iter()
+                .enumerate()
+                .map(|(k, v)| {
+                    let secs = (k as f64 + 0.5) * BUCKET.as_secs_f64();
+                    format!("{:.1},{:.1}", x(secs), bottom - v / max * CHART_HEIGHT)
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(
+                svg,
+                "<polyline points=\"{points}\" fill=\"none\" stroke=\"#c33\"/>\n\
+                 <line x1=\"{MARGIN}\" y1=\"{bottom:.1}\" x2=\"{:.1}\" y2=\"{bottom:.1}\" stroke=\"#000\"/>\n\
+                 <line x1=\"{MARGIN}\" y1=\"{top:.1}\" x2=\"{MARGIN}\" y2=\"{bottom:.1}\" stroke=\"#000\"/>\n\
+                 <text x=\"{MARGIN}\" y=\"{:.1}\">{name}, peak {max:.1}</text>\n\
+                 <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{end:.1}s</text>",
+                WIDTH - MARGIN,
+                top - 6.0,
+                WIDTH - MARGIN,
+                bottom + 14.0,
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+impl StoreHooks for Timeline {
+    fn on_request(&self, _op: ObjectStoreOp, _path: &Path, _range: Option<Range<usize>>) {
+        if self.queue_depth {
+            self.record_depth(self.in_flight.fetch_add(1, Ordering::Relaxed) + 1);
+        }
+    }
+
+    fn on_complete(
+        &self,
+        _op: ObjectStoreOp,
+        _path: &Path,
+        _range: Option<Range<usize>>,
+        bytes: usize,
+        _duration: Duration,
+        _outcome: Outcome<'_>,
+        _labels: &Labels,
+    ) {
+        self.update(|bucket| {
+            bucket.requests += 1;
+            bucket.bytes += bytes as u64;
+        });
+        if self.queue_depth {
+            self.record_depth(self.in_flight.fetch_sub(1, Ordering::Relaxed) - 1);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Which ops are profiled right now. `--disable-ops` starts a run with some of
+//! them off and SIGUSR1 flips those given with `--toggle-ops`, so expensive
+//! put profiling can be left off until the commit heavy part of a long run.
+//! Only profiling is toggled: summaries, stats and the other hooks see every
+//! request either way.
+
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::op::ObjectStoreOp;
+
+pub struct OpToggles {
+    disabled: [AtomicBool; ObjectStoreOp::ALL.len()],
+}
+
+impl Default for OpToggles {
+    fn default() -> Self {
+        Self {
+            disabled: std::array::from_fn(|_| AtomicBool::new(false)),
+        }
+    }
+}
+
+impl OpToggles {
+    pub fn is_enabled(&self, op: ObjectStoreOp) -> bool {
+        !self.disabled[op as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, op: ObjectStoreOp, enabled: bool) {
+        self.disabled[op as usize].store(!enabled, Ordering::Relaxed);
+    }
+
+    /// flips `op`, returning whether it's enabled now
+    pub fn toggle(&self, op: ObjectStoreOp) -> bool {
+        !self.disabled[op as usize].fetch_xor(true, Ordering::Relaxed)
+    }
+}
+
+/// e.g. `get=on put=off`, for the profiled ops
+impl Display for OpToggles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, op) in ObjectStoreOp::PROFILED.into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            let state = if self.is_enabled(op) { "on" } else { "off" };
+            write!(f, "{op}={state}")?;
+        }
+        Ok(())
+    }
+}
+
+/// flips `ops` on every SIGUSR1 until the process exits
+#[cfg(unix)]
+pub fn spawn_signal_toggle(ops: Vec<ObjectStoreOp>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = signal(SignalKind::user_defined1()).unwrap();
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            let toggles = crate::registry::registry().toggles();
+            for &op in &ops {
+                toggles.toggle(op);
+            }
+            log::info!("SIGUSR1 toggled profiling: {toggles}");
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_signal_toggle(_ops: Vec<ObjectStoreOp>) {
+    log::warn!("--toggle-ops needs SIGUSR1, which this platform doesn't have");
+}
@@ -0,0 +1,255 @@
+//! Chrome trace event output (the JSON `trace_event` format understood by
+//! `chrome://tracing` and Perfetto) of every object store request, with the
+//! workload phases as spans around them.
+//!
+//! With `--trace-stacks` the call stack each request was made from is drawn
+//! under it as a flame chart, so it's visible when during the run each call
+//! path did its IO rather than only how much of it there was.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::ops::Range;
+use std::path::Path as FsPath;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use object_store::path::Path;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::hooks::{LabelValue, Labels, Outcome, StoreHooks};
+use crate::op::ObjectStoreOp;
+use crate::run::Phase;
+use crate::stacks;
+
+// phases are drawn on their own thread, requests are async events on top
+const PHASE_TID: u64 = 0;
+const REQUEST_TID: u64 = 1;
+/// stacks are drawn on threads from here up, as many as there were requests
+/// in flight at once
+const STACK_TID: u64 = 2;
+
+#[derive(Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    /// microseconds since the recorder was created
+    ts: f64,
+    pid: u32,
+    tid: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    /// microseconds, for complete (`X`) events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<f64>,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    args: serde_json::Value,
+}
+
+/// A request's stack, kept unresolved until the trace is written.
+struct StackedRequest {
+    op: ObjectStoreOp,
+    ts: f64,
+    dur: f64,
+    ips: Vec<usize>,
+}
+
+pub struct TraceRecorder {
+    started: Instant,
+    next_id: AtomicU64,
+    events: Mutex<Vec<TraceEvent>>,
+    /// set with `--trace-stacks`
+    stacks: Option<Mutex<Vec<StackedRequest>>>,
+}
+
+impl TraceRecorder {
+    pub fn new(stacks: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            next_id: AtomicU64::new(0),
+            events: Mutex::new(vec![]),
+            stacks: stacks.then(|| Mutex::new(vec![])),
+        }
+    }
+
+    pub fn begin_phase(&self, phase: Phase) {
+        self.phase_event(phase, "B");
+    }
+
+    pub fn end_phase(&self, phase: Phase) {
+        self.phase_event(phase, "E");
+    }
+
+    fn phase_event(&self, phase: Phase, ph: &'static str) {
+        let ts = self.micros(self.started.elapsed());
+        self.events.lock().push(TraceEvent {
+            name: phase.as_str().to_string(),
+            cat: "phase",
+            ph,
+            ts,
+            pid: std::process::id(),
+            tid: PHASE_TID,
+            id: None,
+            dur: None,
+            args: serde_json::Value::Null,
+        });
+    }
+
+    fn micros(&self, since_start: Duration) -> f64 {
+        since_start.as_secs_f64() * 1_000_000.0
+    }
+
+    pub fn write(&self, path: &FsPath) -> io::Result<()> {
+        let mut events = self.events.lock();
+        if let Some(stacks) = &self.stacks {
+            events.extend(stack_events(&std::mem::take(&mut *stacks.lock())));
+        }
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(
+            writer,
+            &json!({
+                "traceEvents": &*events,
+                "displayTimeUnit": "ms",
+            }),
+        )?;
+        Ok(())
+    }
+}
+
+/// a slice per frame of each request's stack, outermost at the top, on the
+/// first stack thread that's free at the time. concurrent requests can't
+/// share a thread or their frames would look nested
+fn stack_events(requests: &[StackedRequest]) -> Vec<TraceEvent> {
+    let mut requests = requests.iter().collect::<Vec<_>>();
+    requests.sort_by(|a, b| a.ts.total_cmp(&b.ts));
+
+    let mut names = HashMap::new();
+    let mut lanes: Vec<f64> = vec![];
+    let mut events = vec![];
+    for request in requests {
+        let lane = match lanes.iter().position(|end| *end <= request.ts) {
+            Some(lane) => lane,
+            None => {
+                lanes.push(0.0);
+                events.push(TraceEvent {
+                    name: "thread_name".to_string(),
+                    cat: "__metadata",
+                    ph: "M",
+                    ts: 0.0,
+                    pid: std::process::id(),
+                    tid: STACK_TID + lanes.len() as u64 - 1,
+                    id: None,
+                    dur: None,
+                    args: json!({ "name": format!("request stacks {}", lanes.len() - 1) }),
+                });
+                lanes.len() - 1
+            }
+        };
+        lanes[lane] = request.ts + request.dur;
+
+        let frames = request
+            .ips
+            .iter()
+            .map(|ip| {
+                names
+                    .entry(*ip)
+                    .or_insert_with(|| stacks::symbols(*ip))
+                    .clone()
+            })
+            .collect::<Vec<_>>();
+        // the frames of the hooks recording the request come first
+        let own = stacks::own_frames(
+            &frames,
+            &[
+                "pprof_dev::trace::",
+                "pprof_dev::hooks::",
+                "pprof_dev::scope::",
+            ],
+        );
+        for symbol in frames[own..].iter().flatten().rev() {
+            events.push(TraceEvent {
+                name: symbol.name.clone(),
+                cat: "stack",
+                ph: "X",
+                ts: request.ts,
+                pid: std::process::id(),
+                tid: STACK_TID + lane as u64,
+                id: None,
+                dur: Some(request.dur),
+                args: json!({ "op": request.op.as_str() }),
+            });
+        }
+    }
+    events
+}
+
+impl StoreHooks for TraceRecorder {
+    // the only thing that ties a request to its completion is the call itself,
+    // so both ends of the span are recorded once it completes
+    fn on_complete(
+        &self,
+        op: ObjectStoreOp,
+        path: &Path,
+        range: Option<Range<usize>>,
+        bytes: usize,
+        duration: Duration,
+        outcome: Outcome<'_>,
+        labels: &Labels,
+    ) {
+        let end = self.started.elapsed();
+        let start = end.saturating_sub(duration);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Some(stacked) = &self.stacks {
+            let ips = stacks::capture();
+            stacked.lock().push(StackedRequest {
+                op,
+                ts: self.micros(start),
+                dur: self.micros(duration),
+                ips,
+            });
+        }
+        let outcome = match outcome {
+            Outcome::Success => "ok".to_string(),
+            Outcome::Error(e) => e.to_string(),
+            Outcome::Aborted => "aborted".to_string(),
+        };
+
+        let mut args = json!({ "path": path.as_ref(), "bytes": bytes, "outcome": outcome });
+        if let Some(range) = range {
+            args["range"] = json!([range.start, range.end]);
+        }
+        for (key, value) in labels.iter() {
+            args[*key] = match value {
+                LabelValue::Str(value) => json!(value),
+                LabelValue::Num(value) => json!(value),
+            };
+        }
+
+        let mut events = self.events.lock();
+        events.push(TraceEvent {
+            name: op.as_str().to_string(),
+            cat: "object_store",
+            ph: "b",
+            ts: self.micros(start),
+            pid: std::process::id(),
+            tid: REQUEST_TID,
+            id: Some(id),
+            dur: None,
+            args,
+        });
+        events.push(TraceEvent {
+            name: op.as_str().to_string(),
+            cat: "object_store",
+            ph: "e",
+            ts: self.micros(end),
+            pid: std::process::id(),
+            tid: REQUEST_TID,
+            id: Some(id),
+            dur: None,
+            args: serde_json::Value::Null,
+        });
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+
+use object_store::path::Path;
+use object_store::PutPayload;
+use url::Url;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// copies every file under `local` to `destination` (any url object_store
+/// understands, e.g. `s3://bucket/prefix`), keeping their relative paths.
+/// returns the number of files uploaded
+pub async fn upload_dir(
+    local: &FsPath,
+    destination: &str,
+    storage_options: HashMap<String, String>,
+) -> Result<usize, Error> {
+    let url = Url::parse(destination)?;
+    let (store, prefix) = object_store::parse_url_opts(&url, storage_options)?;
+
+    let files = list_files(local)?;
+    for file in &files {
+        let relative = file.strip_prefix(local)?;
+        let location = prefix
+            .parts()
+            .map(|part| part.as_ref().to_string())
+            .chain(
+                relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+            )
+            .collect::<Path>();
+
+        log::debug!("uploading {} to {location}", file.display());
+        store
+            .put(&location, PutPayload::from(fs::read(file)?))
+            .await?;
+    }
+    Ok(files.len())
+}
+
+fn list_files(dir: &FsPath) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(list_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,140 @@
+//! `pprof-dev view`: opens profiles in `go tool pprof -http` when go is
+//! installed, and otherwise serves them through a minimal viewer of its own,
+//! a page listing the profiles with a flamegraph of each, so a run can be
+//! looked at right after it finishes.
+
+use std::fmt::Write as _;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::Command;
+
+use crate::cli::ViewArgs;
+use crate::{http, report};
+
+pub async fn view(args: &ViewArgs) -> io::Result<()> {
+    let mut profiles = vec![];
+    for path in &args.profiles {
+        if path.is_dir() {
+            profiles.extend(report::find_profiles(path)?);
+        } else {
+            profiles.push(path.clone());
+        }
+    }
+    if profiles.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no profiles to view",
+        ));
+    }
+
+    if !args.embedded && has_go().await {
+        // pprof merges the profiles into one view
+        let status = Command::new("go")
+            .args(["tool", "pprof", &format!("-http={}", args.addr)])
+            .args(&profiles)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "go tool pprof exited with {status}"
+            )));
+        }
+        return Ok(());
+    }
+    serve(&args.addr, profiles).await
+}
+
+async fn has_go() -> bool {
+    let found = Command::new("go")
+        .arg("version")
+        .output()
+        .await
+        .is_ok_and(|output| output.status.success());
+    if !found {
+        log::info!("go isn't installed, serving the profiles without pprof");
+    }
+    found
+}
+
+/// serves `profiles` at `addr` until interrupted
+async fn serve(addr: &str, profiles: Vec<PathBuf>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!(
+        "serving {} profiles at http://{}",
+        profiles.len(),
+        listener.local_addr()?
+    );
+    let profiles = Arc::new(profiles);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let profiles = profiles.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &profiles).await {
+                log::warn!("couldn't answer a viewer request: {e}");
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, profiles: &[PathBuf]) -> io::Result<()> {
+    let path = http::request_path(&mut stream).await?;
+    let (status, content_type, body) = match path.as_str() {
+        "/" => ("200 OK", "text/html; charset=utf-8", index(profiles)),
+        path => match path
+            .strip_prefix("/flamegraph/")
+            .and_then(|file| file.strip_suffix(".svg"))
+            .and_then(|i| i.parse::<usize>().ok())
+            .and_then(|i| profiles.get(i))
+        {
+            Some(profile) => match flamegraph(profile) {
+                Ok(svg) => ("200 OK", "image/svg+xml", svg),
+                Err(e) => (
+                    "500 Internal Server Error",
+                    "text/plain; charset=utf-8",
+                    e.to_string().into_bytes(),
+                ),
+            },
+            None => (
+                "404 Not Found",
+                "text/plain; charset=utf-8",
+                b"not found".to_vec(),
+            ),
+        },
+    };
+    http::respond(stream, status, content_type, &body).await
+}
+
+/// a page linking to each profile's flamegraph
+fn index(profiles: &[PathBuf]) -> Vec<u8> {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>pprof-dev view</title>\
+         </head><body style=\"font-family: sans-serif; margin: 2em;\"><h1>Profiles</h1>\n<ul>\n",
+    );
+    for (i, profile) in profiles.iter().enumerate() {
+        writeln!(
+            html,
+            "<li><a href=\"/flamegraph/{i}.svg\">{}</a></li>",
+            report::escape(&profile.display().to_string())
+        )
+        .unwrap();
+    }
+    html.push_str("</ul></body></html>\n");
+    html.into_bytes()
+}
+
+#[cfg(feature = "profiling")]
+fn flamegraph(path: &std::path::Path) -> io::Result<Vec<u8>> {
+    let profile = crate::flamegraph::read_profile(path)?;
+    crate::flamegraph::render_svg(&profile, &path.display().to_string())
+}
+
+#[cfg(not(feature = "profiling"))]
+fn flamegraph(_path: &std::path::Path) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "built without the profiling feature, can't read profiles",
+    ))
+}
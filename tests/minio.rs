@@ -0,0 +1,82 @@
+//! Runs a small workload against a minio container and checks the requests
+//! recorded for it, so the profiling store is exercised against a real s3
+//! api. needs docker, so it only runs with `cargo test -- --ignored`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
+use testcontainers::core::ExecCommand;
+use testcontainers::runners::AsyncRunner;
+use testcontainers_modules::minio::MinIO;
+
+const BUCKET: &str = "pprof-dev";
+const RUN_ID: &str = "minio";
+
+#[tokio::test]
+#[ignore = "needs docker"]
+async fn workload_against_minio() {
+    let minio = MinIO::default().start().await.unwrap();
+    // a directory in the data dir of a fresh server is a bucket
+    minio
+        .exec(ExecCommand::new([
+            "mkdir",
+            "-p",
+            &format!("/data/{BUCKET}"),
+        ]))
+        .await
+        .unwrap();
+    let port = minio.get_host_port_ipv4(9000).await.unwrap();
+
+    let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("minio");
+    let _ = std::fs::remove_dir_all(&output_dir);
+    let status = Command::new(env!("CARGO_BIN_EXE_pprof-dev"))
+        .args(["--backend", "minio"])
+        .args(["--minio-endpoint", &format!("http://127.0.0.1:{port}")])
+        .args(["--uri", &format!("s3://{BUCKET}/test.lance")])
+        .args(["--rows", "1000", "--vector-dims", "16"])
+        .args(["--run-id", RUN_ID])
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "workload exited with {status}");
+
+    let run_dir = output_dir.join(RUN_ID);
+    let state = read_json(&run_dir.join("state.json"));
+    let summaries = state["summaries"].as_object().unwrap();
+    for (phase, summary) in summaries {
+        let stats = stats_counts(&read_json(&run_dir.join(format!("{phase}_stats.json"))));
+        for (op, s) in summary.as_object().unwrap() {
+            assert_eq!(s["errors"], 0, "{phase} {op} requests failed");
+            // the summary and the stats are recorded by separate hooks
+            assert_eq!(
+                s["count"].as_u64(),
+                stats.get(op.as_str()).copied(),
+                "{phase} {op} counts disagree"
+            );
+        }
+    }
+
+    let count = |phase: &str, op: &str| summaries[phase][op]["count"].as_u64().unwrap_or(0);
+    // at least a data file and a manifest
+    assert!(count("write", "put") >= 2);
+    assert!(count("index", "put") >= 1);
+    assert!(count("query", "get") >= 1);
+}
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+}
+
+/// requests per op in a phase's stats, summed over operators
+fn stats_counts(stats: &Value) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for s in stats.as_array().unwrap() {
+        *counts
+            .entry(s["op"].as_str().unwrap().to_string())
+            .or_default() += s["count"].as_u64().unwrap();
+    }
+    counts
+}